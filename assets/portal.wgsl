@@ -1,14 +1,12 @@
-#import bevy_pbr::{
-    forward_io::VertexOutput,
-    mesh_view_bindings::view, 
-    utils::coords_to_viewport_uv,
-}
+#import bevy_pbr::forward_io::{Vertex, VertexOutput}
+#import bevy_easy_portals::portal::{portal_fragment, portal_vertex}
 
-@group(2) @binding(0) var base_color_texture: texture_2d<f32>;
-@group(2) @binding(1) var base_color_sampler: sampler;
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    return portal_vertex(vertex);
+}
 
 @fragment
 fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
-    let viewport_uv = coords_to_viewport_uv(mesh.position.xy, view.viewport);
-    return textureSample(base_color_texture, base_color_sampler, viewport_uv);
+    return portal_fragment(mesh);
 }
@@ -0,0 +1,13 @@
+#import bevy_sprite::{
+    mesh2d_vertex_output::VertexOutput,
+    mesh2d_view_bindings::view,
+}
+
+@group(2) @binding(0) var base_color_texture: texture_2d<f32>;
+@group(2) @binding(1) var base_color_sampler: sampler;
+
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+    let viewport_uv = (mesh.position.xy - view.viewport.xy) / view.viewport.zw;
+    return textureSample(base_color_texture, base_color_sampler, viewport_uv);
+}
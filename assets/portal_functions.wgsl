@@ -0,0 +1,239 @@
+#define_import_path bevy_easy_portals::portal
+
+#import bevy_pbr::{
+    forward_io::{Vertex, VertexOutput},
+    mesh_functions,
+    mesh_view_bindings::{globals, view},
+    utils::coords_to_viewport_uv,
+    view_transformations::position_world_to_clip,
+}
+
+struct PortalMaterialUniform {
+    uv_rect: vec4<f32>,
+    aperture: f32,
+    planar_from_world: mat4x4<f32>,
+    rim_color: vec4<f32>,
+    rim_width: f32,
+    tint: vec4<f32>,
+    brightness: f32,
+    contrast: f32,
+    blur_radius: f32,
+    distortion_amplitude: f32,
+    distortion_frequency: f32,
+    distortion_scroll: vec2<f32>,
+    swirl_strength: f32,
+    swirl_speed: f32,
+    fade: f32,
+    fade_color: vec4<f32>,
+    dissolve: f32,
+    dissolve_edge_width: f32,
+    mask_cutoff: f32,
+    crossing_offset: f32,
+}
+
+@group(2) @binding(0) var base_color_texture: texture_2d<f32>;
+@group(2) @binding(1) var base_color_sampler: sampler;
+@group(2) @binding(2) var<uniform> material: PortalMaterialUniform;
+@group(2) @binding(3) var distortion_texture: texture_2d<f32>;
+@group(2) @binding(4) var distortion_sampler: sampler;
+@group(2) @binding(5) var dissolve_texture: texture_2d<f32>;
+@group(2) @binding(6) var dissolve_sampler: sampler;
+@group(2) @binding(7) var mask_texture: texture_2d<f32>;
+@group(2) @binding(8) var mask_sampler: sampler;
+
+// Offsets `uv` by ripples, or by `distortion_texture`, moving over time
+fn distort(uv: vec2<f32>) -> vec2<f32> {
+    let scrolled_uv = uv + material.distortion_scroll * globals.time;
+#ifdef PORTAL_DISTORTION_TEXTURE
+    let noise = textureSampleLevel(distortion_texture, distortion_sampler, scrolled_uv, 0.0);
+    let offset = noise.rg * 2.0 - 1.0;
+#else
+    let phase = scrolled_uv * material.distortion_frequency * 6.2831855;
+    let offset = vec2(sin(phase.y), cos(phase.x));
+#endif
+    return uv + offset * material.distortion_amplitude;
+}
+
+// Returns how far `uv` is moved by twisting it around the center of the mesh
+fn swirl_offset(uv: vec2<f32>) -> vec2<f32> {
+    let center_offset = uv - 0.5;
+    let falloff = max(1.0 - length(center_offset) * 2.0, 0.0);
+    let sway = 1.0 + 0.5 * sin(globals.time * material.swirl_speed);
+    let angle = material.swirl_strength * sway * falloff * falloff;
+    let rotation = mat2x2(cos(angle), sin(angle), -sin(angle), cos(angle));
+    return rotation * center_offset - center_offset;
+}
+
+// Returns the dissolve noise at `uv`, from `dissolve_texture` or a procedural value noise
+fn dissolve_noise(uv: vec2<f32>) -> f32 {
+#ifdef PORTAL_DISSOLVE_TEXTURE
+    return textureSampleLevel(dissolve_texture, dissolve_sampler, uv, 0.0).r;
+#else
+    let cell = floor(uv * 16.0);
+    let t = smoothstep(vec2(0.0), vec2(1.0), fract(uv * 16.0));
+    let a = hash(cell);
+    let b = hash(cell + vec2(1.0, 0.0));
+    let c = hash(cell + vec2(0.0, 1.0));
+    let d = hash(cell + vec2(1.0, 1.0));
+    return mix(mix(a, b, t.x), mix(c, d, t.x), t.y);
+#endif
+}
+
+fn hash(p: vec2<f32>) -> f32 {
+    return fract(sin(dot(p, vec2(127.1, 311.7))) * 43758.5453);
+}
+
+// Transforms a vertex of the portal's mesh, pushing it away from the camera while the camera is
+// close enough to the mesh for the near plane to cut through it
+fn portal_vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    let world_from_local = mesh_functions::get_world_from_local(vertex.instance_index);
+    var world_position = mesh_functions::mesh_position_local_to_world(
+        world_from_local,
+        vec4(vertex.position, 1.0),
+    );
+#ifdef VERTEX_NORMALS
+    out.world_normal = mesh_functions::mesh_normal_local_to_world(
+        vertex.normal,
+        vertex.instance_index,
+    );
+    // The image is sampled by screen position, so moving the mesh along its normal doesn't change
+    // what is shown
+    let camera_distance = dot(view.world_position - world_position.xyz, out.world_normal);
+    if abs(camera_distance) < material.crossing_offset {
+        let direction = select(1.0, -1.0, camera_distance > 0.0);
+        world_position += vec4(out.world_normal * direction * material.crossing_offset, 0.0);
+    }
+#endif
+    out.world_position = world_position;
+    out.position = position_world_to_clip(world_position.xyz);
+#ifdef VERTEX_UVS_A
+    out.uv = vertex.uv;
+#endif
+#ifdef VERTEX_UVS_B
+    out.uv_b = vertex.uv_b;
+#endif
+#ifdef VERTEX_TANGENTS
+    out.world_tangent = mesh_functions::mesh_tangent_local_to_world(
+        world_from_local,
+        vertex.tangent,
+        vertex.instance_index,
+    );
+#endif
+#ifdef VERTEX_COLORS
+    out.color = vertex.color;
+#endif
+#ifdef VERTEX_OUTPUT_INSTANCE_INDEX
+    out.instance_index = vertex.instance_index;
+#endif
+#ifdef VISIBILITY_RANGE_DITHER
+    out.visibility_range_dither = mesh_functions::get_visibility_range_dither_level(
+        vertex.instance_index,
+        world_from_local[3],
+    );
+#endif
+    return out;
+}
+
+// Returns the distance to the closest edge of the opening in UV coordinates, discarding fragments
+// outside of the opening
+fn portal_edge_distance(mesh: VertexOutput) -> f32 {
+    var edge_distance = 1.0;
+#ifdef VERTEX_UVS_A
+    // Shrink a circular opening towards the center of the mesh as the aperture closes
+    let aperture_distance = material.aperture * sqrt(0.5) - distance(mesh.uv, vec2(0.5));
+    if aperture_distance < 0.0 {
+        discard;
+    }
+    let mesh_distance = min(min(mesh.uv.x, 1.0 - mesh.uv.x), min(mesh.uv.y, 1.0 - mesh.uv.y));
+    edge_distance = min(aperture_distance, mesh_distance);
+
+#ifdef PORTAL_MASK_TEXTURE
+    // Scale the mask's soft edge so that the border is as wide as the rim
+    let mask = textureSampleLevel(mask_texture, mask_sampler, mesh.uv, 0.0).a;
+    let mask_distance = mask - material.mask_cutoff;
+    if mask_distance < 0.0 {
+        discard;
+    }
+    let mask_edge_width = max(1.0 - material.mask_cutoff, 0.0001);
+    edge_distance = min(edge_distance, mask_distance / mask_edge_width * material.rim_width);
+#endif
+
+    if material.dissolve > 0.0 {
+        // Scale the noise distance so that the border is as wide as the rim at most
+        let dissolve_distance = dissolve_noise(mesh.uv) - material.dissolve;
+        if dissolve_distance < 0.0 {
+            discard;
+        }
+        let edge_width = max(material.dissolve_edge_width, 0.0001);
+        edge_distance = min(edge_distance, dissolve_distance / edge_width * material.rim_width);
+    }
+#endif
+    return edge_distance;
+}
+
+// Returns the coordinates the portal's image is sampled at
+fn portal_uv(mesh: VertexOutput) -> vec2<f32> {
+    var viewport_uv = coords_to_viewport_uv(mesh.position.xy, view.viewport);
+#ifdef PORTAL_MAPPING_PLANAR
+    viewport_uv = (material.planar_from_world * vec4(mesh.world_position.xyz, 1.0)).xy;
+#endif
+#ifdef PORTAL_MAPPING_MESH_UV
+#ifdef VERTEX_UVS_A
+    viewport_uv = mesh.uv;
+#endif
+#endif
+#ifdef PORTAL_MIRROR
+    viewport_uv.x = 1.0 - viewport_uv.x;
+#endif
+#ifdef VERTEX_UVS_A
+    if material.swirl_strength != 0.0 {
+        viewport_uv += swirl_offset(mesh.uv);
+    }
+#endif
+    if material.distortion_amplitude != 0.0 {
+        viewport_uv = distort(viewport_uv);
+    }
+    return material.uv_rect.xy + viewport_uv * material.uv_rect.zw;
+}
+
+// Samples the portal's image at `uv`, averaging a disk of samples if it is blurred
+fn blur(uv: vec2<f32>) -> vec4<f32> {
+    if material.blur_radius <= 0.0 {
+        return textureSample(base_color_texture, base_color_sampler, uv);
+    }
+    // Spread the samples evenly over the disk along a golden angle spiral
+    let texel_radius = material.blur_radius / vec2<f32>(textureDimensions(base_color_texture));
+    var color = vec4(0.0);
+    for (var i = 0u; i < 16u; i += 1u) {
+        let radius = sqrt((f32(i) + 0.5) / 16.0);
+        let angle = f32(i) * 2.3999631;
+        let offset = vec2(cos(angle), sin(angle)) * radius * texel_radius;
+        color += textureSample(base_color_texture, base_color_sampler, uv + offset);
+    }
+    return color / 16.0;
+}
+
+// Samples the portal's image at `uv`, applying blur, color correction and fading
+fn portal_sample(uv: vec2<f32>) -> vec4<f32> {
+    var color = blur(uv);
+    color = vec4((color.rgb - 0.5) * material.contrast + 0.5, color.a);
+    color *= vec4(vec3(material.brightness), 1.0) * material.tint;
+    return vec4(mix(color.rgb, material.fade_color.rgb, material.fade), color.a);
+}
+
+// Blends the rim over `color`, given the distance to the closest edge of the opening
+fn portal_apply_rim(color: vec4<f32>, edge_distance: f32) -> vec4<f32> {
+    var rim = 0.0;
+    if material.rim_width > 0.0 {
+        rim = (1.0 - smoothstep(0.0, material.rim_width, edge_distance)) * material.rim_color.a;
+    }
+    return vec4(mix(color.rgb, material.rim_color.rgb, rim), color.a);
+}
+
+// Computes the color of a fragment of the portal's mesh
+fn portal_fragment(mesh: VertexOutput) -> vec4<f32> {
+    let edge_distance = portal_edge_distance(mesh);
+    let color = portal_sample(portal_uv(mesh));
+    return portal_apply_rim(color, edge_distance);
+}
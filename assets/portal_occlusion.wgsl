@@ -0,0 +1,25 @@
+struct Vertex {
+    @builtin(vertex_index) index: u32,
+    @location(0) clip_from_box_0: vec4<f32>,
+    @location(1) clip_from_box_1: vec4<f32>,
+    @location(2) clip_from_box_2: vec4<f32>,
+    @location(3) clip_from_box_3: vec4<f32>,
+}
+
+// Draws the corners of a cube spanning -1 to 1 as a triangle strip of 14 vertices
+@vertex
+fn vertex(vertex: Vertex) -> @builtin(position) vec4<f32> {
+    let bit = 1u << vertex.index;
+    let corner = vec3<f32>(
+        select(-1.0, 1.0, (0x287au & bit) != 0u),
+        select(-1.0, 1.0, (0x02afu & bit) != 0u),
+        select(-1.0, 1.0, (0x31e3u & bit) != 0u),
+    );
+    let clip_from_box = mat4x4<f32>(
+        vertex.clip_from_box_0,
+        vertex.clip_from_box_1,
+        vertex.clip_from_box_2,
+        vertex.clip_from_box_3,
+    );
+    return clip_from_box * vec4<f32>(corner, 1.0);
+}
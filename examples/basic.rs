@@ -7,7 +7,7 @@ fn main() {
     App::new()
         .add_plugins((
             DefaultPlugins,
-            PortalPlugin,
+            PortalPlugin::default(),
             #[cfg(feature = "gizmos")]
             PortalGizmosPlugin,
         ))
@@ -14,7 +14,7 @@ fn main() {
     App::new()
         .add_plugins((
             DefaultPlugins,
-            PortalPlugin,
+            PortalPlugin::default(),
             PortalPickingPlugin,
             #[cfg(feature = "gizmos")]
             PortalGizmosPlugin,
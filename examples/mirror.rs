@@ -1,5 +1,3 @@
-use std::f32::consts::PI;
-
 use bevy::{color::palettes::tailwind::ORANGE_600, prelude::*};
 #[cfg(feature = "gizmos")]
 use bevy_easy_portals::gizmos::PortalGizmosPlugin;
@@ -9,7 +7,7 @@ fn main() {
     App::new()
         .add_plugins((
             DefaultPlugins,
-            PortalPlugin,
+            PortalPlugin::default(),
             #[cfg(feature = "gizmos")]
             PortalGizmosPlugin,
         ))
@@ -57,12 +55,14 @@ fn setup(
 
     let rectangle = Rectangle::from_size(Vec2::splat(5.0));
 
-    let mirror = commands
+    commands
         .spawn((
             // No need to spawn a material for the mesh here, it will be taken care of by the
             // portal setup
             Mesh3d(meshes.add(rectangle)),
             Transform::from_xyz(0.0, 0.0, 0.0),
+            // A mirror is a portal that reflects the primary camera across itself
+            Portal::mirror(primary_camera),
         ))
         .with_children(|parent| {
             // We can use another mesh for our mirror if we wish
@@ -70,19 +70,7 @@ fn setup(
                 Mesh3d(meshes.add(rectangle)),
                 MeshMaterial3d(materials.add(Color::WHITE.with_alpha(0.2))),
             ));
-        })
-        .id();
-
-    // The target should be the transform of the mirror itself, but flipped
-    let target_transform = Transform::default().with_rotation(Quat::from_rotation_y(PI));
-    let target = commands.spawn(target_transform).id();
-
-    commands
-        .entity(mirror)
-        // Since we're constructing a mirror, let's parent the target to the mirror itself
-        .add_child(target)
-        // Now let's create the portal!
-        .insert(Portal::new(primary_camera, target));
+        });
 }
 
 fn rotate_shape(mut shape_transform: Single<&mut Transform, With<Shape>>, time: Res<Time>) {
@@ -0,0 +1,55 @@
+//! Demonstrates a portal in a 2D scene, looking at a rotating shape elsewhere in the world.
+
+use bevy::{color::palettes::tailwind::ORANGE_600, prelude::*};
+use bevy_easy_portals::portal_2d::{Portal2d, Portal2dPlugin};
+
+fn main() {
+    App::new()
+        .add_plugins((DefaultPlugins, Portal2dPlugin))
+        .add_systems(Startup, setup)
+        .add_systems(Update, rotate_shape)
+        .run();
+}
+
+#[derive(Component)]
+struct Shape;
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let primary_camera = commands
+        .spawn((
+            Camera2d,
+            Camera {
+                // The portal will inherit properties of the primary camera
+                clear_color: ClearColorConfig::Custom(Color::BLACK),
+                ..default()
+            },
+        ))
+        .id();
+
+    // Spawn a shape far away from the camera, so we can only see it through the portal
+    commands.spawn((
+        Mesh2d(meshes.add(Rectangle::from_size(Vec2::splat(100.0)))),
+        MeshMaterial2d(materials.add(Color::from(ORANGE_600))),
+        Transform::from_xyz(2000.0, 0.0, 0.0),
+        Shape,
+    ));
+
+    // Where the portal's camera should be, relative to the portal
+    let target = commands.spawn(Transform::from_xyz(2000.0, 0.0, 0.0)).id();
+
+    commands.spawn((
+        // No need to spawn a material for the mesh here, it will be taken care of by the
+        // portal setup
+        Mesh2d(meshes.add(Circle::new(150.0))),
+        Transform::default(),
+        Portal2d::new(primary_camera, target),
+    ));
+}
+
+fn rotate_shape(mut shape_transform: Single<&mut Transform, With<Shape>>, time: Res<Time>) {
+    shape_transform.rotate_z(time.delta_secs());
+}
@@ -1,27 +1,36 @@
 //! Demonstrates setting up two bidirectional portals with teleportation between them.
 //!
-//! Includes basic collision handling for transitioning seamlessly between portals, a simple camera
-//! controller for movement and looking around, and a basic scene setup
+//! Includes a simple camera controller for movement and looking around, which is teleported by the
+//! portals, and a basic scene setup
 
 use std::f32::consts::FRAC_PI_4;
 
 use bevy::{
     color::palettes::tailwind::{SKY_200, SLATE_200},
     input::mouse::MouseMotion,
-    math::bounding::{Aabb3d, IntersectsVolume},
     prelude::*,
     render::{render_resource::Face, view::RenderLayers},
     window::{CursorGrabMode, PrimaryWindow},
 };
 #[cfg(feature = "gizmos")]
 use bevy_easy_portals::gizmos::PortalGizmosPlugin;
-use bevy_easy_portals::{Portal, PortalCameraSystems, PortalPlugin};
+use bevy_easy_portals::{
+    teleport::{PortalTeleporter, PortalTraveler},
+    Portal, PortalPlugin, PortalPluginSettings,
+};
 
 fn main() {
     App::new()
         .add_plugins((
             DefaultPlugins,
-            PortalPlugin,
+            PortalPlugin {
+                settings: PortalPluginSettings {
+                    // Let the portals be seen through eachother a few times
+                    max_recursion_depth: 3,
+                    ..default()
+                },
+                ..default()
+            },
             #[cfg(feature = "gizmos")]
             PortalGizmosPlugin,
         ))
@@ -30,10 +39,6 @@ fn main() {
             Update,
             (handle_camera_look, handle_movement, apply_shape_rotation),
         )
-        .add_systems(
-            PostUpdate,
-            handle_portal_collision.before(PortalCameraSystems::UpdateFrusta),
-        )
         .run();
 }
 
@@ -70,20 +75,6 @@ impl Default for CameraController {
     }
 }
 
-// Component used to mark the portal's exit
-#[derive(Component)]
-struct Link(Entity);
-
-// Component used to track collisions between an entity and portal
-#[derive(Component, Clone, Copy)]
-#[component(storage = "SparseSet")]
-struct Collision {
-    // Offset of the entity from the portal
-    offset: Vec3,
-    // Portal entity that is being collided with
-    portal_entity: Entity,
-}
-
 // Component used to mark shapes to be rotated
 #[derive(Component)]
 struct Shape;
@@ -113,6 +104,8 @@ fn setup(
             }),
             Transform::from_translation(CAMERA_START_XYZ),
             CameraController::default(),
+            // Let the camera be teleported by the portals
+            PortalTraveler,
             RenderLayers::from_layers(&[0, 1]),
         ))
         .id();
@@ -135,9 +128,9 @@ fn setup(
     let portal_mesh = meshes.add(Rectangle::from_size(Vec2::splat(PORTAL_MESH_SIZE)));
     let wall_mesh = meshes.add(Cuboid::from_size(Vec3::splat(WALL_MESH_SIZE)));
 
-    for (sign, color, portal, other, target) in [
-        (-1.0, SKY_200, portal_a, portal_b, target_b),
-        (1.0, SLATE_200, portal_b, portal_a, target_a),
+    for (sign, color, portal, target) in [
+        (-1.0, SKY_200, portal_a, target_b),
+        (1.0, SLATE_200, portal_b, target_a),
     ] {
         // Floor
         commands.spawn((
@@ -177,82 +170,23 @@ fn setup(
                 // The mesh is a `Rectangle`, so to allow for the portal to be seen from both
                 // sides, don't cull any of its faces
                 Portal::new(primary_camera, target).with_cull_mode(None),
-                Link(other),
-                // Stop portals from recursively rendering eachother
+                PortalTeleporter,
+                // Hide the portal from portal cameras, recursion takes care of rendering it
                 RenderLayers::layer(1),
             ))
             .with_children(|parent| {
                 // Portal borders
                 for (size, translation) in PORTAL_FRAME_SIZES_AND_TRANSLATIONS {
                     parent.spawn((
-                        Mesh3d(meshes.add(Cuboid::from_size(size.into()))),
+                        Mesh3d(meshes.add(Cuboid::from_size(size))),
                         MeshMaterial3d(materials.add(Color::BLACK)),
-                        Transform::from_translation(translation.into()),
+                        Transform::from_translation(translation),
                     ));
                 }
             });
     }
 }
 
-fn handle_portal_collision(
-    mut commands: Commands,
-    mut camera_query: Query<(Entity, &mut Transform), With<CameraController>>,
-    portal_query: Query<(Entity, &Link), With<Portal>>,
-    transform_query: Query<&Transform, Without<CameraController>>,
-    mut stored_collision: Option<Single<&mut Collision>>,
-) {
-    let (camera_entity, mut camera_transform) = camera_query.get_single_mut().unwrap();
-    let camera_aabb = Aabb3d::new(camera_transform.translation, Vec3::ZERO);
-
-    for (portal_entity, link) in &portal_query {
-        let portal_transform = transform_query.get(portal_entity).unwrap();
-        let portal_aabb = Aabb3d::new(
-            portal_transform.translation,
-            Vec2::splat(PORTAL_MESH_SIZE).extend(1.0),
-        );
-
-        // Are we currently inside a portal?
-        if portal_aabb.intersects(&camera_aabb) {
-            let offset = camera_transform.translation - portal_transform.translation;
-
-            let Some(ref mut collision) = stored_collision else {
-                commands.entity(camera_entity).insert(Collision {
-                    offset,
-                    portal_entity,
-                });
-                return;
-            };
-
-            if collision.portal_entity == portal_entity {
-                let portal_forward = *portal_transform.forward();
-                let start_side = collision.offset.dot(portal_forward).signum();
-                let end_side = offset.dot(portal_forward).signum();
-
-                // Have we moved to the other side of the portal?
-                if start_side != end_side {
-                    let target_transform = transform_query.get(link.0).unwrap();
-                    let relative_translation = portal_transform.rotation.inverse()
-                        * (camera_transform.translation - portal_transform.translation);
-                    let relative_rotation =
-                        portal_transform.rotation.inverse() * camera_transform.rotation;
-                    camera_transform.translation = target_transform.translation
-                        + (target_transform.rotation * relative_translation);
-                    camera_transform.rotation = target_transform.rotation * relative_rotation;
-                } else {
-                    collision.offset = offset;
-                }
-            }
-        } else {
-            if stored_collision
-                .as_ref()
-                .is_some_and(|c| c.portal_entity == portal_entity)
-            {
-                commands.entity(camera_entity).remove::<Collision>();
-            }
-        };
-    }
-}
-
 fn handle_camera_look(
     mut mouse_motion_events: EventReader<MouseMotion>,
     mut camera_query: Query<(&CameraController, &mut Transform)>,
@@ -0,0 +1,243 @@
+//! Rendering many [`Portal`]s into one shared image.
+//!
+//! Set [`Portal::atlas`] to render a [`Portal`] into a cell of the [`PortalAtlas`] image, instead
+//! of an image of its own. The atlas is divided into [`PortalAtlas::grid`] cells, each of which
+//! holds the whole view of one [`PortalCamera`] at a fraction of the resolution. This saves
+//! texture memory for scenes with many small portals, e.g. windows or picture frames.
+//!
+//! # Notes
+//!
+//! * [`Portal::resolution_scale`], [`Portal::texture_format`] and [`Portal::scissor`] are ignored
+//!   for portals in the atlas. The atlas image uses [`PortalPluginSettings::texture_format`] at the
+//!   time it is created, or [`TextureFormat::Bgra8UnormSrgb`] if that isn't set.
+//! * Portals in the atlas are not rendered recursively.
+//! * If the atlas is full, a [`Portal`] falls back to an image of its own.
+
+use bevy::{
+    image::ImageSampler,
+    prelude::*,
+    render::{
+        camera::{camera_system, Viewport},
+        render_resource::{Extent3d, TextureFormat},
+    },
+    window::{PrimaryWindow, WindowResized},
+};
+
+#[cfg(doc)]
+use crate::PortalPluginSettings;
+use crate::{
+    camera_window, LinkedPortalCamera, Portal, PortalCamera, PortalCameraSystems, PortalImagePool,
+    PortalProjection,
+};
+
+/// Plugin that sets up the shared [`PortalAtlas`] image.
+///
+/// This is added by [`PortalPlugin`](crate::PortalPlugin), and shouldn't be added manually.
+pub(crate) struct PortalAtlasPlugin;
+
+impl Plugin for PortalAtlasPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PortalAtlas>()
+            .add_systems(
+                PreUpdate,
+                resize_portal_atlas.in_set(PortalCameraSystems::ResizeImage),
+            )
+            .add_systems(
+                PostUpdate,
+                update_portal_atlas_viewports
+                    .in_set(PortalCameraSystems::UpdateViewport)
+                    .before(camera_system::<PortalProjection>),
+            );
+    }
+}
+
+/// Resource holding the image shared by [`Portal`]s with [`Portal::atlas`] enabled.
+///
+/// To use a different grid, insert this resource before any of these portals are spawned.
+#[derive(Resource, Debug)]
+pub struct PortalAtlas {
+    grid: UVec2,
+    image: Option<Handle<Image>>,
+    cells: Vec<Option<Entity>>,
+}
+
+impl Default for PortalAtlas {
+    fn default() -> Self {
+        Self::new(UVec2::splat(4))
+    }
+}
+
+impl PortalAtlas {
+    /// Creates a new [`PortalAtlas`] with a given `grid` of cells.
+    ///
+    /// # See Also
+    ///
+    /// * [`PortalAtlas::grid`]
+    #[inline]
+    #[must_use]
+    pub fn new(grid: UVec2) -> Self {
+        Self {
+            grid,
+            image: None,
+            cells: vec![None; grid.element_product() as usize],
+        }
+    }
+
+    /// Returns the number of columns and rows the atlas image is divided into.
+    ///
+    /// Defaults to 4 by 4 cells.
+    #[inline]
+    pub fn grid(&self) -> UVec2 {
+        self.grid
+    }
+
+    /// Returns the shared image, if any [`Portal`] has been placed in the atlas yet.
+    #[inline]
+    pub fn image(&self) -> Option<&Handle<Image>> {
+        self.image.as_ref()
+    }
+
+    /// Returns the shared image, acquiring it from the [`PortalImagePool`] with a given `size` and
+    /// `format` if needed.
+    fn get_or_create_image(
+        &mut self,
+        image_pool: &mut PortalImagePool,
+        images: &mut Assets<Image>,
+        size: Extent3d,
+        format: TextureFormat,
+    ) -> Handle<Image> {
+        self.image
+            .get_or_insert_with(|| {
+                image_pool.acquire(images, size, 1.0, format, &ImageSampler::Default)
+            })
+            .clone()
+    }
+
+    /// Returns the shared image, creating it with a given `size` and `format` if needed, along
+    /// with the [`Viewport`] of a `cell` within it.
+    pub(crate) fn cell_target(
+        &mut self,
+        image_pool: &mut PortalImagePool,
+        images: &mut Assets<Image>,
+        cell: u32,
+        size: Extent3d,
+        format: TextureFormat,
+    ) -> (Handle<Image>, Viewport) {
+        let image_handle = self.get_or_create_image(image_pool, images, size, format);
+        let image_size = images
+            .get(&image_handle)
+            .map_or(UVec2::new(size.width, size.height), Image::size);
+        (image_handle, self.cell_viewport(cell, image_size))
+    }
+
+    /// Assigns the first free cell to a given `portal`.
+    ///
+    /// Returns [`None`] if the atlas is full.
+    pub(crate) fn allocate(&mut self, portal: Entity) -> Option<u32> {
+        let index = self.cells.iter().position(Option::is_none)?;
+        self.cells[index] = Some(portal);
+        Some(index as u32)
+    }
+
+    /// Frees the cell assigned to a given `portal`.
+    pub(crate) fn free(&mut self, portal: Entity) {
+        for cell in &mut self.cells {
+            if *cell == Some(portal) {
+                *cell = None;
+            }
+        }
+    }
+
+    /// Returns the offset and size of a `cell` in UV coordinates, as `(x, y, width, height)`.
+    pub(crate) fn cell_uv_rect(&self, cell: u32) -> Vec4 {
+        let size = Vec2::ONE / self.grid.as_vec2();
+        let offset = UVec2::new(cell % self.grid.x, cell / self.grid.x).as_vec2() * size;
+        Vec4::new(offset.x, offset.y, size.x, size.y)
+    }
+
+    /// Returns the [`Viewport`] of a `cell`, for an atlas image of a given `size`.
+    pub(crate) fn cell_viewport(&self, cell: u32, size: UVec2) -> Viewport {
+        let position = UVec2::new(cell % self.grid.x, cell / self.grid.x);
+        let start = position * size / self.grid;
+        let end = (position + UVec2::ONE) * size / self.grid;
+        Viewport {
+            physical_position: start,
+            physical_size: (end - start).max(UVec2::ONE),
+            ..default()
+        }
+    }
+}
+
+/// Component holding the [`PortalAtlas`] cell a [`Portal`] renders into.
+#[derive(Component, Debug)]
+pub(crate) struct PortalAtlasCell(pub(crate) u32);
+
+/// System that keeps the [`Viewport`] of [`PortalCamera`]s in the atlas within their cell.
+fn update_portal_atlas_viewports(
+    atlas: Res<PortalAtlas>,
+    portal_query: Query<(&LinkedPortalCamera, &PortalAtlasCell)>,
+    mut camera_query: Query<&mut Camera, With<PortalCamera>>,
+) {
+    for (linked_camera, &PortalAtlasCell(cell)) in &portal_query {
+        let Ok(mut camera) = camera_query.get_mut(linked_camera.get()) else {
+            continue;
+        };
+        let Some(target_size) = camera.physical_target_size() else {
+            continue;
+        };
+
+        let viewport = atlas.cell_viewport(cell, target_size);
+        let unchanged = camera.viewport.as_ref().is_some_and(|current| {
+            current.physical_position == viewport.physical_position
+                && current.physical_size == viewport.physical_size
+        });
+        if !unchanged {
+            camera.viewport = Some(viewport);
+        }
+    }
+}
+
+/// System that resizes the [`PortalAtlas`] image if any [`WindowResized`] events are read for a
+/// window the primary camera of a [`Portal`] in the atlas renders to.
+fn resize_portal_atlas(
+    mut resized_reader: EventReader<WindowResized>,
+    window_query: Query<&Window>,
+    primary_window_query: Query<Entity, With<PrimaryWindow>>,
+    portal_query: Query<&Portal, With<PortalAtlasCell>>,
+    primary_camera_query: Query<&Camera, Without<PortalCamera>>,
+    atlas: Res<PortalAtlas>,
+    mut image_pool: ResMut<PortalImagePool>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let primary_window = primary_window_query.get_single().ok();
+    for event in resized_reader.read() {
+        // Windows without portals in the atlas, e.g. tool windows, don't affect its size
+        let has_portals = portal_query.iter().any(|portal| {
+            primary_camera_query
+                .get(portal.primary_camera)
+                .ok()
+                .and_then(|primary_camera| camera_window(primary_camera, primary_window))
+                == Some(event.window)
+        });
+        if !has_portals {
+            continue;
+        }
+        let Some(image_handle) = atlas.image() else {
+            continue;
+        };
+        let Ok(window) = window_query.get(event.window) else {
+            continue;
+        };
+
+        let window_size = window.physical_size();
+        image_pool.resize_image(
+            &mut images,
+            image_handle,
+            Extent3d {
+                width: window_size.x,
+                height: window_size.y,
+                ..default()
+            },
+        );
+    }
+}
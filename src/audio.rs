@@ -0,0 +1,386 @@
+//! Spatial audio through [`Portal`]s.
+//!
+//! Add the [`PortalAudioPlugin`], then [`PortalAudioEmitter`] to an entity playing a spatial
+//! [`AudioPlayer`]. While the emitter is near a portal's [`Portal::target`] and the
+//! [`SpatialListener`] is near the portal, a [`PortalAudioProxy`] playing the same sound is placed
+//! on the portal's mesh, so that the emitter is heard through the portal. The proxy's volume fades
+//! with the distance from the listener to the emitter through the portal.
+//!
+//! Add [`PortalAudioSettings`] to a [`Portal`] to change how sound passes through it, e.g. to
+//! muffle it while the portal's [`PortalAperture`] is closed.
+//!
+//! # Notes
+//!
+//! * `bevy_audio` doesn't attenuate spatial sounds by distance, so proxies fade out linearly
+//!   instead, until they're silent at [`PortalAudioEmitter::max_distance`].
+//! * Proxies start playing when they are spawned, so they are best suited to looping sounds.
+//! * Secondary views of a [`Portal`] share its mesh, and are skipped.
+
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use bevy::{
+    audio::{AddAudioSource, CpalSample, Decodable, PlaybackMode, Source, Volume},
+    prelude::*,
+    render::{primitives::Aabb, render_resource::Face},
+    utils::{HashMap, HashSet},
+};
+
+use crate::{transform_through_portal, Mirror, Portal, PortalAperture};
+
+/// Plugin that lets [`PortalAudioEmitter`]s be heard through [`Portal`]s.
+pub struct PortalAudioPlugin;
+
+impl Plugin for PortalAudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_source::<PortalFilteredAudio>()
+            .init_resource::<PortalAudioProxies>()
+            .add_systems(
+                PostUpdate,
+                update_portal_audio_proxies.after(TransformSystem::TransformPropagate),
+            )
+            .register_type::<(PortalAudioEmitter, PortalAudioProxy, PortalAudioSettings)>();
+    }
+}
+
+/// Component used to make a spatial [`AudioPlayer`] heard through [`Portal`]s.
+///
+/// This requires the [`PortalAudioPlugin`].
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component, Default)]
+pub struct PortalAudioEmitter {
+    /// The distance from the [`SpatialListener`] through a portal at which the emitter is no
+    /// longer heard.
+    ///
+    /// Defaults to `20.0`.
+    pub max_distance: f32,
+}
+
+impl Default for PortalAudioEmitter {
+    fn default() -> Self {
+        Self { max_distance: 20.0 }
+    }
+}
+
+/// Component used to configure how sound passes through a [`Portal`].
+///
+/// [`Portal`]s without this component pass sound through unchanged, and block it while closed.
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component, Default)]
+pub struct PortalAudioSettings {
+    /// The factor the volume of sound passing through the portal is multiplied by.
+    ///
+    /// Defaults to `1.0`.
+    pub attenuation: f32,
+    /// How much the high frequencies of sound passing through the portal are filtered out, from
+    /// `0.0` (not at all) to `1.0` (muffled).
+    ///
+    /// As the portal's [`PortalAperture`] closes, this increases towards `1.0`.
+    ///
+    /// Defaults to `0.0`.
+    pub low_pass: f32,
+    /// Whether sound is blocked entirely while the portal's [`PortalAperture`] is closed, rather
+    /// than muffled.
+    ///
+    /// Defaults to `true`.
+    pub block_when_closed: bool,
+}
+
+impl Default for PortalAudioSettings {
+    fn default() -> Self {
+        Self {
+            attenuation: 1.0,
+            low_pass: 0.0,
+            block_when_closed: true,
+        }
+    }
+}
+
+/// An [`AudioSource`] played through a low-pass filter whose strength can change while playing.
+///
+/// This is what [`PortalAudioProxy`]s play, to muffle sound passing through a [`Portal`].
+#[derive(Asset, TypePath, Clone)]
+pub struct PortalFilteredAudio {
+    source: AudioSource,
+    low_pass: Arc<AtomicU32>,
+}
+
+impl Decodable for PortalFilteredAudio {
+    type DecoderItem = f32;
+    type Decoder = PortalLowPass<<AudioSource as Decodable>::Decoder>;
+
+    fn decoder(&self) -> Self::Decoder {
+        PortalLowPass {
+            source: self.source.decoder(),
+            low_pass: self.low_pass.clone(),
+            filtered: Vec::new(),
+            channel: 0,
+        }
+    }
+}
+
+/// A one-pole low-pass filter over a [`Source`], used by [`PortalFilteredAudio`].
+pub struct PortalLowPass<S> {
+    source: S,
+    low_pass: Arc<AtomicU32>,
+    /// The last filtered sample of each channel.
+    filtered: Vec<f32>,
+    channel: usize,
+}
+
+// Decoders of `AudioSource`s yield `i16` samples
+impl<S: Source<Item = i16>> Iterator for PortalLowPass<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.source.next()?.to_sample::<f32>();
+        let channels = usize::from(self.source.channels().max(1));
+        if self.filtered.len() != channels {
+            self.filtered = vec![sample; channels];
+            self.channel = 0;
+        }
+
+        // Keep a little of each new sample, so that the sound is never silenced entirely
+        let low_pass = f32::from_bits(self.low_pass.load(Ordering::Relaxed)).clamp(0.0, 0.98);
+        let filtered = &mut self.filtered[self.channel];
+        *filtered += (1.0 - low_pass) * (sample - *filtered);
+        self.channel = (self.channel + 1) % channels;
+        Some(*filtered)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.source.size_hint()
+    }
+}
+
+impl<S: Source<Item = i16>> Source for PortalLowPass<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+/// Component added to the proxy of a [`PortalAudioEmitter`] heard through a [`Portal`].
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component)]
+pub struct PortalAudioProxy {
+    /// The [`PortalAudioEmitter`] entity that is heard through the portal.
+    pub emitter: Entity,
+    /// The [`Portal`] entity the emitter is heard through.
+    pub portal: Entity,
+}
+
+/// The low-pass strength shared with the [`PortalFilteredAudio`] a [`PortalAudioProxy`] plays.
+#[derive(Component)]
+struct PortalAudioLowPass(Arc<AtomicU32>);
+
+/// The [`PortalAudioProxy`] of each [`PortalAudioEmitter`] and [`Portal`] it is heard through.
+#[derive(Resource, Default)]
+struct PortalAudioProxies(HashMap<(Entity, Entity), Entity>);
+
+/// System that spawns, moves and despawns the [`PortalAudioProxy`]s of [`PortalAudioEmitter`]s.
+fn update_portal_audio_proxies(
+    mut commands: Commands,
+    mut proxies: ResMut<PortalAudioProxies>,
+    listener_query: Query<&GlobalTransform, (With<SpatialListener>, Without<PortalAudioProxy>)>,
+    portal_query: Query<
+        (
+            Entity,
+            &Portal,
+            &GlobalTransform,
+            &Aabb,
+            Option<&Parent>,
+            Option<&PortalAperture>,
+            Option<&PortalAudioSettings>,
+        ),
+        (Without<Mirror>, Without<PortalAudioProxy>),
+    >,
+    aperture_query: Query<&PortalAperture>,
+    global_transform_query: Query<&GlobalTransform, Without<PortalAudioProxy>>,
+    emitter_query: Query<
+        (
+            Entity,
+            &PortalAudioEmitter,
+            &AudioPlayer,
+            &PlaybackSettings,
+            &GlobalTransform,
+        ),
+        Without<PortalAudioProxy>,
+    >,
+    mut proxy_query: Query<
+        (
+            &mut Transform,
+            &mut GlobalTransform,
+            Option<&SpatialAudioSink>,
+            &PortalAudioLowPass,
+        ),
+        With<PortalAudioProxy>,
+    >,
+    audio_sources: Res<Assets<AudioSource>>,
+    mut filtered_audio: ResMut<Assets<PortalFilteredAudio>>,
+) {
+    let mut heard = HashSet::new();
+
+    // Without a listener, every proxy is despawned
+    let listener_translation = listener_query
+        .iter()
+        .next()
+        .map(GlobalTransform::translation);
+    if let Some(listener_translation) = listener_translation {
+        for (emitter, audio_emitter, audio_player, settings, emitter_global_transform) in
+            &emitter_query
+        {
+            for (entity, portal, portal_global_transform, aabb, parent, aperture, audio_settings) in
+                &portal_query
+            {
+                if parent.is_some_and(|parent| portal_query.contains(parent.get())) {
+                    continue;
+                }
+                let audio_settings = audio_settings.copied().unwrap_or_default();
+                let aperture = PortalAperture::resolve(aperture, parent, &aperture_query);
+                if audio_settings.block_when_closed && aperture <= 0.0 {
+                    continue;
+                }
+                let Ok(target_global_transform) = global_transform_query.get(portal.target) else {
+                    continue;
+                };
+                let portal_transform = portal_global_transform.compute_transform();
+                let target_transform = target_global_transform.compute_transform();
+
+                // Where the emitter appears to be when looking through the portal
+                let virtual_translation = transform_through_portal(
+                    &target_transform,
+                    &portal_transform,
+                    Transform::from_translation(emitter_global_transform.translation()),
+                )
+                .translation;
+
+                // Positive values are in front of the portal's mesh
+                let local_from_world = portal_global_transform.affine().inverse();
+                let local_listener = local_from_world.transform_point3(listener_translation);
+                let local_virtual = local_from_world.transform_point3(virtual_translation);
+                let can_hear = match portal.cull_mode {
+                    Some(Face::Back) => local_listener.z > 0.0,
+                    Some(Face::Front) => local_listener.z < 0.0,
+                    None => true,
+                };
+                if !can_hear {
+                    continue;
+                }
+
+                // The sound comes from where the line to the emitter meets the portal's mesh
+                let along_line = if local_listener.z.signum() != local_virtual.z.signum() {
+                    local_listener.z / (local_listener.z - local_virtual.z)
+                } else {
+                    1.0
+                };
+                let local_surface = local_listener
+                    .lerp(local_virtual, along_line)
+                    .truncate()
+                    .clamp(
+                        Vec3::from(aabb.min()).truncate(),
+                        Vec3::from(aabb.max()).truncate(),
+                    )
+                    .extend(0.0);
+                let surface_translation = portal_global_transform
+                    .affine()
+                    .transform_point3(local_surface);
+
+                let distance = listener_translation.distance(surface_translation)
+                    + surface_translation.distance(virtual_translation);
+                if distance >= audio_emitter.max_distance {
+                    continue;
+                }
+                let volume = settings.volume.get()
+                    * audio_settings.attenuation
+                    * (1.0 - distance / audio_emitter.max_distance.max(f32::EPSILON));
+                // Closing the portal muffles the sound
+                let low_pass =
+                    audio_settings.low_pass + (1.0 - audio_settings.low_pass) * (1.0 - aperture);
+
+                heard.insert((emitter, entity));
+                let transform = Transform::from_translation(surface_translation);
+                let proxy = proxies.0.get(&(emitter, entity)).copied();
+                if let Some((
+                    mut proxy_transform,
+                    mut proxy_global_transform,
+                    sink,
+                    proxy_low_pass,
+                )) = proxy.and_then(|proxy| proxy_query.get_mut(proxy).ok())
+                {
+                    if *proxy_transform != transform {
+                        *proxy_transform = transform;
+                        *proxy_global_transform = GlobalTransform::from(transform);
+                    }
+                    if let Some(sink) = sink {
+                        sink.set_volume(volume);
+                    }
+                    proxy_low_pass
+                        .0
+                        .store(low_pass.to_bits(), Ordering::Relaxed);
+                    continue;
+                }
+
+                let Some(source) = audio_sources.get(&audio_player.0) else {
+                    continue;
+                };
+                let proxy_low_pass = Arc::new(AtomicU32::new(low_pass.to_bits()));
+                let filtered = filtered_audio.add(PortalFilteredAudio {
+                    source: source.clone(),
+                    low_pass: proxy_low_pass.clone(),
+                });
+
+                let proxy = commands
+                    .spawn((
+                        Name::new("Portal Audio Proxy"),
+                        PortalAudioProxy {
+                            emitter,
+                            portal: entity,
+                        },
+                        AudioPlayer(filtered),
+                        PortalAudioLowPass(proxy_low_pass),
+                        PlaybackSettings {
+                            // Proxies are despawned by this system only
+                            mode: match settings.mode {
+                                PlaybackMode::Loop => PlaybackMode::Loop,
+                                _ => PlaybackMode::Once,
+                            },
+                            volume: Volume::new(volume),
+                            spatial: true,
+                            ..*settings
+                        },
+                        transform,
+                        GlobalTransform::from(transform),
+                    ))
+                    .id();
+                proxies.0.insert((emitter, entity), proxy);
+            }
+        }
+    }
+
+    proxies.0.retain(|key, &mut proxy| {
+        let keep = heard.contains(key);
+        if !keep {
+            if let Some(proxy) = commands.get_entity(proxy) {
+                proxy.despawn_recursive();
+            }
+        }
+        keep
+    });
+}
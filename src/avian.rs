@@ -0,0 +1,210 @@
+//! [Avian](https://github.com/Jondolf/avian) physics integration.
+//!
+//! Add the [`PortalAvianPlugin`] to teleport dynamic and kinematic [`RigidBody`] entities through
+//! [`PortalTeleporter`]s. Their [`Position`], [`Rotation`], [`LinearVelocity`] and
+//! [`AngularVelocity`] are moved into the space of [`Portal::target`], so that they keep moving
+//! the same way relative to the portal they exit.
+//!
+//! # Notes
+//!
+//! * Crossings are detected by the [`teleport`](crate::teleport) module, so
+//!   [`PortalTraveler`] is added to non-static [`RigidBody`] entities automatically.
+//! * While a [`RigidBody`] straddles a [`PortalTeleporter`], a [`PortalColliderProxy`] of its
+//!   [`Collider`] is placed at [`Portal::target`], so that e.g. a box half-way through rests on the
+//!   floor on the far side. Colliders around the portal should not collide with travelers, or the
+//!   proxy gets stuck in the wall around the target.
+//!
+//! [`PortalTeleporter`]: crate::teleport::PortalTeleporter
+//! [`Portal::target`]: crate::Portal::target
+
+use avian3d::prelude::*;
+use bevy::{
+    prelude::*,
+    render::primitives::Aabb,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    portal_transform_velocity,
+    teleport::{
+        PortalCrossed, PortalStraddle, PortalTeleportSystems, PortalTraveler, StraddledTeleporters,
+    },
+    Portal,
+};
+
+/// Plugin that teleports [`RigidBody`] entities through portals.
+pub struct PortalAvianPlugin;
+
+impl Plugin for PortalAvianPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PortalColliderProxies>()
+            .add_systems(
+                PostUpdate,
+                (teleport_rigid_bodies, update_collider_proxies)
+                    .chain()
+                    .after(PortalTeleportSystems)
+                    .before(TransformSystem::TransformPropagate),
+            )
+            .add_observer(add_traveler)
+            .register_type::<PortalColliderProxy>();
+    }
+}
+
+/// Component added to the collider proxy of a [`RigidBody`] straddling a [`PortalTeleporter`].
+///
+/// The proxy is a child of the body with a copy of its [`Collider`], placed where the collider
+/// would be if the body were teleported. This way, the part of the body that passed through the
+/// portal collides with what is around [`Portal::target`]. The proxy has no mass, and is despawned
+/// once the body no longer straddles the portal.
+///
+/// [`PortalTeleporter`]: crate::teleport::PortalTeleporter
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component)]
+pub struct PortalColliderProxy {
+    /// The [`Portal`] entity the body straddles.
+    pub portal: Entity,
+}
+
+/// The [`PortalColliderProxy`] of each [`RigidBody`] and [`Portal`] it straddles.
+#[derive(Resource, Default)]
+struct PortalColliderProxies(HashMap<(Entity, Entity), Entity>);
+
+/// Observer that adds [`PortalTraveler`] to new non-static [`RigidBody`] entities.
+fn add_traveler(
+    trigger: Trigger<OnAdd, RigidBody>,
+    mut commands: Commands,
+    rigid_body_query: Query<&RigidBody, Without<PortalTraveler>>,
+) {
+    let entity = trigger.entity();
+    if rigid_body_query
+        .get(entity)
+        .is_ok_and(|rigid_body| !rigid_body.is_static())
+    {
+        commands.entity(entity).insert(PortalTraveler);
+    }
+}
+
+/// System that moves the physics state of teleported [`RigidBody`] entities into the space of the
+/// portal's target.
+fn teleport_rigid_bodies(
+    mut crossed_events: EventReader<PortalCrossed>,
+    portal_query: Query<(&Portal, &GlobalTransform)>,
+    global_transform_query: Query<&GlobalTransform>,
+    mut rigid_body_query: Query<
+        (
+            &mut Position,
+            &mut Rotation,
+            Option<&mut LinearVelocity>,
+            Option<&mut AngularVelocity>,
+        ),
+        With<RigidBody>,
+    >,
+) {
+    for crossed in crossed_events.read().filter(|crossed| crossed.teleported) {
+        let Ok((mut position, mut rotation, linear_velocity, angular_velocity)) =
+            rigid_body_query.get_mut(crossed.entity)
+        else {
+            continue;
+        };
+        let Ok((portal, portal_global_transform)) = portal_query.get(crossed.portal) else {
+            continue;
+        };
+        let Ok(target_global_transform) = global_transform_query.get(portal.target) else {
+            continue;
+        };
+
+        position.0 = crossed.transform.translation;
+        rotation.0 = crossed.transform.rotation;
+
+        let (linvel, angvel) = portal_transform_velocity(
+            &portal_global_transform.compute_transform(),
+            &target_global_transform.compute_transform(),
+            linear_velocity
+                .as_deref()
+                .map_or(Vec3::ZERO, |velocity| velocity.0),
+            angular_velocity
+                .as_deref()
+                .map_or(Vec3::ZERO, |velocity| velocity.0),
+        );
+        if let Some(mut linear_velocity) = linear_velocity {
+            linear_velocity.0 = linvel;
+        }
+        if let Some(mut angular_velocity) = angular_velocity {
+            angular_velocity.0 = angvel;
+        }
+    }
+}
+
+/// System that spawns, moves and despawns the [`PortalColliderProxy`]s of [`RigidBody`] entities
+/// straddling a [`PortalTeleporter`].
+fn update_collider_proxies(
+    mut commands: Commands,
+    mut proxies: ResMut<PortalColliderProxies>,
+    straddled_teleporters: StraddledTeleporters,
+    rigid_body_query: Query<
+        (Entity, &Transform, Ref<Collider>),
+        (With<RigidBody>, With<PortalTraveler>),
+    >,
+    mut proxy_query: Query<
+        (&mut Transform, &mut Collider),
+        (With<PortalColliderProxy>, Without<RigidBody>),
+    >,
+) {
+    let mut straddled = HashSet::new();
+
+    for (rigid_body, transform, collider) in &rigid_body_query {
+        let aabb = collider.shape().compute_local_aabb();
+        let aabb = Aabb::from_min_max(
+            Vec3::new(aabb.mins.x, aabb.mins.y, aabb.mins.z),
+            Vec3::new(aabb.maxs.x, aabb.maxs.y, aabb.maxs.z),
+        );
+
+        for PortalStraddle {
+            portal,
+            through_portal,
+            ..
+        } in straddled_teleporters.get(transform, &aabb)
+        {
+            straddled.insert((rigid_body, portal));
+            // The proxy is a child of the body, so it moves along with it
+            let proxy_transform = Transform::from_matrix(
+                transform.compute_matrix().inverse() * through_portal.compute_matrix(),
+            );
+
+            let proxy = proxies.0.get(&(rigid_body, portal)).copied();
+            if let Some((mut current_transform, mut proxy_collider)) =
+                proxy.and_then(|proxy| proxy_query.get_mut(proxy).ok())
+            {
+                if *current_transform != proxy_transform {
+                    *current_transform = proxy_transform;
+                }
+                if collider.is_changed() {
+                    *proxy_collider = Collider::clone(&collider);
+                }
+                continue;
+            }
+
+            let proxy = commands
+                .spawn((
+                    Name::new("Portal Collider Proxy"),
+                    PortalColliderProxy { portal },
+                    Collider::clone(&collider),
+                    ColliderDensity(0.0),
+                    proxy_transform,
+                ))
+                .set_parent(rigid_body)
+                .id();
+            proxies.0.insert((rigid_body, portal), proxy);
+        }
+    }
+
+    proxies.0.retain(|key, &mut proxy| {
+        let keep = straddled.contains(key);
+        if !keep {
+            if let Some(proxy) = commands.get_entity(proxy) {
+                proxy.despawn_recursive();
+            }
+        }
+        keep
+    });
+}
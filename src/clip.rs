@@ -0,0 +1,192 @@
+//! Clipping meshes against the plane of a [`Portal`].
+//!
+//! Add [`PortalClipped`] to an entity with a [`StandardMaterial`] to hide the part of its mesh on
+//! one side of a plane. The entity's material is replaced by a [`PortalClippedMaterial`] of its
+//! own, and restored once [`PortalClipped`] is removed.
+//!
+//! [`PortalTraveler`]s straddling a [`PortalTeleporter`] and their [`PortalTravelerClone`]s are
+//! clipped automatically, so that neither pokes through the wall around the portal.
+//!
+//! # Notes
+//!
+//! * Only [`StandardMaterial`]s are clipped.
+//! * The depth prepass isn't clipped, so clipped meshes may still occlude what is behind them in
+//!   effects relying on it.
+//!
+//! [`Portal`]: crate::Portal
+//! [`PortalTraveler`]: crate::teleport::PortalTraveler
+//! [`PortalTeleporter`]: crate::teleport::PortalTeleporter
+//! [`PortalTravelerClone`]: crate::teleport::PortalTravelerClone
+
+use bevy::{
+    asset::load_internal_asset,
+    pbr::{ExtendedMaterial, MaterialExtension},
+    prelude::*,
+    render::render_resource::{AsBindGroup, ShaderRef},
+};
+
+const PORTAL_CLIP_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(160347920741382610558192310928737645003);
+
+/// Plugin that clips entities with [`PortalClipped`].
+///
+/// This is added by [`PortalPlugin`](crate::PortalPlugin), and shouldn't be added manually.
+pub(crate) struct PortalClipPlugin;
+
+impl Plugin for PortalClipPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            PORTAL_CLIP_SHADER_HANDLE,
+            concat!(env!("CARGO_MANIFEST_DIR"), "/assets/portal_clip.wgsl"),
+            Shader::from_wgsl
+        );
+
+        app.add_plugins(MaterialPlugin::<PortalClippedMaterial>::default())
+            .add_systems(
+                PostUpdate,
+                update_clipped_materials.after(TransformSystem::TransformPropagate),
+            )
+            .add_observer(unclip_material)
+            .register_type::<PortalClipped>();
+    }
+}
+
+/// A [`StandardMaterial`] clipped against a plane.
+pub type PortalClippedMaterial = ExtendedMaterial<StandardMaterial, PortalClipExtension>;
+
+/// Component used to clip an entity's mesh against the local xy-plane of another entity, e.g. a
+/// [`Portal`](crate::Portal) or its [`Portal::target`](crate::Portal::target).
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component)]
+pub struct PortalClipped {
+    /// The entity whose local xy-plane the mesh is clipped against.
+    ///
+    /// This entity should contain a [`GlobalTransform`] component.
+    pub plane: Entity,
+    /// Whether the part of the mesh in front of the plane (along its local positive z-axis) is
+    /// kept, rather than the part behind it.
+    pub keep_front: bool,
+}
+
+/// A [`MaterialExtension`] discarding the fragments on one side of a plane.
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone, Default)]
+pub struct PortalClipExtension {
+    /// The plane fragments are clipped against, as a world space normal and a distance.
+    ///
+    /// Fragments where `dot(clip_plane.xyz, world_position) + clip_plane.w` is negative are
+    /// discarded.
+    ///
+    /// This field's value is kept in sync with [`PortalClipped`].
+    ///
+    /// Defaults to [`Vec4::ZERO`], which keeps every fragment.
+    #[uniform(100)]
+    pub clip_plane: Vec4,
+}
+
+impl MaterialExtension for PortalClipExtension {
+    fn fragment_shader() -> ShaderRef {
+        PORTAL_CLIP_SHADER_HANDLE.into()
+    }
+
+    fn deferred_fragment_shader() -> ShaderRef {
+        PORTAL_CLIP_SHADER_HANDLE.into()
+    }
+}
+
+/// The [`StandardMaterial`] of an entity with [`PortalClipped`], restored once it is removed.
+#[derive(Component)]
+struct PortalUnclippedMaterial(Handle<StandardMaterial>);
+
+/// System that replaces the [`StandardMaterial`] of entities with [`PortalClipped`] by a
+/// [`PortalClippedMaterial`], and keeps its plane in sync.
+fn update_clipped_materials(
+    mut commands: Commands,
+    clipped_query: Query<(
+        Entity,
+        &PortalClipped,
+        Option<&MeshMaterial3d<StandardMaterial>>,
+        Option<&MeshMaterial3d<PortalClippedMaterial>>,
+        Option<&PortalUnclippedMaterial>,
+    )>,
+    global_transform_query: Query<&GlobalTransform>,
+    standard_materials: Res<Assets<StandardMaterial>>,
+    mut clipped_materials: ResMut<Assets<PortalClippedMaterial>>,
+) {
+    for (entity, clipped, standard_material, clipped_material, unclipped_material) in &clipped_query
+    {
+        let Ok(plane_global_transform) = global_transform_query.get(clipped.plane) else {
+            continue;
+        };
+        let normal = if clipped.keep_front {
+            plane_global_transform.back()
+        } else {
+            plane_global_transform.forward()
+        };
+        let clip_plane = normal.extend(-normal.dot(plane_global_transform.translation()));
+
+        // The material may have been inserted again, e.g. by another plugin
+        if let Some(standard_material) = standard_material {
+            if clipped_material.is_some()
+                && unclipped_material.is_some_and(|unclipped| unclipped.0 == standard_material.0)
+            {
+                commands
+                    .entity(entity)
+                    .remove::<MeshMaterial3d<StandardMaterial>>();
+                continue;
+            }
+            let Some(base) = standard_materials.get(standard_material) else {
+                continue;
+            };
+            let clipped_material = clipped_materials.add(PortalClippedMaterial {
+                base: base.clone(),
+                extension: PortalClipExtension { clip_plane },
+            });
+            commands
+                .entity(entity)
+                .remove::<MeshMaterial3d<StandardMaterial>>()
+                .insert((
+                    MeshMaterial3d(clipped_material),
+                    PortalUnclippedMaterial(standard_material.0.clone()),
+                ));
+            continue;
+        }
+
+        let Some(clipped_material) = clipped_material else {
+            continue;
+        };
+        if clipped_materials
+            .get(clipped_material)
+            .is_none_or(|material| material.extension.clip_plane == clip_plane)
+        {
+            continue;
+        }
+        if let Some(material) = clipped_materials.get_mut(clipped_material) {
+            material.extension.clip_plane = clip_plane;
+        }
+    }
+}
+
+/// Observer that restores the [`StandardMaterial`] of an entity once [`PortalClipped`] is removed.
+fn unclip_material(
+    trigger: Trigger<OnRemove, PortalClipped>,
+    mut commands: Commands,
+    unclipped_query: Query<&PortalUnclippedMaterial>,
+) {
+    let entity = trigger.entity();
+    let Ok(unclipped_material) = unclipped_query.get(entity) else {
+        return;
+    };
+    let standard_material = MeshMaterial3d(unclipped_material.0.clone());
+    // The entity may be in the middle of being despawned
+    commands.queue(move |world: &mut World| {
+        if let Ok(mut entity) = world.get_entity_mut(entity) {
+            entity
+                .remove::<(
+                    MeshMaterial3d<PortalClippedMaterial>,
+                    PortalUnclippedMaterial,
+                )>()
+                .insert(standard_material);
+        }
+    });
+}
@@ -0,0 +1,72 @@
+//! Spawning [`Portal`]s in a single call.
+//!
+//! Import [`PortalCommandsExt`] to spawn a [`Portal`] along with its mesh and target, or a pair of
+//! [`Portal`]s looking through each other, from [`Commands`].
+
+use bevy::prelude::*;
+
+use crate::Portal;
+
+/// Extension trait for [`Commands`] that spawns [`Portal`]s along with their targets.
+pub trait PortalCommandsExt {
+    /// Spawns a [`Portal`] with a given `mesh` at `transform`, and a target entity at
+    /// `target_transform`.
+    ///
+    /// The [`Portal`] is created by `portal` from the target entity, e.g.
+    /// `|target| Portal::new(primary_camera, target)`.
+    ///
+    /// Returns the portal entity and the target entity.
+    fn spawn_portal(
+        &mut self,
+        mesh: Handle<Mesh>,
+        transform: Transform,
+        target_transform: Transform,
+        portal: impl FnOnce(Entity) -> Portal,
+    ) -> (Entity, Entity);
+
+    /// Spawns two [`Portal`]s with a given `mesh` at `transforms`, each looking out of the other.
+    ///
+    /// Each [`Portal`] is created by `portal` from its target entity, which is a child of the other
+    /// portal. Moving one of the portals therefore changes what the other one shows.
+    ///
+    /// Returns the two portal entities, in the order of `transforms`.
+    fn spawn_portal_pair(
+        &mut self,
+        mesh: Handle<Mesh>,
+        transforms: [Transform; 2],
+        portal: impl FnMut(Entity) -> Portal,
+    ) -> [Entity; 2];
+}
+
+impl PortalCommandsExt for Commands<'_, '_> {
+    fn spawn_portal(
+        &mut self,
+        mesh: Handle<Mesh>,
+        transform: Transform,
+        target_transform: Transform,
+        portal: impl FnOnce(Entity) -> Portal,
+    ) -> (Entity, Entity) {
+        let target = self.spawn(target_transform).id();
+        let portal = self.spawn((Mesh3d(mesh), transform, portal(target))).id();
+        (portal, target)
+    }
+
+    fn spawn_portal_pair(
+        &mut self,
+        mesh: Handle<Mesh>,
+        transforms: [Transform; 2],
+        mut portal: impl FnMut(Entity) -> Portal,
+    ) -> [Entity; 2] {
+        // Both targets need to exist before either portal is set up
+        let targets = [(); 2].map(|_| self.spawn(Transform::IDENTITY).id());
+        [0, 1].map(|index| {
+            self.spawn((
+                Mesh3d(mesh.clone()),
+                transforms[index],
+                portal(targets[1 - index]),
+            ))
+            .add_child(targets[index])
+            .id()
+        })
+    }
+}
@@ -0,0 +1,126 @@
+//! Picture-in-picture overlay of [`Portal`] images, for debugging.
+//!
+//! Add the [`PortalDebugOverlayPlugin`] to show the image rendered by each [`PortalCamera`] in the
+//! bottom right corner of the screen. This makes it possible to check the output of a
+//! [`PortalCamera`] independently of how the [`PortalMaterial`] samples it, e.g. when a portal is
+//! only showing black.
+//!
+//! [`PortalCamera`]: crate::PortalCamera
+//! [`PortalMaterial`]: crate::PortalMaterial
+
+use bevy::prelude::*;
+
+use crate::{Portal, PortalImage};
+
+/// Plugin that shows the image of every [`Portal`] in an overlay.
+pub struct PortalDebugOverlayPlugin;
+
+impl Plugin for PortalDebugOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PortalDebugOverlay>()
+            .register_type::<PortalDebugOverlay>()
+            .add_systems(PostUpdate, update_portal_debug_overlay);
+    }
+}
+
+/// Resource used to configure the overlay of the [`PortalDebugOverlayPlugin`].
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource, Default)]
+pub struct PortalDebugOverlay {
+    /// Whether the overlay is shown.
+    ///
+    /// Defaults to `true`.
+    pub enabled: bool,
+    /// The width of each image in the overlay, in logical pixels.
+    ///
+    /// Defaults to `192.0`.
+    pub width: f32,
+}
+
+impl Default for PortalDebugOverlay {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            width: 192.0,
+        }
+    }
+}
+
+/// Marker component for the root UI node of the overlay.
+#[derive(Component)]
+struct PortalDebugOverlayRoot;
+
+/// System that rebuilds the overlay whenever it or the [`PortalImage`]s change.
+fn update_portal_debug_overlay(
+    mut commands: Commands,
+    overlay: Res<PortalDebugOverlay>,
+    root_query: Query<Entity, With<PortalDebugOverlayRoot>>,
+    portal_query: Query<&PortalImage, With<Portal>>,
+    changed_query: Query<(), Changed<PortalImage>>,
+    mut removed_images: RemovedComponents<PortalImage>,
+    mut image_events: EventReader<AssetEvent<Image>>,
+    images: Res<Assets<Image>>,
+) {
+    // Images are resized along with the window, which changes their aspect ratio
+    let resized = image_events.read().any(|event| {
+        let AssetEvent::Modified { id } = event else {
+            return false;
+        };
+        portal_query
+            .iter()
+            .any(|portal_image| portal_image.image.id() == *id)
+    });
+    let removed = removed_images.read().count() > 0;
+    if !overlay.is_changed() && changed_query.is_empty() && !removed && !resized {
+        return;
+    }
+
+    for root in &root_query {
+        commands.entity(root).despawn_recursive();
+    }
+    if !overlay.enabled {
+        return;
+    }
+
+    commands
+        .spawn((
+            Name::new("Portal Debug Overlay"),
+            PortalDebugOverlayRoot,
+            Node {
+                position_type: PositionType::Absolute,
+                right: Val::Px(8.0),
+                bottom: Val::Px(8.0),
+                max_width: Val::Percent(100.0),
+                flex_wrap: FlexWrap::WrapReverse,
+                justify_content: JustifyContent::FlexEnd,
+                column_gap: Val::Px(8.0),
+                row_gap: Val::Px(8.0),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            for portal_image in &portal_query {
+                let Some(image) = images.get(&portal_image.image) else {
+                    continue;
+                };
+                // Only show the part of the image the portal is rendered to
+                let size = image.size_f32();
+                let uv_rect = portal_image.uv_rect;
+                let rect =
+                    Rect::from_corners(uv_rect.xy() * size, (uv_rect.xy() + uv_rect.zw()) * size);
+                parent.spawn((
+                    ImageNode {
+                        rect: Some(rect),
+                        ..ImageNode::new(portal_image.image.clone())
+                    },
+                    Node {
+                        width: Val::Px(overlay.width),
+                        aspect_ratio: Some(rect.width() / rect.height().max(1.0)),
+                        border: UiRect::all(Val::Px(1.0)),
+                        ..default()
+                    },
+                    BorderColor(Color::WHITE),
+                ));
+            }
+        });
+}
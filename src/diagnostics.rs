@@ -0,0 +1,83 @@
+//! [`Diagnostic`]s measuring the cost of [`Portal`]s.
+//!
+//! Add the [`PortalDiagnosticsPlugin`] to record these diagnostics in the [`DiagnosticsStore`],
+//! where they can be shown by e.g. the [`LogDiagnosticsPlugin`].
+//!
+//! [`DiagnosticsStore`]: bevy::diagnostic::DiagnosticsStore
+//! [`LogDiagnosticsPlugin`]: bevy::diagnostic::LogDiagnosticsPlugin
+
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic},
+    image::{TextureFormatPixelInfo, Volume},
+    prelude::*,
+    utils::HashSet,
+};
+
+use crate::{Portal, PortalCamera, PortalDisabled, PortalImage};
+
+/// Plugin that records [`Diagnostic`]s for [`Portal`]s.
+pub struct PortalDiagnosticsPlugin;
+
+impl PortalDiagnosticsPlugin {
+    /// The number of [`Portal`]s, including the views spawned for secondary cameras.
+    pub const PORTAL_COUNT: DiagnosticPath = DiagnosticPath::const_new("portal/count");
+    /// The number of [`PortalCamera`]s that are active, i.e. rendering this frame.
+    pub const ACTIVE_CAMERAS: DiagnosticPath = DiagnosticPath::const_new("portal/active_cameras");
+    /// The number of [`Portal`]s with a [`PortalDisabled`] component.
+    pub const DISABLED_PORTALS: DiagnosticPath =
+        DiagnosticPath::const_new("portal/disabled_portals");
+    /// The memory used by the images of all [`Portal`]s, in mebibytes.
+    pub const TEXTURE_MEMORY: DiagnosticPath = DiagnosticPath::const_new("portal/texture_memory");
+}
+
+impl Plugin for PortalDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_diagnostic(Diagnostic::new(Self::PORTAL_COUNT))
+            .register_diagnostic(Diagnostic::new(Self::ACTIVE_CAMERAS))
+            .register_diagnostic(Diagnostic::new(Self::DISABLED_PORTALS))
+            .register_diagnostic(Diagnostic::new(Self::TEXTURE_MEMORY).with_suffix(" MiB"))
+            .add_systems(Last, measure_portal_diagnostics);
+    }
+}
+
+/// System that records the [`PortalDiagnosticsPlugin`] diagnostics.
+fn measure_portal_diagnostics(
+    mut diagnostics: Diagnostics,
+    portal_query: Query<(Option<&PortalImage>, Has<PortalDisabled>), With<Portal>>,
+    camera_query: Query<&Camera, With<PortalCamera>>,
+    images: Res<Assets<Image>>,
+) {
+    diagnostics.add_measurement(&PortalDiagnosticsPlugin::PORTAL_COUNT, || {
+        portal_query.iter().count() as f64
+    });
+    diagnostics.add_measurement(&PortalDiagnosticsPlugin::ACTIVE_CAMERAS, || {
+        camera_query
+            .iter()
+            .filter(|camera| camera.is_active)
+            .count() as f64
+    });
+    diagnostics.add_measurement(&PortalDiagnosticsPlugin::DISABLED_PORTALS, || {
+        portal_query
+            .iter()
+            .filter(|(_, disabled)| *disabled)
+            .count() as f64
+    });
+    diagnostics.add_measurement(&PortalDiagnosticsPlugin::TEXTURE_MEMORY, || {
+        // Images may be shared, e.g. by portals in the atlas
+        let image_ids: HashSet<AssetId<Image>> = portal_query
+            .iter()
+            .filter_map(|(portal_image, _)| {
+                portal_image.map(|portal_image| portal_image.image.id())
+            })
+            .collect();
+        let bytes: usize = image_ids
+            .into_iter()
+            .filter_map(|id| images.get(id))
+            .map(|image| {
+                let descriptor = &image.texture_descriptor;
+                descriptor.size.volume() * descriptor.format.pixel_size()
+            })
+            .sum();
+        bytes as f64 / (1024.0 * 1024.0)
+    });
+}
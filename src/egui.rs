@@ -0,0 +1,117 @@
+//! `bevy_egui` interaction through portals.
+//!
+//! Add the [`PortalEguiPlugin`] along with the [`PortalPickingPlugin`] to forward pointer inputs
+//! sent through portals to egui contexts rendered to images, e.g. in-world computer screens seen
+//! through a portal. The entity with the [`EguiRenderToTextureHandle`] needs to be picked via a
+//! backend, like any other entity on the far side of a portal.
+//!
+//! # Notes
+//!
+//! * The position on the egui context is computed from the picked entity's [`Aabb`], so only flat
+//!   meshes like [`Rectangle`]s, with the image covering the whole mesh, are supported.
+//!
+//! [`PortalPickingPlugin`]: crate::picking::PortalPickingPlugin
+
+use bevy::{
+    picking::{
+        backend::HitData,
+        pointer::{PointerButton, PointerId},
+        PickSet,
+    },
+    prelude::*,
+    render::primitives::Aabb,
+};
+use bevy_egui::{egui, EguiContext, EguiInput, EguiRenderToTextureHandle};
+
+use crate::picking::PortalPointer;
+
+/// Forwards pointer inputs sent through [`Portal`](crate::Portal)s to egui contexts rendered to
+/// images.
+pub struct PortalEguiPlugin;
+
+impl Plugin for PortalEguiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PreUpdate, forward_portal_egui_inputs.in_set(PickSet::Last));
+    }
+}
+
+/// System that forwards [`Pointer`] events of [`PortalPointer`]s on entities with an
+/// [`EguiRenderToTextureHandle`] to their egui context.
+fn forward_portal_egui_inputs(
+    portal_pointer_query: Query<&PointerId, With<PortalPointer>>,
+    mut move_events: EventReader<Pointer<Move>>,
+    mut down_events: EventReader<Pointer<Down>>,
+    mut up_events: EventReader<Pointer<Up>>,
+    mut context_query: Query<(
+        &mut EguiInput,
+        &mut EguiContext,
+        &EguiRenderToTextureHandle,
+        &GlobalTransform,
+        &Aabb,
+    )>,
+    images: Res<Assets<Image>>,
+) {
+    let mut forward = |target: Entity, hit: &HitData, button: Option<(PointerButton, bool)>| {
+        let Ok((mut input, mut context, render_to_image, global_transform, aabb)) =
+            context_query.get_mut(target)
+        else {
+            return;
+        };
+        let Some(position) = hit.position else {
+            return;
+        };
+        let Some(image) = images.get(&render_to_image.0) else {
+            return;
+        };
+
+        // Map the hit to the mesh's UVs, and then to the egui context's points
+        let local_position = global_transform
+            .affine()
+            .inverse()
+            .transform_point3(position);
+        let min = Vec3::from(aabb.min()).truncate();
+        let size = Vec3::from(aabb.half_extents).truncate() * 2.0;
+        let uv = (local_position.truncate() - min) / size.max(Vec2::splat(f32::EPSILON));
+        let uv = Vec2::new(uv.x, 1.0 - uv.y);
+        let points = uv * image.size_f32() / context.get_mut().pixels_per_point();
+        let pos = egui::pos2(points.x, points.y);
+
+        input.0.events.push(egui::Event::PointerMoved(pos));
+        if let Some((button, pressed)) = button {
+            input.0.events.push(egui::Event::PointerButton {
+                pos,
+                button: match button {
+                    PointerButton::Primary => egui::PointerButton::Primary,
+                    PointerButton::Secondary => egui::PointerButton::Secondary,
+                    PointerButton::Middle => egui::PointerButton::Middle,
+                },
+                pressed,
+                modifiers: egui::Modifiers::default(),
+            });
+        }
+    };
+
+    let is_portal_pointer = |pointer_id: &PointerId| {
+        portal_pointer_query
+            .iter()
+            .any(|portal_pointer_id| portal_pointer_id == pointer_id)
+    };
+    for event in move_events
+        .read()
+        .filter(|event| is_portal_pointer(&event.pointer_id))
+    {
+        forward(event.target, &event.hit, None);
+    }
+    for event in down_events
+        .read()
+        .filter(|event| is_portal_pointer(&event.pointer_id))
+    {
+        forward(event.target, &event.hit, Some((event.button, true)));
+    }
+    for event in up_events
+        .read()
+        .filter(|event| is_portal_pointer(&event.pointer_id))
+    {
+        forward(event.target, &event.hit, Some((event.button, false)));
+    }
+}
@@ -0,0 +1,108 @@
+//! Extending [`PortalMaterial`] with custom shader logic.
+//!
+//! This works the same way as extending a [`StandardMaterial`]: implement [`MaterialExtension`]
+//! for a type, add [`PortalMaterialExtensionPlugin`] for it, and add a [`PortalMaterialExtension`]
+//! next to a [`Portal`]. The [`PortalMaterial`] inserted for the [`Portal`] is then replaced by an
+//! [`ExtendedPortalMaterial`], which keeps being updated like a [`PortalMaterial`] would.
+//!
+//! The extension's fragment shader can import the functions of the [`PortalMaterial`] shader from
+//! `bevy_easy_portals::portal`, and should place its own bindings at index `100` and above:
+//!
+//! ```wgsl
+//! #import bevy_pbr::forward_io::VertexOutput
+//! #import bevy_easy_portals::portal::{portal_edge_distance, portal_uv, portal_sample}
+//!
+//! @group(2) @binding(100) var<uniform> glow: vec4<f32>;
+//!
+//! @fragment
+//! fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+//!     let edge_distance = portal_edge_distance(mesh);
+//!     return portal_sample(portal_uv(mesh)) + glow * (1.0 - edge_distance);
+//! }
+//! ```
+//!
+//! # Notes
+//!
+//! * Recursion proxies keep using a plain [`PortalMaterial`].
+
+use std::{hash::Hash, marker::PhantomData};
+
+use bevy::{
+    pbr::{ExtendedMaterial, MaterialExtension},
+    prelude::*,
+    render::render_resource::AsBindGroup,
+};
+
+use crate::{add_portal_material_systems, AsPortalMaterial, Portal, PortalMaterial};
+
+/// A [`PortalMaterial`] extended by `E`.
+pub type ExtendedPortalMaterial<E> = ExtendedMaterial<PortalMaterial, E>;
+
+impl<E: MaterialExtension> AsPortalMaterial for ExtendedPortalMaterial<E> {
+    fn portal_material(&self) -> &PortalMaterial {
+        &self.base
+    }
+
+    fn portal_material_mut(&mut self) -> &mut PortalMaterial {
+        &mut self.base
+    }
+}
+
+/// Plugin that renders [`Portal`]s with a [`PortalMaterialExtension<E>`] using an
+/// [`ExtendedPortalMaterial<E>`].
+pub struct PortalMaterialExtensionPlugin<E>(PhantomData<E>);
+
+impl<E> Default for PortalMaterialExtensionPlugin<E> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<E: MaterialExtension> Plugin for PortalMaterialExtensionPlugin<E>
+where
+    ExtendedPortalMaterial<E>: Material,
+    <ExtendedPortalMaterial<E> as AsBindGroup>::Data: PartialEq + Eq + Hash + Clone,
+{
+    fn build(&self, app: &mut App) {
+        app.add_plugins(MaterialPlugin::<ExtendedPortalMaterial<E>>::default())
+            .add_observer(extend_portal_material::<E>);
+
+        add_portal_material_systems::<ExtendedPortalMaterial<E>>(app);
+    }
+}
+
+/// Component used to extend the [`PortalMaterial`] of a [`Portal`] by `E`.
+///
+/// This requires the [`PortalMaterialExtensionPlugin<E>`].
+#[derive(Component, Debug, Clone)]
+pub struct PortalMaterialExtension<E: MaterialExtension>(pub E);
+
+/// Observer that replaces the [`PortalMaterial`] of a [`Portal`] with a
+/// [`PortalMaterialExtension<E>`] by an [`ExtendedPortalMaterial<E>`].
+fn extend_portal_material<E: MaterialExtension>(
+    trigger: Trigger<OnAdd, MeshMaterial3d<PortalMaterial>>,
+    mut commands: Commands,
+    portal_query: Query<
+        (&MeshMaterial3d<PortalMaterial>, &PortalMaterialExtension<E>),
+        With<Portal>,
+    >,
+    portal_materials: Res<Assets<PortalMaterial>>,
+    mut extended_materials: ResMut<Assets<ExtendedPortalMaterial<E>>>,
+) {
+    let entity = trigger.entity();
+    let Ok((portal_material_handle, extension)) = portal_query.get(entity) else {
+        return;
+    };
+    let Some(portal_material) = portal_materials.get(portal_material_handle) else {
+        return;
+    };
+
+    let extended_material = extended_materials.add(ExtendedMaterial {
+        base: portal_material.clone(),
+        extension: extension.0.clone(),
+    });
+    commands
+        .entity(entity)
+        .remove::<MeshMaterial3d<PortalMaterial>>()
+        .insert(MeshMaterial3d(extended_material));
+}
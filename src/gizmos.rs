@@ -1,51 +1,295 @@
 //! Gizmos for [`Portal`] debugging.
 
-use bevy::{color::palettes::tailwind::ORANGE_600, prelude::*, render::primitives::Aabb};
+use bevy::{
+    color::palettes::tailwind::ORANGE_600,
+    prelude::*,
+    render::primitives::{Aabb, Frustum},
+};
 
-use crate::Portal;
+use crate::{Portal, PortalCamera};
 
-#[derive(Reflect, Default, GizmoConfigGroup)]
-pub struct PortalGizmos;
+/// The [`GizmoConfigGroup`] of the gizmos drawn by the [`PortalGizmosPlugin`].
+#[derive(Reflect, GizmoConfigGroup)]
+pub struct PortalGizmos {
+    /// Whether gizmos are drawn for [`Portal`]s without [`PortalGizmoSettings`].
+    ///
+    /// Defaults to `true`.
+    pub draw_all: bool,
+}
+
+impl Default for PortalGizmos {
+    fn default() -> Self {
+        Self { draw_all: true }
+    }
+}
+
+/// Component used to configure the gizmos drawn for a [`Portal`].
+///
+/// [`Portal`]s without this component have all gizmos drawn if [`PortalGizmos::draw_all`] is
+/// enabled, and none otherwise.
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component, Default)]
+pub struct PortalGizmoSettings {
+    /// The color of the portal's gizmos.
+    ///
+    /// If set to `None`, a color unique to the portal is used.
+    ///
+    /// Defaults to `None`.
+    pub color: Option<Color>,
+    /// Whether the [`Aabb`] of the portal's mesh is drawn.
+    ///
+    /// Defaults to `true`.
+    pub show_mesh: bool,
+    /// Whether an arrow is drawn at [`Portal::target`], pointing forward.
+    ///
+    /// Defaults to `true`.
+    pub show_target: bool,
+    /// Whether the [`Frustum`] of the [`PortalCamera`] is drawn.
+    ///
+    /// Defaults to `true`.
+    pub show_frustum: bool,
+    /// Whether a line from the portal to [`Portal::target`] is drawn, with axes at both ends.
+    ///
+    /// Defaults to `true`.
+    pub show_link: bool,
+    /// Whether the plane the [`PortalCamera`] clips geometry at is drawn.
+    ///
+    /// Defaults to `true`.
+    pub show_clip_plane: bool,
+}
+
+impl Default for PortalGizmoSettings {
+    fn default() -> Self {
+        Self {
+            color: None,
+            show_mesh: true,
+            show_target: true,
+            show_frustum: true,
+            show_link: true,
+            show_clip_plane: true,
+        }
+    }
+}
+
+impl PortalGizmoSettings {
+    /// Returns the settings used for a [`Portal`] with the given `settings`, or `None` if no
+    /// gizmos should be drawn for it.
+    fn resolve(config: &PortalGizmos, settings: Option<&Self>) -> Option<Self> {
+        match settings {
+            Some(settings) => Some(*settings),
+            None => config.draw_all.then(Self::default),
+        }
+    }
+}
 
 /// Gizmo plugin for [`Portal`]s.
 ///
-/// These gizmos help visualize aspects like [`Portal`] meshes and where the
-/// [`Portal::target_transform`] is located (along with its facing direction).
+/// These gizmos help visualize aspects like [`Portal`] meshes, where the
+/// [`Portal::target_transform`] is located (along with its facing direction), and the [`Frustum`]
+/// of each [`PortalCamera`]. Each [`Portal`] is linked to its target by a line, with axes at both
+/// ends showing the orientation the transform math uses (red, green and blue for the local x, y
+/// and z-axes, where the negative z-axis is forward). The plane each [`PortalCamera`] clips
+/// geometry at is shown as a translucent grid at the target.
 pub struct PortalGizmosPlugin;
 
 impl Plugin for PortalGizmosPlugin {
     fn build(&self, app: &mut App) {
         app.init_gizmo_group::<PortalGizmos>()
-            .add_systems(Update, (debug_portal_meshes, debug_portal_cameras));
+            .register_type::<PortalGizmoSettings>()
+            .add_systems(
+                Update,
+                (
+                    debug_portal_meshes,
+                    debug_portal_cameras,
+                    debug_portal_frusta,
+                    debug_portal_links,
+                    debug_portal_clip_planes,
+                ),
+            );
     }
 }
 
 /// System that renders the [`Aabb`]s of a [`Portal`]'s mesh.
 fn debug_portal_meshes(
     mut gizmos: Gizmos<PortalGizmos>,
-    portal_query: Query<(&Transform, &Aabb), With<Portal>>,
+    portal_query: Query<(&Transform, &Aabb, Option<&PortalGizmoSettings>), With<Portal>>,
 ) {
-    for (&transform, aabb) in &portal_query {
+    for (&transform, aabb, settings) in &portal_query {
+        let Some(settings) = PortalGizmoSettings::resolve(gizmos.config_ext, settings) else {
+            continue;
+        };
+        if !settings.show_mesh {
+            continue;
+        }
         let transform = Transform {
             scale: (aabb.half_extents * 2.0).into(),
             ..transform
         };
-        gizmos.cuboid(transform, ORANGE_600);
+        gizmos.cuboid(transform, settings.color.unwrap_or(ORANGE_600.into()));
     }
 }
+
 /// System that renders arrows indicating the translation and rotation of [`PortalCamera`]s.
 fn debug_portal_cameras(
     mut gizmos: Gizmos<PortalGizmos>,
-    portal_query: Query<&Portal>,
+    portal_query: Query<(&Portal, Option<&PortalGizmoSettings>)>,
     global_transform_query: Query<&GlobalTransform>,
 ) {
-    for portal in &portal_query {
-        let transform = global_transform_query
+    for (portal, settings) in &portal_query {
+        let Some(settings) = PortalGizmoSettings::resolve(gizmos.config_ext, settings) else {
+            continue;
+        };
+        if !settings.show_target {
+            continue;
+        }
+        let Ok(transform) = global_transform_query
             .get(portal.target)
             .map(GlobalTransform::compute_transform)
-            .expect("target should have GlobalTransform");
+        else {
+            continue;
+        };
         let start = transform.translation;
         let end = start + transform.forward() * 0.5;
-        gizmos.arrow(start, end, ORANGE_600);
+        gizmos.arrow(start, end, settings.color.unwrap_or(ORANGE_600.into()));
+    }
+}
+
+/// System that renders the [`Frustum`] of each [`PortalCamera`], in a color unique to its
+/// [`Portal`].
+///
+/// This includes the planes fitted to the portal's mesh, and the near plane clipping everything in
+/// front of [`Portal::target`].
+fn debug_portal_frusta(
+    mut gizmos: Gizmos<PortalGizmos>,
+    portal_camera_query: Query<(&PortalCamera, &Frustum)>,
+    settings_query: Query<Option<&PortalGizmoSettings>, With<Portal>>,
+) {
+    for (&PortalCamera(portal), frustum) in &portal_camera_query {
+        let Ok(settings) = settings_query.get(portal) else {
+            continue;
+        };
+        let Some(settings) = PortalGizmoSettings::resolve(gizmos.config_ext, settings) else {
+            continue;
+        };
+        if !settings.show_frustum {
+            continue;
+        }
+        let color = settings.color.unwrap_or_else(|| portal_color(portal));
+
+        // The half spaces are ordered left, right, bottom, top, near, far
+        let corner = |x: usize, y: usize, z: usize| frustum_corner(frustum, [x, 2 + y, 4 + z]);
+        let corners = [0, 1].map(|x| [0, 1].map(|y| [0, 1].map(|z| corner(x, y, z))));
+
+        let mut line = |a: Option<Vec3>, b: Option<Vec3>| {
+            if let (Some(a), Some(b)) = (a, b) {
+                gizmos.line(a, b, color);
+            }
+        };
+        for (i, j) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            line(corners[i][j][0], corners[i][j][1]);
+            line(corners[i][0][j], corners[i][1][j]);
+            line(corners[0][i][j], corners[1][i][j]);
+        }
+    }
+}
+
+/// System that renders a line from each [`Portal`] to its [`Portal::target`], in a color unique to
+/// the [`Portal`], along with the axes of both.
+fn debug_portal_links(
+    mut gizmos: Gizmos<PortalGizmos>,
+    portal_query: Query<(
+        Entity,
+        &Portal,
+        &GlobalTransform,
+        Option<&PortalGizmoSettings>,
+    )>,
+    global_transform_query: Query<&GlobalTransform>,
+) {
+    for (entity, portal, portal_global_transform, settings) in &portal_query {
+        let Some(settings) = PortalGizmoSettings::resolve(gizmos.config_ext, settings) else {
+            continue;
+        };
+        if !settings.show_link {
+            continue;
+        }
+        let Ok(target_global_transform) = global_transform_query.get(portal.target) else {
+            continue;
+        };
+        let portal_transform = portal_global_transform.compute_transform();
+        let target_transform = target_global_transform.compute_transform();
+
+        gizmos.line(
+            portal_transform.translation,
+            target_transform.translation,
+            settings.color.unwrap_or_else(|| portal_color(entity)),
+        );
+        gizmos.axes(portal_transform, 0.25);
+        gizmos.axes(target_transform, 0.25);
+    }
+}
+
+/// System that renders the plane each [`PortalCamera`] clips geometry at, as a grid the size of
+/// the [`Portal`]'s mesh.
+///
+/// See [`Portal::clip_offset`].
+fn debug_portal_clip_planes(
+    mut gizmos: Gizmos<PortalGizmos>,
+    portal_camera_query: Query<(&PortalCamera, &GlobalTransform)>,
+    portal_query: Query<(
+        &Portal,
+        &GlobalTransform,
+        &Aabb,
+        Option<&PortalGizmoSettings>,
+    )>,
+    global_transform_query: Query<&GlobalTransform>,
+) {
+    for (&PortalCamera(entity), camera_global_transform) in &portal_camera_query {
+        let Ok((portal, portal_global_transform, aabb, settings)) = portal_query.get(entity) else {
+            continue;
+        };
+        let Some(settings) = PortalGizmoSettings::resolve(gizmos.config_ext, settings) else {
+            continue;
+        };
+        if !settings.show_clip_plane {
+            continue;
+        }
+        let Ok(target_global_transform) = global_transform_query.get(portal.target) else {
+            continue;
+        };
+        let target_transform = target_global_transform.compute_transform();
+
+        // The plane is moved from the target towards the camera by the clip offset
+        let forward = target_transform.forward();
+        let side = forward
+            .dot(camera_global_transform.translation() - target_transform.translation)
+            .signum();
+        let translation = target_transform.translation + forward * side * portal.clip_offset;
+
+        let size = Vec3::from(aabb.half_extents).truncate()
+            * 2.0
+            * portal_global_transform.compute_transform().scale.truncate();
+        let isometry = Isometry3d::new(translation, target_transform.rotation);
+        let color = settings
+            .color
+            .unwrap_or_else(|| portal_color(entity))
+            .with_alpha(0.3);
+        gizmos.rect(isometry, size, color);
+        gizmos.grid(isometry, UVec2::splat(8), size / 8.0, color);
+    }
+}
+
+/// Returns the color used for the gizmos of a given `portal`.
+fn portal_color(portal: Entity) -> Color {
+    // Spread the hues of consecutive portals by the golden angle
+    Color::hsl((portal.index() as f32 * 137.507_77) % 360.0, 0.8, 0.6)
+}
+
+/// Returns the point where the three half spaces of `frustum` at `indices` intersect, if any.
+fn frustum_corner(frustum: &Frustum, indices: [usize; 3]) -> Option<Vec3> {
+    let [a, b, c] = indices.map(|index| frustum.half_spaces[index].normal_d());
+    let normals = Mat3::from_cols(a.truncate(), b.truncate(), c.truncate()).transpose();
+    if normals.determinant().abs() < f32::EPSILON {
+        return None;
     }
+    Some(normals.inverse() * -Vec3::new(a.w, b.w, c.w))
 }
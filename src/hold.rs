@@ -0,0 +1,121 @@
+//! Carrying entities through [`Portal`]s.
+//!
+//! Add the [`PortalHoldPlugin`], then [`PortalHeld`] to an entity carried by another, e.g. an
+//! object picked up by the player. The entity is kept at an offset from its holder, and whenever
+//! the line from the holder to that offset passes through a [`Portal`], the entity is placed on the
+//! far side of it instead. This way, pushing a carried object through a portal moves it to the
+//! other side, and pulling it back returns it.
+//!
+//! Add [`PortalTraveler`] as well to show a clone of the entity while it straddles a portal.
+//!
+//! # Notes
+//!
+//! * Neither the holder nor the held entity should have a parent, since their [`Transform`] is
+//!   treated as global.
+//!
+//! [`Portal`]: crate::Portal
+//! [`PortalTraveler`]: crate::teleport::PortalTraveler
+
+use bevy::prelude::*;
+
+use crate::{raycast::PortalRayCast, teleport::PortalTeleportSystems};
+
+/// Plugin that moves [`PortalHeld`] entities along with their holder, through portals.
+pub struct PortalHoldPlugin;
+
+impl Plugin for PortalHoldPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            update_held_entities
+                .after(PortalTeleportSystems)
+                .before(TransformSystem::TransformPropagate),
+        )
+        .register_type::<(PortalHeld, PortalHeldGhost)>();
+    }
+}
+
+/// Component used to carry an entity at an offset from a holder, through
+/// [`Portal`](crate::Portal)s.
+///
+/// This requires the [`PortalHoldPlugin`].
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component)]
+#[require(Transform, PortalHeldGhost)]
+pub struct PortalHeld {
+    /// The entity carrying this entity, e.g. the player.
+    ///
+    /// This entity should contain a [`Transform`] component.
+    pub holder: Entity,
+    /// The [`Transform`] of this entity relative to the holder.
+    pub offset: Transform,
+    /// The number of portals the line from the holder to this entity can pass through.
+    pub max_depth: usize,
+}
+
+impl PortalHeld {
+    /// Creates a new [`PortalHeld`] carried by `holder` at a given `offset`, through a single
+    /// portal at most.
+    #[inline]
+    #[must_use]
+    pub fn new(holder: Entity, offset: Transform) -> Self {
+        Self {
+            holder,
+            offset,
+            max_depth: 1,
+        }
+    }
+}
+
+/// Where a [`PortalHeld`] entity would be without portals, i.e. on the holder's side.
+///
+/// While a portal is between the holder and the held entity, the entity's [`Transform`] and
+/// this ghost are on different sides of it: the entity is shown where the holder sees it through
+/// the portal, and the ghost is where it is held. The two swap as the entity crosses the portal.
+#[derive(Component, Reflect, Debug, Default, Clone)]
+#[reflect(Component, Default)]
+pub struct PortalHeldGhost {
+    /// The [`Transform`] of the held entity on the holder's side.
+    pub transform: Transform,
+    /// The [`Portal`](crate::Portal) entities between the holder and the held entity, in order.
+    pub portals: Vec<Entity>,
+}
+
+/// System that moves each [`PortalHeld`] entity to its offset from the holder, through any
+/// portals in between.
+fn update_held_entities(
+    ray_cast: PortalRayCast,
+    holder_query: Query<&Transform, Without<PortalHeld>>,
+    mut held_query: Query<(&PortalHeld, &mut Transform, &mut PortalHeldGhost)>,
+) {
+    for (held, mut transform, mut ghost) in &mut held_query {
+        let Ok(holder_transform) = holder_query.get(held.holder) else {
+            continue;
+        };
+        let hold_transform = holder_transform.mul_transform(held.offset);
+
+        ghost.transform = hold_transform;
+        ghost.portals.clear();
+
+        // Follow the line from the holder to the held entity through portals
+        let mut through_portals = hold_transform;
+        let line = hold_transform.translation - holder_transform.translation;
+        if let Ok(direction) = Dir3::new(line) {
+            let ray = Ray3d::new(holder_transform.translation, direction);
+            for segment in ray_cast.cast_ray(ray, line.length(), held.max_depth) {
+                let Some(portal) = segment.portal else {
+                    continue;
+                };
+                let Some(transform) = ray_cast.transform_through(portal, through_portals) else {
+                    break;
+                };
+                through_portals = transform;
+                ghost.portals.push(portal);
+            }
+        }
+
+        if *transform != through_portals {
+            *transform = through_portals;
+        }
+    }
+}
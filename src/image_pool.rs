@@ -0,0 +1,444 @@
+//! Allocation of the images [`Portal`]s are rendered to.
+//!
+//! Each [`PortalCamera`] renders to an image handed out by the [`PortalImagePool`], sized after
+//! the viewport of its primary camera. Images are resized along with the viewport, and reclaimed
+//! when their [`Portal`] is despawned.
+
+use bevy::{
+    image::{ImageSampler, TextureFormatPixelInfo, Volume},
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        render_resource::{
+            Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+        },
+    },
+    utils::{HashMap, HashSet},
+    window::{Monitor, PrimaryWindow, WindowResized},
+};
+
+use crate::{
+    atlas, camera_window, update_portal_resolution_lods, LinkedPortalCamera, Portal, PortalCamera,
+    PortalCameraShares, PortalCameraSystems, PortalImage, ViewportSize,
+};
+
+/// Plugin that sets up the [`PortalImagePool`] and keeps its images sized to their viewports.
+///
+/// This is added by [`PortalPlugin`](crate::PortalPlugin), and shouldn't be added manually.
+pub(crate) struct PortalImagePoolPlugin {
+    pub(crate) resize_delay_frames: u32,
+    pub(crate) image_allocation: PortalImageAllocation,
+}
+
+impl Plugin for PortalImagePoolPlugin {
+    fn build(&self, app: &mut App) {
+        init_image_pool(app);
+        app.insert_resource(PendingImageResizes {
+            delay_frames: self.resize_delay_frames,
+            ..default()
+        })
+        .add_systems(
+            PreUpdate,
+            (
+                resize_portal_images
+                    .before(update_portal_resolution_lods)
+                    .before(apply_pending_image_resizes),
+                update_portal_image_uv_rects
+                    .after(update_portal_resolution_lods)
+                    .after(apply_pending_image_resizes),
+            )
+                .in_set(PortalCameraSystems::ResizeImage),
+        );
+
+        if self.image_allocation == PortalImageAllocation::MaxMonitorSize {
+            app.add_systems(
+                PreUpdate,
+                update_portal_image_allocation_size.before(PortalCameraSystems::ResizeImage),
+            );
+        }
+    }
+}
+
+/// Sets up the [`PortalImagePool`] and the [`PendingImageResizes`], unless `PortalPlugin` or
+/// `Portal2dPlugin` already did.
+pub(crate) fn init_image_pool(app: &mut App) {
+    if app.world().contains_resource::<PortalImagePool>() {
+        return;
+    }
+    app.init_resource::<PortalImagePool>()
+        .init_resource::<PendingImageResizes>()
+        .add_systems(
+            PreUpdate,
+            apply_pending_image_resizes.in_set(PortalCameraSystems::ResizeImage),
+        );
+}
+
+/// System that resizes the image of each [`PortalCamera`] to the viewport size of its primary
+/// camera.
+///
+/// This runs if any [`WindowResized`] events are read for the window the primary camera renders
+/// to, or if the primary camera's [`Camera`] changed, e.g. when its [`Viewport`] is set at runtime.
+/// Images are only resized once their size has settled, see [`PendingImageResizes`].
+fn resize_portal_images(
+    mut resized_reader: EventReader<WindowResized>,
+    primary_window_query: Query<Entity, With<PrimaryWindow>>,
+    portal_query: Query<(&Portal, &LinkedPortalCamera), Without<atlas::PortalAtlasCell>>,
+    primary_camera_query: Query<Ref<Camera>, Without<PortalCamera>>,
+    camera_query: Query<&Camera, With<PortalCamera>>,
+    mut image_pool: ResMut<PortalImagePool>,
+    mut pending_resizes: ResMut<PendingImageResizes>,
+    viewport_size: ViewportSize,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let resized_windows = resized_reader
+        .read()
+        .map(|event| event.window)
+        .collect::<HashSet<_>>();
+    let primary_window = primary_window_query.get_single().ok();
+
+    for (portal, linked_camera) in &portal_query {
+        let Ok(primary_camera) = primary_camera_query.get(portal.primary_camera) else {
+            continue;
+        };
+        let window_resized = camera_window(&primary_camera, primary_window)
+            .is_some_and(|window| resized_windows.contains(&window));
+        if !window_resized && !primary_camera.is_changed() {
+            continue;
+        }
+        let Some(size) = viewport_size.get_viewport_size(&primary_camera) else {
+            continue;
+        };
+        let Ok(Camera {
+            target: RenderTarget::Image(image_handle),
+            ..
+        }) = camera_query.get(linked_camera.get())
+        else {
+            continue;
+        };
+
+        pending_resizes.resize_image(&mut image_pool, &mut images, image_handle, size);
+    }
+}
+
+/// System that updates the [`PortalImage::uv_rect`] of each [`Portal`] to the area of its image
+/// that is rendered to, see [`PortalImagePool::used_size`].
+fn update_portal_image_uv_rects(
+    mut portal_query: Query<
+        (Entity, Option<&LinkedPortalCamera>, &mut PortalImage),
+        (With<Portal>, Without<atlas::PortalAtlasCell>),
+    >,
+    camera_query: Query<&Camera, With<PortalCamera>>,
+    camera_shares: Res<PortalCameraShares>,
+    image_pool: Res<PortalImagePool>,
+    images: Res<Assets<Image>>,
+) {
+    for (entity, linked_camera, mut portal_image) in &mut portal_query {
+        let Some(Camera {
+            target: RenderTarget::Image(image_handle),
+            ..
+        }) = linked_camera
+            .map(LinkedPortalCamera::get)
+            .or_else(|| camera_shares.camera(entity))
+            .and_then(|camera| camera_query.get(camera).ok())
+        else {
+            continue;
+        };
+        let (Some(used_size), Some(image)) =
+            (image_pool.used_size(image_handle), images.get(image_handle))
+        else {
+            continue;
+        };
+
+        let uv_size = (used_size.as_vec2() / image.size_f32()).min(Vec2::ONE);
+        let uv_rect = Vec4::new(0.0, 0.0, uv_size.x, uv_size.y);
+        if portal_image.uv_rect != uv_rect {
+            portal_image.uv_rect = uv_rect;
+        }
+    }
+}
+
+/// System that resizes the images whose size has settled, see [`PendingImageResizes`].
+pub(crate) fn apply_pending_image_resizes(
+    mut image_pool: ResMut<PortalImagePool>,
+    mut pending_resizes: ResMut<PendingImageResizes>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let delay_frames = pending_resizes.delay_frames;
+    pending_resizes
+        .pending
+        .retain(|_, (image_handle, size, stable_frames)| {
+            if *stable_frames < delay_frames {
+                *stable_frames += 1;
+                return true;
+            }
+            image_pool.resize_image(&mut images, image_handle, *size);
+            false
+        });
+}
+
+/// Resource keeping track of the images waiting for their size to settle before being resized,
+/// see [`PortalPlugin::resize_delay_frames`](crate::PortalPlugin::resize_delay_frames).
+#[derive(Resource, Default)]
+pub(crate) struct PendingImageResizes {
+    delay_frames: u32,
+    /// The image, the size it is resized to, and the number of frames that size has been stable.
+    pending: HashMap<AssetId<Image>, (Handle<Image>, Extent3d, u32)>,
+}
+
+impl PendingImageResizes {
+    /// Resizes an image handed out by the [`PortalImagePool`] to a given viewport `size`, once
+    /// that size has stayed the same for the delay.
+    pub(crate) fn resize_image(
+        &mut self,
+        image_pool: &mut PortalImagePool,
+        images: &mut Assets<Image>,
+        handle: &Handle<Image>,
+        size: Extent3d,
+    ) {
+        if self.delay_frames == 0 {
+            image_pool.resize_image(images, handle, size);
+            return;
+        }
+        let (_, pending_size, stable_frames) = self
+            .pending
+            .entry(handle.id())
+            .or_insert_with(|| (handle.clone(), size, 0));
+        if *pending_size != size {
+            *pending_size = size;
+            *stable_frames = 0;
+        }
+    }
+}
+
+/// Resource that hands out and reclaims the images [`PortalCamera`]s render to.
+///
+/// Images of despawned [`Portal`]s are kept around, so that spawning [`Portal`]s at runtime
+/// doesn't continuously allocate and drop images.
+///
+/// With [`PortalImageAllocation::MaxMonitorSize`], images are allocated large enough for any
+/// monitor, and only the area given by [`PortalImagePool::used_size`] is rendered to.
+#[derive(Resource, Debug, Default)]
+pub struct PortalImagePool {
+    /// The images handed out, along with the scale of their size relative to the viewport size.
+    in_use: HashMap<AssetId<Image>, f32>,
+    /// The size of the area rendered to within each image handed out.
+    used_sizes: HashMap<AssetId<Image>, Extent3d>,
+    /// The images that can be handed out again.
+    available: Vec<Handle<Image>>,
+    /// The size images are allocated at least, if they are over-allocated.
+    min_allocation_size: Option<Extent3d>,
+}
+
+impl PortalImagePool {
+    /// Hands out an image of a given `format` and `sampler`, sized `size` scaled by `scale`.
+    ///
+    /// An available image of the same `format` is reused (and resized if needed), otherwise a new
+    /// image is created.
+    pub fn acquire(
+        &mut self,
+        images: &mut Assets<Image>,
+        size: Extent3d,
+        scale: f32,
+        format: TextureFormat,
+        sampler: &ImageSampler,
+    ) -> Handle<Image> {
+        let size = scale_image_size(size, scale);
+        let reusable = self.available.iter().position(|handle| {
+            images
+                .get(handle)
+                .is_some_and(|image| image.texture_descriptor.format == format)
+        });
+
+        let handle = match reusable {
+            Some(index) => {
+                let handle = self.available.swap_remove(index);
+                if let Some(image) = images.get_mut(&handle) {
+                    if !self.fits(image, size) {
+                        image.resize(self.allocation_size(size));
+                    }
+                    image.sampler = sampler.clone();
+                }
+                handle
+            }
+            None => images.add(Image {
+                sampler: sampler.clone(),
+                ..new_portal_image(self.allocation_size(size), format)
+            }),
+        };
+
+        self.in_use.insert(handle.id(), scale);
+        self.used_sizes.insert(handle.id(), size);
+        handle
+    }
+
+    /// Reclaims an image handed out by [`PortalImagePool::acquire`].
+    ///
+    /// The image shouldn't be rendered to anymore after it is released.
+    pub fn release(&mut self, handle: Handle<Image>) {
+        self.used_sizes.remove(&handle.id());
+        if self.in_use.remove(&handle.id()).is_some() {
+            self.available.push(handle);
+        }
+    }
+
+    /// Resizes an image in use to a given viewport `size`, scaled by its scale.
+    ///
+    /// Returns whether the image was resized. Over-allocated images are only resized if they are
+    /// too small, otherwise just their [`PortalImagePool::used_size`] changes.
+    pub fn resize_image(
+        &mut self,
+        images: &mut Assets<Image>,
+        handle: &Handle<Image>,
+        size: Extent3d,
+    ) -> bool {
+        let Some(&scale) = self.in_use.get(&handle.id()) else {
+            return false;
+        };
+        let size = scale_image_size(size, scale);
+        self.used_sizes.insert(handle.id(), size);
+        if images
+            .get(handle)
+            .is_none_or(|image| self.fits(image, size))
+        {
+            return false;
+        }
+        images.get_mut(handle).is_some_and(|image| {
+            image.resize(self.allocation_size(size));
+            true
+        })
+    }
+
+    /// Changes the scale of an image in use, and resizes it to a given viewport `size` scaled by
+    /// the new `scale`.
+    ///
+    /// Returns whether the image was resized.
+    pub fn rescale_image(
+        &mut self,
+        images: &mut Assets<Image>,
+        handle: &Handle<Image>,
+        size: Extent3d,
+        scale: f32,
+    ) -> bool {
+        let Some(in_use_scale) = self.in_use.get_mut(&handle.id()) else {
+            return false;
+        };
+        *in_use_scale = scale;
+        self.resize_image(images, handle, size)
+    }
+
+    /// Resizes all images in use to a given viewport `size`, scaled by their respective scale.
+    ///
+    /// Available images are resized once they are handed out again.
+    pub fn resize(&mut self, images: &mut Assets<Image>, size: Extent3d) {
+        let handles = self.in_use.keys().copied().collect::<Vec<_>>();
+        for id in handles {
+            let size = scale_image_size(size, self.in_use[&id]);
+            self.used_sizes.insert(id, size);
+            let Some(image) = images.get_mut(id) else {
+                continue;
+            };
+            if !self.fits(image, size) {
+                image.resize(self.allocation_size(size));
+            }
+        }
+    }
+
+    /// Drops the images that are not in use, i.e. those of despawned [`Portal`]s.
+    ///
+    /// The images are freed once no other strong handles to them remain.
+    pub fn drop_available(&mut self) {
+        self.available.clear();
+    }
+
+    /// Returns the size of the area rendered to within an image in use.
+    ///
+    /// This is smaller than the image itself if it is over-allocated, see
+    /// [`PortalImageAllocation::MaxMonitorSize`].
+    pub fn used_size(&self, handle: &Handle<Image>) -> Option<UVec2> {
+        self.used_sizes
+            .get(&handle.id())
+            .map(|size| UVec2::new(size.width, size.height))
+    }
+
+    /// Returns the size to allocate for an image rendered to at a given `size`.
+    fn allocation_size(&self, size: Extent3d) -> Extent3d {
+        self.min_allocation_size.map_or(size, |min_size| Extent3d {
+            width: size.width.max(min_size.width),
+            height: size.height.max(min_size.height),
+            ..size
+        })
+    }
+
+    /// Returns whether `image` can be rendered to at a given `size` without being resized.
+    fn fits(&self, image: &Image, size: Extent3d) -> bool {
+        let image_size = image.texture_descriptor.size;
+        match self.min_allocation_size {
+            Some(_) => image_size.width >= size.width && image_size.height >= size.height,
+            None => image_size == size,
+        }
+    }
+}
+
+/// How [`PortalImagePool`] allocates the images of [`Portal`]s.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PortalImageAllocation {
+    /// Images are allocated at the size they are rendered at, and reallocated whenever that size
+    /// changes, e.g. when the window is resized.
+    #[default]
+    Exact,
+    /// Images are allocated at the size of the largest monitor, and cameras render into the area
+    /// they need. Resizing the window then doesn't reallocate any images, at the cost of texture
+    /// memory.
+    MaxMonitorSize,
+}
+
+/// System that keeps the minimum allocation size of the [`PortalImagePool`] at the size of the
+/// largest monitor, for [`PortalImageAllocation::MaxMonitorSize`].
+fn update_portal_image_allocation_size(
+    monitor_query: Query<&Monitor>,
+    mut image_pool: ResMut<PortalImagePool>,
+) {
+    let max_size = monitor_query
+        .iter()
+        .map(|monitor| UVec2::new(monitor.physical_width, monitor.physical_height))
+        .reduce(UVec2::max);
+    let min_allocation_size = max_size.map(|max_size| Extent3d {
+        width: max_size.x,
+        height: max_size.y,
+        ..default()
+    });
+    if image_pool.min_allocation_size != min_allocation_size {
+        image_pool.min_allocation_size = min_allocation_size;
+    }
+}
+
+/// Scales a viewport `size` by `scale`, e.g. [`Portal::resolution_scale`].
+fn scale_image_size(size: Extent3d, scale: f32) -> Extent3d {
+    let scale = |length: u32| ((length as f32 * scale).round() as u32).max(1);
+    Extent3d {
+        width: scale(size.width),
+        height: scale(size.height),
+        ..size
+    }
+}
+
+/// Creates an image of a given `size` that a portal camera can render to.
+pub(crate) fn new_portal_image(size: Extent3d, format: TextureFormat) -> Image {
+    Image {
+        data: vec![0; size.volume() * format.pixel_size()],
+        texture_descriptor: TextureDescriptor {
+            label: None,
+            size,
+            dimension: TextureDimension::D2,
+            format,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        },
+        ..default()
+    }
+}
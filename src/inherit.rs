@@ -0,0 +1,97 @@
+//! Inheriting components of primary cameras on [`PortalCamera`]s.
+//!
+//! A [`PortalCamera`] is spawned with a copy of a fixed set of its primary camera's components,
+//! such as [`Camera`], [`Camera3d`] and [`Tonemapping`]. Other components, e.g. [`Bloom`],
+//! [`Skybox`] or those of third-party crates, can be copied as well by adding a
+//! [`PortalCameraInheritPlugin`] for them, or through [`PortalAppExt`]:
+//!
+//! ```no_run
+//! # use bevy::{core_pipeline::bloom::Bloom, prelude::*};
+//! use bevy_easy_portals::inherit::PortalAppExt;
+//!
+//! App::new().inherit_portal_camera_component::<Bloom>();
+//! ```
+//!
+//! Inherited components are kept in sync: they are copied again whenever they change on the
+//! primary camera, and removed from the [`PortalCamera`] when removed from the primary camera.
+//!
+//! [`Tonemapping`]: bevy::core_pipeline::tonemapping::Tonemapping
+//! [`Bloom`]: bevy::core_pipeline::bloom::Bloom
+//! [`Skybox`]: bevy::core_pipeline::Skybox
+
+use std::marker::PhantomData;
+
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::{Portal, PortalCamera};
+
+/// Plugin that copies the `C` component of primary cameras onto their [`PortalCamera`]s.
+///
+/// See the [module docs](self) for details.
+pub struct PortalCameraInheritPlugin<C>(PhantomData<C>);
+
+impl<C> Default for PortalCameraInheritPlugin<C> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<C: Component + Clone> Plugin for PortalCameraInheritPlugin<C> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PostUpdate, inherit_portal_camera_component::<C>);
+    }
+}
+
+/// Extension trait for [`App`] to configure [`PortalCamera`]s.
+pub trait PortalAppExt {
+    /// Copies the `C` component of primary cameras onto their [`PortalCamera`]s.
+    ///
+    /// This adds a [`PortalCameraInheritPlugin<C>`], unless it was added already.
+    fn inherit_portal_camera_component<C: Component + Clone>(&mut self) -> &mut Self;
+}
+
+impl PortalAppExt for App {
+    fn inherit_portal_camera_component<C: Component + Clone>(&mut self) -> &mut Self {
+        if !self.is_plugin_added::<PortalCameraInheritPlugin<C>>() {
+            self.add_plugins(PortalCameraInheritPlugin::<C>::default());
+        }
+        self
+    }
+}
+
+/// System that keeps the `C` component of [`PortalCamera`]s in sync with their primary cameras.
+fn inherit_portal_camera_component<C: Component + Clone>(
+    mut commands: Commands,
+    camera_query: Query<(Entity, Ref<PortalCamera>, Has<C>)>,
+    portal_query: Query<Ref<Portal>>,
+    primary_camera_query: Query<Ref<C>>,
+    mut removed: RemovedComponents<C>,
+) {
+    let removed: HashSet<Entity> = removed.read().collect();
+
+    for (entity, portal_camera, inherited) in &camera_query {
+        let Ok(portal) = portal_query.get(portal_camera.0) else {
+            continue;
+        };
+
+        match primary_camera_query.get(portal.primary_camera) {
+            Ok(component) => {
+                // Changes to the portal may have linked it to another primary camera
+                if !inherited
+                    || component.is_changed()
+                    || portal.is_changed()
+                    || portal_camera.is_added()
+                {
+                    commands.entity(entity).insert(C::clone(&component));
+                }
+            }
+            Err(_)
+                if inherited
+                    && (removed.contains(&portal.primary_camera) || portal.is_changed()) =>
+            {
+                commands.entity(entity).remove::<C>();
+            }
+            Err(_) => {}
+        }
+    }
+}
@@ -1,48 +1,207 @@
 #![doc = include_str!("../README.md")]
 
+pub mod atlas;
+#[cfg(feature = "audio")]
+pub mod audio;
+#[cfg(feature = "avian")]
+pub mod avian;
+pub mod clip;
+pub mod commands;
+#[cfg(feature = "debug_overlay")]
+pub mod debug_overlay;
+pub mod diagnostics;
+#[cfg(feature = "egui")]
+pub mod egui;
+pub mod extension;
 #[cfg(feature = "gizmos")]
 pub mod gizmos;
+pub mod hold;
+mod image_pool;
+pub mod inherit;
+pub mod mipmap;
+mod mirror;
+pub mod occlusion;
 #[cfg(feature = "picking")]
 pub mod picking;
+#[cfg(feature = "2d")]
+pub mod portal_2d;
+#[cfg(feature = "rapier")]
+pub mod rapier;
+pub mod raycast;
+pub mod readback;
+pub mod recursion;
+pub mod screenshot;
+mod settings;
+pub mod spectator;
+pub mod teleport;
+mod update_rate;
+pub mod validation;
 
+/// Re-exports of the most commonly used types.
+///
+/// ```
+/// use bevy_easy_portals::prelude::*;
+/// ```
+pub mod prelude {
+    #[cfg(feature = "gizmos")]
+    pub use crate::gizmos::PortalGizmosPlugin;
+    #[cfg(feature = "picking")]
+    pub use crate::picking::PortalPickingPlugin;
+    #[cfg(feature = "2d")]
+    pub use crate::portal_2d::{LinkedPortal2dCamera, Portal2d, Portal2dPlugin};
+    pub use crate::{
+        commands::PortalCommandsExt, inherit::PortalAppExt, LinkedPortalCamera, Mirror, Portal,
+        PortalCamera, PortalCameraSpawned, PortalCameraSystems, PortalDisabled, PortalError,
+        PortalErrorKind, PortalImage, PortalMaterial, PortalPlugin, PortalPluginSettings,
+        PortalPlugins, PortalSetupState, PortalSurfaceMapping, PortalTarget,
+    };
+}
+
+use std::{f32::consts::PI, ops::Range};
+
+use atlas::PortalAtlas;
 use bevy::{
+    app::PluginGroupBuilder,
     asset::load_internal_asset,
+    core::FrameCount,
     core_pipeline::tonemapping::{DebandDither, Tonemapping},
-    ecs::system::SystemParam,
-    image::{TextureFormatPixelInfo, Volume},
+    ecs::{component::ComponentId, entity::Entities, system::SystemParam, world::DeferredWorld},
+    image::ImageSampler,
+    math::Vec3A,
     pbr::{MaterialPipeline, MaterialPipelineKey},
     prelude::*,
     render::{
-        camera::{Exposure, RenderTarget},
+        camera::{
+            camera_system, CameraProjection, CameraUpdateSystem, Exposure, ManualTextureViews,
+            NormalizedRenderTarget, RenderTarget, SubCameraView, Viewport,
+        },
         mesh::MeshVertexBufferLayoutRef,
-        primitives::{Frustum, HalfSpace},
+        primitives::{Aabb, Frustum, HalfSpace},
         render_resource::{
             AsBindGroup, Extent3d, Face, RenderPipelineDescriptor, ShaderRef,
-            SpecializedMeshPipelineError, TextureDescriptor, TextureDimension, TextureFormat,
-            TextureUsages,
+            SpecializedMeshPipelineError, TextureFormat,
         },
-        view::{ColorGrading, VisibilitySystems},
+        view::{update_frusta, ColorGrading, RenderLayers, VisibilitySystems},
     },
-    window::{PrimaryWindow, WindowRef, WindowResized},
+    utils::{HashMap, HashSet},
+    window::{PrimaryWindow, WindowRef},
 };
+pub use image_pool::{PortalImageAllocation, PortalImagePool};
+use mirror::reflect_through_mirror;
+pub use mirror::Mirror;
+pub use settings::{PortalImageSize, PortalPluginSettings};
+use update_rate::{camera_image_memory, portal_viewport_coverage};
+pub use update_rate::{PortalBudget, PortalPriority, PortalUpdateRate};
 
 const PORTAL_SHADER_HANDLE: Handle<Shader> =
     Handle::weak_from_u128(115090128739399034051596692516865947112);
+const PORTAL_FUNCTIONS_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(203574190651182395034623810375614058231);
 
 /// A plugin that provides the required systems to make a [`Portal`] work.
-#[derive(Default)]
-pub struct PortalPlugin;
+pub struct PortalPlugin {
+    /// The number of frames the primary camera's viewport size has to stay the same before the
+    /// images of [`Portal`]s are resized to it.
+    ///
+    /// While a window is being resized interactively, its size changes every frame. Delaying the
+    /// resize avoids reallocating every portal image each frame, at the cost of portals looking
+    /// stretched until the size settles.
+    ///
+    /// Defaults to `0`, which resizes images right away.
+    pub resize_delay_frames: u32,
+    /// How the images of [`Portal`]s are allocated.
+    ///
+    /// Defaults to [`PortalImageAllocation::Exact`].
+    pub image_allocation: PortalImageAllocation,
+    /// Whether [`Portal`]s hidden behind other geometry are skipped, using occlusion queries. See
+    /// the [`occlusion`] module for details.
+    ///
+    /// Defaults to `false`.
+    pub occlusion_culling: bool,
+    /// The size of the images of [`Portal`]s.
+    ///
+    /// Defaults to [`PortalImageSize::Viewport`].
+    pub image_size: PortalImageSize,
+    /// The defaults [`Portal`]s are set up with.
+    ///
+    /// These are stored in the [`PortalPluginSettings`] resource, and can be changed there at
+    /// runtime to affect [`Portal`]s set up afterwards.
+    ///
+    /// Defaults to [`PortalPluginSettings::default`].
+    pub settings: PortalPluginSettings,
+}
+
+impl Default for PortalPlugin {
+    fn default() -> Self {
+        Self {
+            resize_delay_frames: 0,
+            image_allocation: PortalImageAllocation::Exact,
+            occlusion_culling: false,
+            image_size: PortalImageSize::Viewport,
+            settings: PortalPluginSettings::default(),
+        }
+    }
+}
+
+/// A [`PluginGroup`] with [`PortalPlugin`], along with the plugins of enabled features:
+///
+/// * `PortalPickingPlugin` from the `picking` module, with the `picking` feature.
+/// * `PortalGizmosPlugin` from the `gizmos` module, with the `gizmos` feature.
+/// * `Portal2dPlugin` from the `portal_2d` module, with the `2d` feature.
+///
+/// Use [`PluginGroup::set`] to configure the [`PortalPlugin`], or [`PluginGroupBuilder::disable`]
+/// to leave out one of the others.
+///
+/// [`PluginGroupBuilder::disable`]: bevy::app::PluginGroupBuilder::disable
+pub struct PortalPlugins;
+
+impl PluginGroup for PortalPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        let group = PluginGroupBuilder::start::<Self>().add(PortalPlugin::default());
+        #[cfg(feature = "picking")]
+        let group = group.add(picking::PortalPickingPlugin);
+        #[cfg(feature = "gizmos")]
+        let group = group.add(gizmos::PortalGizmosPlugin);
+        #[cfg(feature = "2d")]
+        let group = group.add(portal_2d::Portal2dPlugin);
+        group
+    }
+}
 
 /// Label for systems that update [`Portal`] related cameras.
 #[derive(Debug, PartialEq, Eq, Clone, Hash, SystemSet)]
 pub enum PortalCameraSystems {
-    /// Resizes [`Portal::linked_camera`]'s rendered image if any [`WindowResized`] events are read.
+    /// Resizes the [`PortalCamera`]'s rendered image if the window of
+    /// [`Portal::primary_camera`] is resized, if its [`Camera`] changed, e.g. its [`Viewport`], or
+    /// if the level of the [`Portal`]'s [`PortalResolutionLod`] changed.
     ResizeImage,
-    /// Updates the [`GlobalTransform`] and [`Transform`] components for [`Portal::linked_camera`]
+    /// Updates the [`GlobalTransform`] and [`Transform`] components of the [`PortalCamera`]
     /// based on the [`Portal::primary_camera`]s [`GlobalTransform`].
     UpdateTransform,
-    /// Updates the [`Frustum`] for [`Portal::linked_camera`].
+    /// Updates the near clipping plane of the [`PortalProjection`] of the [`PortalCamera`],
+    /// which in turn updates its [`Frustum`].
+    ///
+    /// [`Frustum`]: bevy::render::primitives::Frustum
     UpdateFrusta,
+    /// Replaces the side planes of the [`Frustum`] of the [`PortalCamera`] with planes through
+    /// the edges of the [`Portal`], as seen through its target.
+    ///
+    /// [`Frustum`]: bevy::render::primitives::Frustum
+    FitFrustum,
+    /// Copies the primary camera's [`Projection`] to the [`PortalProjection`] of
+    /// the [`PortalCamera`], if [`Portal::sync_projection`] is enabled.
+    SyncProjection,
+    /// Restricts the viewport of the [`PortalCamera`] to the area the [`Portal`] covers on
+    /// the screen of [`Portal::primary_camera`].
+    UpdateViewport,
+    /// Checks whether a [`Portal`]'s mesh is within the [`Frustum`] of [`Portal::primary_camera`].
+    ///
+    /// [`Frustum`]: bevy::render::primitives::Frustum
+    CheckFrustum,
+    /// Activates the [`PortalCamera`] only if the [`Portal`] is visible this frame, within the
+    /// primary camera's frustum, not occluded (if [`PortalPlugin::occlusion_culling`] is enabled),
+    /// and within its [`PortalActivationRange`] (if any).
+    UpdateActivity,
 }
 
 impl Plugin for PortalPlugin {
@@ -53,11 +212,32 @@ impl Plugin for PortalPlugin {
             concat!(env!("CARGO_MANIFEST_DIR"), "/assets/portal.wgsl"),
             Shader::from_wgsl
         );
+        load_internal_asset!(
+            app,
+            PORTAL_FUNCTIONS_SHADER_HANDLE,
+            concat!(env!("CARGO_MANIFEST_DIR"), "/assets/portal_functions.wgsl"),
+            Shader::from_wgsl
+        );
 
         app.add_plugins(MaterialPlugin::<PortalMaterial>::default())
+            .add_event::<PortalPrimaryCameraLost>()
+            .add_event::<PortalTargetLost>()
+            .add_event::<PortalError>()
+            .add_systems(
+                PreUpdate,
+                (
+                    retry_portal_setups,
+                    register_portal_targets,
+                    update_portal_primary_cameras,
+                    update_portal_targets,
+                    relink_changed_portals,
+                )
+                    .chain()
+                    .before(PortalCameraSystems::ResizeImage),
+            )
             .add_systems(
                 PreUpdate,
-                resize_portal_images.in_set(PortalCameraSystems::ResizeImage),
+                update_portal_resolution_lods.in_set(PortalCameraSystems::ResizeImage),
             )
             .add_systems(
                 PostUpdate,
@@ -66,11 +246,124 @@ impl Plugin for PortalPlugin {
                     update_portal_camera_frusta.in_set(PortalCameraSystems::UpdateFrusta),
                 )
                     .after(TransformSystem::TransformPropagate)
+                    .before(CameraUpdateSystem)
                     .before(VisibilitySystems::UpdateFrusta)
                     .chain(),
             )
+            .add_systems(
+                PostUpdate,
+                sync_portal_camera_projections
+                    .in_set(PortalCameraSystems::SyncProjection)
+                    .after(camera_system::<Projection>)
+                    .before(camera_system::<PortalProjection>),
+            )
+            .add_systems(
+                PostUpdate,
+                update_portal_camera_viewports
+                    .in_set(PortalCameraSystems::UpdateViewport)
+                    .after(VisibilitySystems::CalculateBounds)
+                    .after(TransformSystem::TransformPropagate)
+                    .after(camera_system::<Projection>)
+                    .before(camera_system::<PortalProjection>),
+            )
+            // `PortalCamera`s also have a `Projection`, so make sure the `PortalProjection` wins
+            .add_systems(
+                PostStartup,
+                camera_system::<PortalProjection>
+                    .in_set(CameraUpdateSystem)
+                    .after(camera_system::<Projection>),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    camera_system::<PortalProjection>
+                        .in_set(CameraUpdateSystem)
+                        .after(camera_system::<Projection>),
+                    update_frusta::<PortalProjection>
+                        .in_set(VisibilitySystems::UpdateFrusta)
+                        .after(camera_system::<PortalProjection>)
+                        .after(update_frusta::<Projection>)
+                        .after(TransformSystem::TransformPropagate),
+                    fit_portal_camera_frusta
+                        .in_set(PortalCameraSystems::FitFrustum)
+                        .in_set(VisibilitySystems::UpdateFrusta)
+                        .after(update_frusta::<PortalProjection>)
+                        .after(VisibilitySystems::CalculateBounds),
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                check_portal_frusta
+                    .in_set(PortalCameraSystems::CheckFrustum)
+                    .after(VisibilitySystems::CalculateBounds)
+                    .after(VisibilitySystems::UpdateFrusta)
+                    .before(PortalCameraSystems::UpdateActivity),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    (
+                        update_portal_activation_ranges,
+                        update_portal_distance_fades,
+                    ),
+                    update_portal_camera_activity,
+                )
+                    .in_set(PortalCameraSystems::UpdateActivity)
+                    .after(VisibilitySystems::CheckVisibility)
+                    .chain(),
+            )
+            .init_resource::<ViewRenderLayers>()
+            .init_resource::<PortalCameraShares>()
+            .init_resource::<PortalBudget>()
+            .insert_resource(self.image_size)
+            .insert_resource(self.settings.clone())
+            .add_observer(request_portal_setup)
             .add_observer(setup_portal)
-            .register_type::<(Portal, PortalCamera)>();
+            .add_observer(despawn_portal_camera)
+            .add_observer(unregister_portal_target)
+            .register_type::<(
+                Portal,
+                PortalCamera,
+                LinkedPortalCamera,
+                PortalImage,
+                PortalProjection,
+                PortalActivationRange,
+                PortalResolutionLod,
+                PortalDisabled,
+                PortalUpdateRate,
+            )>()
+            // `register_type` takes tuples of up to 12 types
+            .register_type::<(
+                PortalSetupState,
+                PortalTarget,
+                PortalPriority,
+                PortalBudget,
+                PortalImageSize,
+                PortalAperture,
+                PortalSurfaceMapping,
+                PortalDistanceFade,
+                PortalDissolve,
+            )>();
+
+        if self.occlusion_culling {
+            app.add_plugins(occlusion::PortalOcclusionPlugin);
+        }
+
+        add_portal_material_systems::<PortalMaterial>(app);
+
+        app.add_plugins((
+            image_pool::PortalImagePoolPlugin {
+                resize_delay_frames: self.resize_delay_frames,
+                image_allocation: self.image_allocation,
+            },
+            recursion::PortalRecursionPlugin {
+                fallback_color: self.settings.recursion_fallback_color,
+            },
+            teleport::PortalTeleportPlugin,
+            clip::PortalClipPlugin,
+            mipmap::PortalMipmapPlugin,
+            atlas::PortalAtlasPlugin,
+        ));
     }
 }
 
@@ -82,7 +375,7 @@ impl Plugin for PortalPlugin {
 /// A [`PortalMaterial`] is also inserted on the entity, inherting [`Portal::cull_mode`].
 #[derive(Component, Reflect, Debug)]
 #[reflect(Component)]
-#[require(Transform)]
+#[require(Transform, PortalInFrustum, PortalSetupState)]
 pub struct Portal {
     /// The entity with the primary render [`Camera`].
     ///
@@ -100,10 +393,170 @@ pub struct Portal {
     // TODO: Can this be remotely reflected upstream now that #6042 has landed?
     #[reflect(ignore)]
     pub cull_mode: Option<Face>,
-    /// The [`Entity`] that has this portal's [`PortalCamera`].
+    /// A camera of your own to render the portal through, instead of spawning a [`PortalCamera`].
+    ///
+    /// The camera is turned into the [`PortalCamera`]: its [`Camera::target`] is set to the
+    /// portal's image, its [`Projection`] is replaced by a [`PortalProjection`], and its transform
+    /// is updated like a spawned camera's. Other components, including the rest of [`Camera`], are
+    /// left as is. Make sure its [`Camera::order`] is lower than the primary camera's, so that the
+    /// image is rendered before it's shown.
+    ///
+    /// The camera is kept when the portal is despawned, but deactivated. This is only used when the
+    /// portal is set up, and is ignored by [`Portal::share_camera`].
+    ///
+    /// Defaults to `None`.
+    pub camera: Option<Entity>,
+    /// An image of your own for the [`PortalCamera`] to render to, instead of allocating one.
+    ///
+    /// The image is never resized, so its size and [`TextureFormat`] are up to you, and
+    /// [`Portal::resolution_scale`], [`Portal::texture_format`], [`Portal::image_sampler`] and
+    /// [`Portal::atlas`] are ignored. It needs [`TextureUsages::RENDER_ATTACHMENT`], and should
+    /// be an HDR image if [`Portal::hdr`] or the primary camera's [`Camera::hdr`] is enabled.
+    ///
+    /// This is only used when the portal is set up, and the portal doesn't share its camera (see
+    /// [`Portal::share_camera`]).
+    ///
+    /// Defaults to `None`.
+    ///
+    /// [`TextureUsages::RENDER_ATTACHMENT`]: bevy::render::render_resource::TextureUsages
+    pub image: Option<Handle<Image>>,
+    /// Additional entities with a primary render [`Camera`], e.g. for split-screen.
+    ///
+    /// Each secondary camera gets a child [`Portal`] (and [`PortalCamera`]) of its own. To make
+    /// sure every camera only sees the portal rendered for it, the portal meshes and cameras are
+    /// assigned one of the [`VIEW_RENDER_LAYERS`].
+    ///
+    /// Defaults to no secondary cameras.
+    pub secondary_cameras: Vec<Entity>,
+    /// The [`RenderLayers`] given to the [`PortalCamera`].
+    ///
+    /// Use this to hide entities from the view through the portal, e.g. the player's model.
+    ///
+    /// If set to `None`, the [`PortalCamera`] uses the default [`RenderLayers`].
+    ///
+    /// Defaults to `None`.
+    pub render_layers: Option<RenderLayers>,
+    /// The size of the portal's image, as a fraction of the primary camera's viewport size.
+    ///
+    /// Lower values trade image quality for performance, which is useful for small portals.
+    ///
+    /// Defaults to `1.0`.
+    pub resolution_scale: f32,
+    /// The [`Msaa`] used by the [`PortalCamera`].
+    ///
+    /// The portal's image itself is never multisampled: the [`PortalCamera`] renders to a
+    /// multisampled texture, which is then resolved to the image.
+    ///
+    /// If set to `None`, the primary camera's [`Msaa`] is used.
+    ///
+    /// Defaults to `None`.
+    pub msaa: Option<Msaa>,
+    /// Whether the portal's image should be an HDR ([`TextureFormat::Rgba16Float`]) image.
+    ///
+    /// If enabled, [`Camera::hdr`] is set on the [`PortalCamera`], and its [`Tonemapping`] is
+    /// disabled, leaving tonemapping (and effects like bloom) to the primary camera. This should
+    /// only be enabled if the primary camera has [`Camera::hdr`] set as well.
+    ///
+    /// Defaults to `false`.
+    pub hdr: bool,
+    /// The [`TextureFormat`] of the portal's image.
+    ///
+    /// If set to `None`, [`PortalPluginSettings::texture_format`] is used. If that isn't set
+    /// either, [`TextureFormat::Rgba16Float`] is used if [`Portal::hdr`] is enabled, and
+    /// [`TextureFormat::Bgra8UnormSrgb`] otherwise.
+    ///
+    /// Defaults to `None`.
+    #[reflect(ignore)]
+    pub texture_format: Option<TextureFormat>,
+    /// The [`ImageSampler`] the portal's image is sampled with, e.g. for nearest filtering in
+    /// pixel-art projects.
+    ///
+    /// This is only applied when the [`PortalCamera`] is spawned, and ignored if
+    /// [`Portal::atlas`] is enabled, since the atlas is shared.
+    ///
+    /// Defaults to [`ImageSampler::Default`].
+    #[reflect(ignore)]
+    pub image_sampler: ImageSampler,
+    /// Whether the portal's image should have mipmaps, generated after the [`PortalCamera`]
+    /// renders.
+    ///
+    /// This keeps portals far away or seen at glancing angles from shimmering, at the cost of an
+    /// extra image and a few render passes. See the [`mipmap`](mod@mipmap) module for details.
+    ///
+    /// Defaults to `false`.
+    pub mipmaps: bool,
+    /// Whether the [`PortalCamera`] should only render the area the portal covers on screen.
+    ///
+    /// This is ignored for portals that are rendered recursively, or that don't use
+    /// [`PortalSurfaceMapping::ScreenSpace`], since these sample the whole image.
+    ///
+    /// Defaults to `true`.
+    pub scissor: bool,
+    /// Whether the [`PortalCamera`] should render into a cell of the shared [`PortalAtlas`],
+    /// instead of an image of its own.
+    ///
+    /// See the [`atlas`](mod@atlas) module for details.
     ///
-    /// This is set internally and should not be manually assigned.
-    pub linked_camera: Option<Entity>,
+    /// Defaults to `false`.
+    pub atlas: bool,
+    /// Whether the [`PortalCamera`]'s [`PortalProjection::projection`] should be kept in sync with
+    /// the primary camera's [`Projection`].
+    ///
+    /// Disable this to use a custom projection for the [`PortalCamera`].
+    ///
+    /// Defaults to `true`.
+    pub sync_projection: bool,
+    /// How far behind the [`Portal::target`] surface the [`PortalCamera`]'s clipping plane is
+    /// placed.
+    ///
+    /// Everything between the [`PortalCamera`] and the target surface is clipped. Increase this
+    /// slightly if geometry right at the surface flickers or z-fights.
+    ///
+    /// Defaults to `0.0`.
+    pub clip_offset: f32,
+    /// The far plane distance of the [`PortalCamera`], if it should be closer than the primary
+    /// camera's.
+    ///
+    /// Entities farther away are culled, which saves rendering e.g. the open world behind a portal
+    /// that looks into a small room. If [`Portal::sync_projection`] is disabled, this is only
+    /// applied when the [`PortalCamera`] is spawned.
+    ///
+    /// Defaults to `None`.
+    pub max_view_distance: Option<f32>,
+    /// How the portal's image is mapped onto its mesh.
+    ///
+    /// Defaults to [`PortalSurfaceMapping::ScreenSpace`].
+    pub surface_mapping: PortalSurfaceMapping,
+    /// The [`Entity`] the back face of the portal leads to, if it should differ from
+    /// [`Portal::target`].
+    ///
+    /// If set, the back face gets a child [`Portal`] (and [`PortalCamera`]) of its own, which is
+    /// rotated to face the other way. The portal itself then only shows its front face, regardless
+    /// of [`Portal::cull_mode`]. This is ignored for [`Mirror`]s.
+    ///
+    /// Defaults to `None`.
+    pub back_target: Option<Entity>,
+    /// Whether no [`PortalMaterial`] should be inserted for the portal, to use a material of your
+    /// own instead.
+    ///
+    /// The [`PortalCamera`] is still spawned, and its image can be bound in the material through
+    /// the [`PortalImage`] inserted next to the portal. The views spawned for
+    /// [`Portal::secondary_cameras`] and [`Portal::back_target`] need a material as well.
+    ///
+    /// Defaults to `false`.
+    pub manual_material: bool,
+    /// Whether the portal should reuse the [`PortalCamera`] and [`PortalImage`] of another portal
+    /// with the same [`Portal::primary_camera`] and [`Portal::target`], e.g. for a wall of windows
+    /// onto the same scenery.
+    ///
+    /// The shared [`PortalCamera`] belongs to the first of these portals, and every portal shows
+    /// its view. The view is only exact for the first portal, so this is best suited to portals
+    /// looking at distant scenery, where the difference is barely visible. Shared cameras render
+    /// their whole image, and aren't fitted to any single portal. The other portals have no
+    /// [`LinkedPortalCamera`] of their own. This is ignored if [`Portal::atlas`] is enabled.
+    ///
+    /// Defaults to `false`.
+    pub share_camera: bool,
 }
 
 impl Portal {
@@ -120,307 +573,2974 @@ impl Portal {
             primary_camera,
             target,
             cull_mode: Some(Face::Back),
-            linked_camera: None,
+            camera: None,
+            image: None,
+            secondary_cameras: Vec::new(),
+            render_layers: None,
+            resolution_scale: 1.0,
+            msaa: None,
+            hdr: false,
+            texture_format: None,
+            image_sampler: ImageSampler::Default,
+            mipmaps: false,
+            scissor: true,
+            atlas: false,
+            sync_projection: true,
+            clip_offset: 0.0,
+            max_view_distance: None,
+            surface_mapping: PortalSurfaceMapping::ScreenSpace,
+            back_target: None,
+            manual_material: false,
+            share_camera: false,
         }
     }
 
+    /// Creates a new mirror from a given `primary_camera`.
+    ///
+    /// This is a [`Portal`] targeting itself, along with a [`Mirror`] marker.
+    ///
+    /// # See Also
+    ///
+    /// * [`Portal::primary_camera`]
+    /// * [`Mirror`]
+    #[inline]
+    #[must_use]
+    pub fn mirror(primary_camera: Entity) -> (Self, Mirror) {
+        // The target is set to the mirror itself in `setup_portal`
+        (Self::new(primary_camera, Entity::PLACEHOLDER), Mirror)
+    }
+
     #[inline]
     #[must_use]
     pub fn with_cull_mode(mut self, cull_mode: Option<Face>) -> Self {
         self.cull_mode = cull_mode;
         self
     }
-}
 
-/// Component used to mark a [`Portal`]'s associated camera.
-#[derive(Component, Reflect, Debug)]
-#[reflect(Component)]
-#[require(Camera3d)]
-pub struct PortalCamera(pub Entity);
+    /// Sets [`Portal::secondary_cameras`].
+    #[inline]
+    #[must_use]
+    pub fn with_secondary_cameras(mut self, cameras: impl IntoIterator<Item = Entity>) -> Self {
+        self.secondary_cameras = cameras.into_iter().collect();
+        self
+    }
 
-/// Material used for a [`Portal`]'s mesh.
-#[derive(Asset, AsBindGroup, Clone, TypePath)]
-#[bind_group_data(PortalMaterialKey)]
-pub struct PortalMaterial {
-    #[texture(0)]
-    #[sampler(1)]
-    base_color_texture: Option<Handle<Image>>,
-    /// Specifies which side of the portal to cull: "front", "back", or neither.
-    ///
-    /// If set to `None`, both sides of the portal’s mesh will be rendered.
-    ///
-    /// This field's value is inherited from what is set on [`Portal`], but not kept in sync.
-    ///
-    /// Defaults to `Some(Face::Back)`, similar to [`StandardMaterial::cull_mode`] and [`Portal`].
-    pub cull_mode: Option<Face>,
-}
+    /// Sets [`Portal::render_layers`].
+    #[inline]
+    #[must_use]
+    pub fn with_render_layers(mut self, render_layers: RenderLayers) -> Self {
+        self.render_layers = Some(render_layers);
+        self
+    }
 
-impl Material for PortalMaterial {
-    fn fragment_shader() -> ShaderRef {
-        PORTAL_SHADER_HANDLE.into()
+    /// Sets [`Portal::resolution_scale`].
+    #[inline]
+    #[must_use]
+    pub fn with_resolution_scale(mut self, resolution_scale: f32) -> Self {
+        self.resolution_scale = resolution_scale;
+        self
     }
 
-    fn specialize(
-        _pipeline: &MaterialPipeline<Self>,
-        descriptor: &mut RenderPipelineDescriptor,
-        _layout: &MeshVertexBufferLayoutRef,
-        key: MaterialPipelineKey<Self>,
-    ) -> Result<(), SpecializedMeshPipelineError> {
-        descriptor.primitive.cull_mode = key.bind_group_data.cull_mode;
-        Ok(())
+    /// Sets [`Portal::msaa`].
+    #[inline]
+    #[must_use]
+    pub fn with_msaa(mut self, msaa: Msaa) -> Self {
+        self.msaa = Some(msaa);
+        self
     }
-}
 
-#[derive(Clone, PartialEq, Eq, Hash)]
-pub struct PortalMaterialKey {
-    cull_mode: Option<Face>,
-}
+    /// Sets [`Portal::hdr`].
+    #[inline]
+    #[must_use]
+    pub fn with_hdr(mut self, hdr: bool) -> Self {
+        self.hdr = hdr;
+        self
+    }
 
-impl From<&PortalMaterial> for PortalMaterialKey {
-    fn from(material: &PortalMaterial) -> Self {
-        Self {
-            cull_mode: material.cull_mode,
-        }
+    /// Sets [`Portal::texture_format`].
+    #[inline]
+    #[must_use]
+    pub fn with_texture_format(mut self, texture_format: TextureFormat) -> Self {
+        self.texture_format = Some(texture_format);
+        self
     }
-}
 
-/// System that is triggered whenever a [`Portal`] component is added to an entity.
-///
-/// An image is created based on the primary camera's viewport size. Then, a [`PortalCamera`] is
-/// created, with [`Camera::target`] set to render the [`PortalCamera`]'s view to the image.
-///
-/// Finally, a [`PortalMaterial`] is added to the [`Portal`] entity.
-///
-/// # Notes
-///
-/// * The [`PortalCamera`] will inherit any properties currently present on the primary camera.
-fn setup_portal(
-    trigger: Trigger<OnAdd, Portal>,
-    mut commands: Commands,
-    mut portal_query: Query<&mut Portal>,
-    primary_camera_query: Query<(
-        &Camera,
-        Option<&Camera3d>,
-        Option<&DebandDither>,
-        Option<&Tonemapping>,
-        Option<&ColorGrading>,
-        Option<&Exposure>,
-    )>,
-    mut images: ResMut<Assets<Image>>,
-    mut portal_materials: ResMut<Assets<PortalMaterial>>,
-    global_transform_query: Query<&GlobalTransform>,
-    viewport_size: ViewportSize,
-) {
-    let entity = trigger.entity();
+    /// Sets [`Portal::image_sampler`].
+    #[inline]
+    #[must_use]
+    pub fn with_image_sampler(mut self, image_sampler: ImageSampler) -> Self {
+        self.image_sampler = image_sampler;
+        self
+    }
 
-    let mut portal = portal_query
-        .get_mut(entity)
-        .expect("observer guarantees existence of component");
+    /// Sets [`Portal::mipmaps`].
+    #[inline]
+    #[must_use]
+    pub fn with_mipmaps(mut self, mipmaps: bool) -> Self {
+        self.mipmaps = mipmaps;
+        self
+    }
 
-    let Ok((primary_camera, camera_3d, tonemapping, deband_dither, color_grading, exposure)) =
-        primary_camera_query.get(portal.primary_camera)
-    else {
-        error!(
-            "could not setup portal {entity}: primary_camera does not contain a Camera component"
-        );
-        return;
-    };
+    /// Sets [`Portal::scissor`].
+    #[inline]
+    #[must_use]
+    pub fn with_scissor(mut self, scissor: bool) -> Self {
+        self.scissor = scissor;
+        self
+    }
 
-    let image_handle = {
-        let Some(size) = viewport_size.get_viewport_size(primary_camera) else {
-            error!("could not compute viewport size for portal {entity}");
-            return;
-        };
-        let format = TextureFormat::Bgra8UnormSrgb;
-        let image = Image {
-            data: vec![0; size.volume() * format.pixel_size()],
-            texture_descriptor: TextureDescriptor {
-                label: None,
-                size,
-                dimension: TextureDimension::D2,
-                format,
-                mip_level_count: 1,
-                sample_count: 1,
-                usage: TextureUsages::TEXTURE_BINDING
-                    | TextureUsages::COPY_DST
-                    | TextureUsages::RENDER_ATTACHMENT,
-                view_formats: &[],
-            },
-            ..default()
-        };
-        images.add(image)
-    };
+    /// Sets [`Portal::atlas`].
+    #[inline]
+    #[must_use]
+    pub fn with_atlas(mut self, atlas: bool) -> Self {
+        self.atlas = atlas;
+        self
+    }
 
-    let Ok(global_transform) = global_transform_query.get(portal.target).copied() else {
-        error!("portal target is missing a GlobalTransform");
-        return;
-    };
-    portal.linked_camera = Some(
-        commands
-            .spawn((
-                Name::new("Portal Camera"),
-                Camera {
-                    order: -1,
-                    target: RenderTarget::Image(image_handle.clone()),
-                    ..primary_camera.clone()
-                },
-                global_transform.compute_transform(),
-                global_transform,
-                camera_3d.cloned().unwrap_or_default(),
-                tonemapping.copied().unwrap_or_default(),
-                deband_dither.copied().unwrap_or_default(),
-                color_grading.cloned().unwrap_or_default(),
-                exposure.copied().unwrap_or_default(),
-                PortalCamera(entity),
-            ))
-            .id(),
-    );
+    /// Sets [`Portal::sync_projection`].
+    #[inline]
+    #[must_use]
+    pub fn with_sync_projection(mut self, sync_projection: bool) -> Self {
+        self.sync_projection = sync_projection;
+        self
+    }
 
-    commands
-        .entity(entity)
-        .insert(MeshMaterial3d(portal_materials.add(PortalMaterial {
-            base_color_texture: Some(image_handle.clone()),
-            cull_mode: portal.cull_mode,
-        })));
-}
+    /// Sets [`Portal::clip_offset`].
+    #[inline]
+    #[must_use]
+    pub fn with_clip_offset(mut self, clip_offset: f32) -> Self {
+        self.clip_offset = clip_offset;
+        self
+    }
 
-/// System that updates a [`PortalCamera`]'s translation and rotation based on the primary camera.
-///
-/// # Notes
-///
+    /// Sets [`Portal::max_view_distance`].
+    #[inline]
+    #[must_use]
+    pub fn with_max_view_distance(mut self, max_view_distance: f32) -> Self {
+        self.max_view_distance = Some(max_view_distance);
+        self
+    }
+
+    /// Sets [`Portal::surface_mapping`].
+    #[inline]
+    #[must_use]
+    pub fn with_surface_mapping(mut self, surface_mapping: PortalSurfaceMapping) -> Self {
+        self.surface_mapping = surface_mapping;
+        self
+    }
+
+    /// Sets [`Portal::back_target`].
+    #[inline]
+    #[must_use]
+    pub fn with_back_target(mut self, back_target: Entity) -> Self {
+        self.back_target = Some(back_target);
+        self
+    }
+
+    /// Enables [`Portal::manual_material`].
+    #[inline]
+    #[must_use]
+    pub fn with_manual_material(mut self) -> Self {
+        self.manual_material = true;
+        self
+    }
+
+    /// Sets [`Portal::camera`].
+    #[inline]
+    #[must_use]
+    pub fn with_camera(mut self, camera: Entity) -> Self {
+        self.camera = Some(camera);
+        self
+    }
+
+    /// Sets [`Portal::image`].
+    #[inline]
+    #[must_use]
+    pub fn with_image(mut self, image: Handle<Image>) -> Self {
+        self.image = Some(image);
+        self
+    }
+
+    /// Sets [`Portal::share_camera`].
+    #[inline]
+    #[must_use]
+    pub fn with_share_camera(mut self, share_camera: bool) -> Self {
+        self.share_camera = share_camera;
+        self
+    }
+
+    /// Returns the [`Face`] culled by the portal's material.
+    ///
+    /// If [`Portal::back_target`] is set, the back face is rendered by a child [`Portal`] instead.
+    pub(crate) fn material_cull_mode(&self) -> Option<Face> {
+        if self.back_target.is_some() {
+            Some(Face::Back)
+        } else {
+            self.cull_mode
+        }
+    }
+
+    /// Returns the primary camera's `projection`, with the far plane limited to
+    /// [`Portal::max_view_distance`].
+    fn limit_view_distance(&self, mut projection: Projection) -> Projection {
+        if let Some(max_view_distance) = self.max_view_distance {
+            match &mut projection {
+                Projection::Perspective(perspective) => {
+                    perspective.far = perspective.far.min(max_view_distance);
+                }
+                Projection::Orthographic(orthographic) => {
+                    orthographic.far = orthographic.far.min(max_view_distance);
+                }
+            }
+        }
+        projection
+    }
+
+    /// Returns the [`TextureFormat`] of the portal's image.
+    ///
+    /// See [`Portal::texture_format`].
+    #[inline]
+    pub fn image_texture_format(&self) -> TextureFormat {
+        match self.texture_format {
+            Some(texture_format) => texture_format,
+            None if self.hdr => TextureFormat::Rgba16Float,
+            None => TextureFormat::Bgra8UnormSrgb,
+        }
+    }
+}
+
+/// How a [`Portal`]'s image is mapped onto its mesh.
+#[derive(Reflect, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[reflect(Debug, Default, PartialEq, Hash)]
+pub enum PortalSurfaceMapping {
+    /// The image is sampled at the position of each fragment on the screen.
+    ///
+    /// This makes the portal look like an opening into the target's space, regardless of the
+    /// shape of its mesh.
+    #[default]
+    ScreenSpace,
+    /// The whole image is projected onto the mesh along its local z-axis, fitted to its [`Aabb`].
+    ///
+    /// Use this for non-flat meshes that should show the image like a screen, without relying on
+    /// their UVs.
+    Planar,
+    /// The whole image is mapped onto the mesh using its UVs.
+    ///
+    /// Meshes without UVs fall back to [`PortalSurfaceMapping::ScreenSpace`].
+    MeshUv,
+}
+
+/// The render layers used to show each primary camera its own view of a [`Portal`] with
+/// [`Portal::secondary_cameras`], and to hide [`Portal`]s from their portal cameras when recursion
+/// is enabled.
+///
+/// A layer from this range is added to the [`RenderLayers`] of each primary camera involved.
+pub const VIEW_RENDER_LAYERS: Range<usize> = 16..24;
+
+/// Resource keeping track of the layer in [`VIEW_RENDER_LAYERS`] assigned to each primary camera.
+#[derive(Resource, Default)]
+struct ViewRenderLayers(HashMap<Entity, usize>);
+
+impl ViewRenderLayers {
+    /// Retrieves the layer assigned to `camera`, assigning the next free one if there's none.
+    fn get_or_assign(
+        &mut self,
+        commands: &mut Commands,
+        camera: Entity,
+        render_layers_query: &Query<&RenderLayers>,
+    ) -> usize {
+        let next = VIEW_RENDER_LAYERS.start + self.0.len();
+        *self.0.entry(camera).or_insert_with(|| {
+            if !VIEW_RENDER_LAYERS.contains(&next) {
+                warn!("ran out of view render layers for primary camera {camera}");
+            }
+            let render_layers = render_layers_query.get(camera).cloned().unwrap_or_default();
+            commands.entity(camera).insert(render_layers.with(next));
+            next
+        })
+    }
+}
+
+/// Resource keeping track of the [`Portal`]s sharing a [`PortalCamera`], see
+/// [`Portal::share_camera`].
+#[derive(Resource, Default)]
+struct PortalCameraShares(HashMap<(Entity, Entity), PortalCameraShare>);
+
+/// The [`Portal`]s with a given primary camera and target that share a [`PortalCamera`].
+struct PortalCameraShare {
+    /// The shared [`PortalCamera`].
+    camera: Entity,
+    /// The image the shared [`PortalCamera`] renders to.
+    image: PortalImage,
+    /// The [`Portal`]s sharing the camera, the first of which owns it.
+    portals: Vec<Entity>,
+}
+
+impl PortalCameraShares {
+    /// Returns the share `portal` is part of, if any.
+    fn get(&self, portal: Entity) -> Option<&PortalCameraShare> {
+        self.0
+            .values()
+            .find(|share| share.portals.contains(&portal))
+    }
+
+    /// Returns the [`PortalCamera`] `portal` renders through, if it shares one.
+    fn camera(&self, portal: Entity) -> Option<Entity> {
+        self.get(portal).map(|share| share.camera)
+    }
+
+    /// Returns whether `portal` shares its [`PortalCamera`] with other portals.
+    fn is_shared(&self, portal: Entity) -> bool {
+        self.get(portal)
+            .is_some_and(|share| share.portals.len() > 1)
+    }
+}
+
+/// Component used to deactivate a [`Portal`]'s camera when the primary camera is far away.
+///
+/// The [`PortalCamera`] is deactivated once the primary camera is farther than `max_distance` from
+/// the portal, and activated again once it is closer than `max_distance - hysteresis`. While
+/// deactivated, the portal keeps showing the last image that was rendered.
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component)]
+#[require(PortalInRange)]
+pub struct PortalActivationRange {
+    /// The distance beyond which the [`PortalCamera`] is deactivated.
+    pub max_distance: f32,
+    /// How much closer than `max_distance` the primary camera has to get to activate the
+    /// [`PortalCamera`] again.
+    ///
+    /// This prevents the camera from toggling every frame around `max_distance`.
+    pub hysteresis: f32,
+}
+
+/// Component used to lower the resolution of a [`Portal`]'s image as the primary camera moves
+/// away.
+///
+/// The image is resized in steps, so that far portals are cheap to render, and is restored as the
+/// primary camera gets closer again. The image's [`ImageSampler`] smooths the lower resolutions.
+///
+/// # Notes
+///
+/// * This is ignored for portals rendering into the [`PortalAtlas`].
+#[derive(Component, Reflect, Debug, Default, Clone)]
+#[reflect(Component, Default)]
+#[require(PortalLodLevel)]
+pub struct PortalResolutionLod {
+    /// The distances at which the resolution drops, each with the scale used beyond it, relative
+    /// to [`Portal::resolution_scale`].
+    ///
+    /// This should be sorted by distance, in ascending order.
+    pub levels: Vec<(f32, f32)>,
+    /// How much closer than a level's distance the primary camera has to get to restore the
+    /// previous resolution.
+    ///
+    /// This prevents the image from being resized every frame around a level's distance.
+    pub hysteresis: f32,
+}
+
+impl PortalResolutionLod {
+    /// Returns the resolution scale of a given `level`, relative to [`Portal::resolution_scale`].
+    fn scale(&self, level: usize) -> f32 {
+        level
+            .checked_sub(1)
+            .and_then(|level| self.levels.get(level))
+            .map_or(1.0, |&(_, scale)| scale)
+    }
+}
+
+/// The current level of a [`PortalResolutionLod`], `0` being the full resolution.
+#[derive(Component, Default)]
+struct PortalLodLevel(usize);
+
+/// Component used to freeze a [`Portal`].
+///
+/// The [`PortalCamera`] is deactivated while this is present, so the portal keeps showing the last
+/// image that was rendered. Remove this component to unfreeze the portal.
+///
+/// This also applies to the views spawned for [`Portal::secondary_cameras`] and
+/// [`Portal::back_target`].
+///
+/// # Notes
+///
+/// * If a [`Portal`] is disabled before its first frame is rendered, its image stays black.
+#[derive(Component, Reflect, Debug, Default, Clone, Copy)]
+#[reflect(Component, Default)]
+pub struct PortalDisabled;
+
+/// Marker component for the child [`Portal`] spawned for the back face of a [`Portal`] with a
+/// [`Portal::back_target`].
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub(crate) struct PortalBackFace;
+
+/// Component used to open or close a [`Portal`], e.g. for an opening animation.
+///
+/// The value ranges from `0.0` (closed) to `1.0` (open), and is applied to
+/// [`PortalMaterial::aperture`]. While closed, the [`PortalCamera`] is deactivated.
+///
+/// This also applies to the views spawned for [`Portal::secondary_cameras`].
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq)]
+#[reflect(Component, Default)]
+pub struct PortalAperture(pub f32);
+
+impl Default for PortalAperture {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+impl PortalAperture {
+    /// Returns the aperture of a [`Portal`], or of the [`Portal`] its view is spawned for.
+    ///
+    /// Defaults to `1.0` if neither has a [`PortalAperture`].
+    pub(crate) fn resolve(
+        aperture: Option<&Self>,
+        parent: Option<&Parent>,
+        aperture_query: &Query<&Self>,
+    ) -> f32 {
+        aperture
+            .or_else(|| parent.and_then(|parent| aperture_query.get(parent.get()).ok()))
+            .map_or(1.0, |aperture| aperture.0.clamp(0.0, 1.0))
+    }
+}
+
+/// Component used to dissolve a [`Portal`], e.g. to let it materialize or vanish.
+///
+/// The value ranges from `0.0` (solid) to `1.0` (gone), and is applied to
+/// [`PortalMaterial::dissolve`]. Insert this along with the [`Portal`] and animate it towards
+/// `0.0` to let the portal appear gradually.
+#[derive(Component, Reflect, Debug, Default, Clone, Copy, PartialEq)]
+#[reflect(Component, Default)]
+pub struct PortalDissolve(pub f32);
+
+/// Component used to fade a [`Portal`] to a flat color as the primary camera moves away.
+///
+/// The portal blends from its image at `start` to `color` at `end`, where the [`PortalCamera`] is
+/// deactivated. The distance is measured between the primary camera and the portal.
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component)]
+#[require(PortalFadeAmount)]
+pub struct PortalDistanceFade {
+    /// The distance the portal starts fading at.
+    pub start: f32,
+    /// The distance the portal is fully faded at, and the [`PortalCamera`] is deactivated.
+    pub end: f32,
+    /// The color the portal fades to.
+    pub color: Color,
+}
+
+/// How far a [`Portal`] with a [`PortalDistanceFade`] is faded, from `0.0` to `1.0`.
+#[derive(Component, Default)]
+struct PortalFadeAmount(f32);
+
+/// Whether the primary camera is within a [`PortalActivationRange`].
+#[derive(Component)]
+struct PortalInRange(bool);
+
+impl Default for PortalInRange {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+/// Whether a [`Portal`]'s mesh is within the frustum of its primary camera.
+#[derive(Component)]
+struct PortalInFrustum(bool);
+
+impl Default for PortalInFrustum {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+/// The [`Portal::primary_camera`] a [`Portal`]'s camera was last set up for.
+#[derive(Component)]
+struct PortalLinkedPrimaryCamera(Entity);
+
+/// Marker component for [`Portal`]s whose [`Portal::primary_camera`] was despawned.
+///
+/// The [`PortalCamera`] is deactivated until a new primary camera is assigned.
+#[derive(Component)]
+struct PortalOrphaned;
+
+/// Event sent when the [`Portal::primary_camera`] of a [`Portal`] no longer exists, e.g. because it
+/// was despawned.
+///
+/// The [`PortalCamera`] of the portal is deactivated, and the portal keeps showing the last image
+/// that was rendered. Assign a new [`Portal::primary_camera`] to link the portal to it.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PortalPrimaryCameraLost {
+    /// The [`Portal`] entity.
+    pub portal: Entity,
+    /// The primary camera entity that no longer exists.
+    pub primary_camera: Entity,
+}
+
+/// Component inserted on the [`Portal::target`] of [`Portal`]s, listing the [`Portal`]s using it.
+///
+/// Once all of its [`Portal`]s are removed or retargeted, a target keeps this component with no
+/// portals left, see [`PortalTarget::is_orphaned`].
+#[derive(Component, Reflect, Debug, Default, Clone)]
+#[reflect(Component, Default)]
+pub struct PortalTarget {
+    portals: Vec<Entity>,
+}
+
+impl PortalTarget {
+    /// Returns the [`Portal`] entities using this target.
+    #[inline]
+    pub fn portals(&self) -> &[Entity] {
+        &self.portals
+    }
+
+    /// Returns whether no [`Portal`] uses this target anymore.
+    #[inline]
+    pub fn is_orphaned(&self) -> bool {
+        self.portals.is_empty()
+    }
+}
+
+/// The [`Portal::target`] a [`Portal`] is listed in the [`PortalTarget`] of.
+#[derive(Component)]
+struct PortalRegisteredTarget(Entity);
+
+/// Marker component for [`Portal`]s whose [`Portal::target`] is missing a [`GlobalTransform`],
+/// e.g. because it was despawned.
+///
+/// The [`PortalCamera`] is deactivated until the target is back, or a new one is assigned.
+#[derive(Component)]
+struct PortalTargetMissing;
+
+/// Event sent when the [`Portal::target`] of a [`Portal`] is missing a [`GlobalTransform`], e.g.
+/// because it was despawned.
+///
+/// The [`PortalCamera`] of the portal is deactivated, and the portal keeps showing the last image
+/// that was rendered. It resumes once the target has a [`GlobalTransform`] again, or a new
+/// [`Portal::target`] is assigned.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PortalTargetLost {
+    /// The [`Portal`] entity.
+    pub portal: Entity,
+    /// The target entity that is missing.
+    pub target: Entity,
+}
+
+/// Whether a [`Portal`]'s [`PortalCamera`], image and material are set up.
+///
+/// Setting up a [`Portal`] requires its [`Portal::primary_camera`] to have a [`Camera`] with a
+/// known viewport size, and its [`Portal::target`] to have a [`GlobalTransform`]. Until then, setup
+/// is retried every frame.
+#[derive(Component, Reflect, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[reflect(Component, Default)]
+pub enum PortalSetupState {
+    /// The [`Portal`] is waiting for its primary camera or target to be ready.
+    #[default]
+    Pending,
+    /// The [`Portal`] is set up.
+    Active,
+    /// The [`Portal`]'s primary camera or target doesn't exist.
+    ///
+    /// Setup is retried once the [`Portal`] changes, e.g. when a new target is assigned.
+    Failed,
+}
+
+/// Event sent when a [`Portal`] can't be set up (fully), e.g. because it is misconfigured.
+///
+/// Setup is retried for [`PortalSetupState::Pending`] portals, but each problem is only reported
+/// once, when the [`Portal`] is added or changed.
+///
+/// This is sent for 2D portals too, in which case [`PortalError::portal`] is a `Portal2d` entity.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortalError {
+    /// The [`Portal`] entity.
+    pub portal: Entity,
+    /// What went wrong.
+    pub kind: PortalErrorKind,
+}
+
+/// The kinds of [`PortalError`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortalErrorKind {
+    /// An entity the [`Portal`] refers to, i.e. its [`Portal::primary_camera`] or
+    /// [`Portal::target`], doesn't exist.
+    MissingEntity(Entity),
+    /// The [`Portal::primary_camera`] has no [`Camera`] component.
+    MissingCamera,
+    /// The [`Portal::primary_camera`] renders to an image, whose size isn't known. Use
+    /// [`PortalImageSize::Fixed`] for such cameras.
+    UnsupportedRenderTarget,
+    /// The viewport size of the [`Portal::primary_camera`] isn't known, e.g. because its window
+    /// doesn't exist yet.
+    UnknownViewportSize,
+    /// The [`Portal::target`] has no [`GlobalTransform`] component.
+    MissingTargetTransform,
+    /// The [`Portal`] has no [`Mesh3d`], which its [`Portal::back_target`] or
+    /// [`Portal::secondary_cameras`] need.
+    MissingMesh,
+}
+
+/// Event triggered for a [`Portal`] entity once its [`PortalCamera`] is spawned.
+///
+/// Observe this to customize the [`PortalCamera`], e.g. by inserting post-processing components
+/// or markers. Commands queued by the observer are applied before the camera first renders.
+///
+/// This isn't triggered for portals reusing the camera of another portal (see
+/// [`Portal::share_camera`]).
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PortalCameraSpawned {
+    /// The entity of the spawned [`PortalCamera`].
+    pub camera: Entity,
+}
+
+/// Event triggered for a [`Portal`] entity to set it up, see [`PortalSetupState`].
+#[derive(Event)]
+pub(crate) struct SetupPortal {
+    /// Whether to report why setup can't complete yet, which is skipped for repeated retries.
+    pub(crate) report_pending: bool,
+}
+
+/// Component used to mark a [`Portal`]'s associated camera, holding the [`Portal`] entity.
+///
+/// This is the camera side of the link between a [`Portal`] and its camera: inserting it inserts
+/// a matching [`LinkedPortalCamera`] on the [`Portal`], and removing it (or replacing it with
+/// another [`Portal`]) removes that [`LinkedPortalCamera`] again.
+///
+/// Components of the primary camera other than the ones copied at spawn can be inherited, see the
+/// [`inherit`] module.
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+#[require(Camera3d, PortalProjection)]
+#[component(on_insert = link_portal_camera, on_replace = unlink_portal_camera)]
+pub struct PortalCamera(pub Entity);
+
+/// Component holding the entity with a [`Portal`]'s [`PortalCamera`].
+///
+/// This is the portal side of the link between a [`Portal`] and its camera, and is kept in sync
+/// with [`PortalCamera`], so it can't be inserted manually. Portals reusing the camera of another
+/// portal (see [`Portal::share_camera`]) don't have one, until the camera is handed over to them.
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+#[reflect(Component)]
+pub struct LinkedPortalCamera(Entity);
+
+impl LinkedPortalCamera {
+    /// Returns the entity with the [`PortalCamera`].
+    #[inline]
+    #[must_use]
+    pub fn get(&self) -> Entity {
+        self.0
+    }
+}
+
+/// Hook that inserts a [`LinkedPortalCamera`] on the [`Portal`] of an inserted [`PortalCamera`].
+fn link_portal_camera(mut world: DeferredWorld, camera: Entity, _: ComponentId) {
+    let Some(&PortalCamera(portal)) = world.get::<PortalCamera>(camera) else {
+        return;
+    };
+    world.commands().queue(move |world: &mut World| {
+        if let Ok(mut portal) = world.get_entity_mut(portal) {
+            portal.insert(LinkedPortalCamera(camera));
+        }
+    });
+}
+
+/// Hook that removes the [`LinkedPortalCamera`] of the [`Portal`] of a removed or replaced
+/// [`PortalCamera`], unless the [`Portal`] was linked to another camera since.
+fn unlink_portal_camera(mut world: DeferredWorld, camera: Entity, _: ComponentId) {
+    let Some(&PortalCamera(portal)) = world.get::<PortalCamera>(camera) else {
+        return;
+    };
+    // The portal may be in the middle of being despawned
+    world.commands().queue(move |world: &mut World| {
+        let Ok(mut portal) = world.get_entity_mut(portal) else {
+            return;
+        };
+        if portal.get::<LinkedPortalCamera>() == Some(&LinkedPortalCamera(camera)) {
+            portal.remove::<LinkedPortalCamera>();
+        }
+    });
+}
+
+/// Component holding the image a [`Portal`]'s [`PortalCamera`] renders to.
+///
+/// This is inserted next to every [`Portal`], e.g. to bind the image in a material of your own
+/// (see [`Portal::manual_material`]).
+///
+/// # Lifetime
+///
+/// [`PortalImage::image`] is a strong handle, so the image stays alive for as long as this
+/// component does, regardless of the [`PortalCamera`]'s [`RenderTarget`]. Once the [`Portal`] is
+/// removed, the image is kept by the [`PortalImagePool`] to be reused by the next [`Portal`], until
+/// [`PortalImagePool::drop_available`] is called. Clone the handle to keep the image alive beyond
+/// that, e.g. to show the last frame of a despawned portal.
+#[derive(Component, Reflect, Debug, Clone)]
+#[reflect(Component)]
+pub struct PortalImage {
+    /// The image rendered to, which may be shared with other portals.
+    pub image: Handle<Image>,
+    /// The area of [`PortalImage::image`] the portal is rendered to, packed as
+    /// `(x, y, width, height)` in UV coordinates.
+    ///
+    /// This only differs from the whole image if [`Portal::atlas`] is enabled, or if the image is
+    /// over-allocated, see [`PortalImageAllocation::MaxMonitorSize`].
+    pub uv_rect: Vec4,
+}
+
+/// A [`CameraProjection`] used by portal cameras, that clips everything behind the portal's target.
+///
+/// The near plane of [`PortalProjection::projection`] is replaced by
+/// [`PortalProjection::near_clip_plane`], forming an oblique view frustum. This makes sure that
+/// nothing between the camera and the target surface is rendered.
+///
+/// See <https://terathon.com/lengyel/Lengyel-Oblique.pdf> for details.
+#[derive(Component, Reflect, Debug, Clone, Default)]
+#[reflect(Component, Default)]
+pub struct PortalProjection {
+    /// The projection used before clipping.
+    pub projection: Projection,
+    /// The plane to clip at, in view space.
+    ///
+    /// Points `p` where `near_clip_plane.dot(p.extend(1.0))` is negative are clipped. If set to
+    /// `None`, [`PortalProjection::projection`] is used as-is.
+    ///
+    /// This is set internally every frame.
+    pub near_clip_plane: Option<Vec4>,
+}
+
+impl PortalProjection {
+    /// Replaces the near plane of `clip_from_view` with [`PortalProjection::near_clip_plane`].
+    fn oblique(&self, clip_from_view: Mat4) -> Mat4 {
+        let Some(plane) = self.near_clip_plane else {
+            return clip_from_view;
+        };
+
+        // Scale the plane so that the far corners of the frustum (`z = 0` with reverse-z) are still
+        // in front of the new far plane. For infinite projections, these corners are directions.
+        let view_from_clip = clip_from_view.inverse();
+        let Some(scale) = [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)]
+            .into_iter()
+            .map(|(x, y)| plane.dot(view_from_clip * Vec4::new(x, y, 0.0, 1.0)))
+            .filter(|distance| *distance > 0.0)
+            .map(f32::recip)
+            .reduce(f32::min)
+        else {
+            return clip_from_view;
+        };
+
+        // With reverse-z, the near plane is `w - z`
+        let mut rows = clip_from_view.transpose();
+        rows.z_axis = rows.w_axis - plane * scale;
+        rows.transpose()
+    }
+}
+
+impl CameraProjection for PortalProjection {
+    fn get_clip_from_view(&self) -> Mat4 {
+        self.oblique(self.projection.get_clip_from_view())
+    }
+
+    fn get_clip_from_view_for_sub(&self, sub_view: &SubCameraView) -> Mat4 {
+        self.oblique(self.projection.get_clip_from_view_for_sub(sub_view))
+    }
+
+    fn update(&mut self, width: f32, height: f32) {
+        self.projection.update(width, height);
+    }
+
+    fn far(&self) -> f32 {
+        self.projection.far()
+    }
+
+    fn get_frustum_corners(&self, z_near: f32, z_far: f32) -> [Vec3A; 8] {
+        self.projection.get_frustum_corners(z_near, z_far)
+    }
+}
+
+/// Material used for a [`Portal`]'s mesh.
+#[derive(Asset, AsBindGroup, Clone, TypePath)]
+#[bind_group_data(PortalMaterialKey)]
+pub struct PortalMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    base_color_texture: Option<Handle<Image>>,
+    /// Specifies which side of the portal to cull: "front", "back", or neither.
+    ///
+    /// If set to `None`, both sides of the portal’s mesh will be rendered.
+    ///
+    /// This field's value is inherited from what is set on [`Portal`], but not kept in sync.
+    ///
+    /// Defaults to `Some(Face::Back)`, similar to [`StandardMaterial::cull_mode`] and [`Portal`].
+    pub cull_mode: Option<Face>,
+    /// Whether the image is flipped horizontally, which is needed for a [`Mirror`].
+    ///
+    /// Defaults to `false`.
+    pub mirror: bool,
+    /// How the image is mapped onto the mesh: by the position on screen, or by the mesh's UVs.
+    ///
+    /// This field's value is inherited from what is set on [`Portal`], but not kept in sync.
+    /// [`PortalSurfaceMapping::Planar`] only works for materials on a [`Portal`] entity.
+    ///
+    /// Defaults to [`PortalSurfaceMapping::ScreenSpace`].
+    pub surface_mapping: PortalSurfaceMapping,
+    /// Whether the portal's mesh writes to the depth buffer.
+    ///
+    /// Disable this to keep the portal from occluding transparent effects in front of it, e.g. fog
+    /// volumes or particles at the threshold.
+    ///
+    /// Defaults to `true`.
+    pub depth_write: bool,
+    /// A bias added to the depth of the portal's mesh, similar to [`StandardMaterial::depth_bias`].
+    ///
+    /// Use this to avoid z-fighting with geometry in the same plane, e.g. a frame or a decal.
+    ///
+    /// Defaults to `0.0`.
+    pub depth_bias: f32,
+    /// The area of the image that is sampled, packed as `(x, y, width, height)` in UV coordinates.
+    #[uniform(2)]
+    uv_rect: Vec4,
+    /// How far the portal's mesh is opened, from `0.0` (closed) to `1.0` (open).
+    ///
+    /// The visible area is a circle around the center of the mesh's UVs, which covers the whole
+    /// mesh at `1.0`. Meshes without UVs are always fully open.
+    ///
+    /// This field's value is kept in sync with [`PortalAperture`].
+    ///
+    /// Defaults to `1.0`.
+    #[uniform(2)]
+    pub aperture: f32,
+    /// Maps world space positions to image UVs for [`PortalSurfaceMapping::Planar`].
+    ///
+    /// This is set internally whenever the [`Portal`]'s [`GlobalTransform`] or [`Aabb`] changes.
+    #[uniform(2)]
+    planar_from_world: Mat4,
+    /// The color of the glowing rim along the edges of the mesh, and of the opening while the
+    /// [`PortalAperture`] is partially closed.
+    ///
+    /// The alpha channel controls how strongly the rim covers the image. Use values above `1.0`
+    /// for a bloom effect on HDR cameras.
+    ///
+    /// Defaults to [`LinearRgba::NONE`].
+    #[uniform(2)]
+    pub rim_color: LinearRgba,
+    /// The width of the rim, in UV coordinates of the mesh.
+    ///
+    /// The rim fades out towards the inside of the mesh. Meshes without UVs have no rim.
+    ///
+    /// Defaults to `0.0`.
+    #[uniform(2)]
+    pub rim_width: f32,
+    /// The color the image is multiplied by.
+    ///
+    /// Defaults to [`LinearRgba::WHITE`].
+    #[uniform(2)]
+    pub tint: LinearRgba,
+    /// The factor the brightness of the image is multiplied by.
+    ///
+    /// Defaults to `1.0`.
+    #[uniform(2)]
+    pub brightness: f32,
+    /// The factor the contrast of the image is scaled by, around a mid-grey of `0.5`.
+    ///
+    /// Defaults to `1.0`.
+    #[uniform(2)]
+    pub contrast: f32,
+    /// The radius the image is blurred by, in pixels of the image, e.g. for frosted glass.
+    ///
+    /// The blur takes 16 samples of the image per fragment, so large radii look grainy. Use a
+    /// lower [`Portal::resolution_scale`] along with it to blur more smoothly and cheaply.
+    ///
+    /// Defaults to `0.0`.
+    #[uniform(2)]
+    pub blur_radius: f32,
+    /// How far the image is distorted, in UV coordinates, e.g. for a watery shimmer.
+    ///
+    /// A value of `0.0` disables the distortion.
+    ///
+    /// Defaults to `0.0`.
+    #[uniform(2)]
+    pub distortion_amplitude: f32,
+    /// The number of ripples across the image.
+    ///
+    /// This is ignored if [`PortalMaterial::distortion_texture`] is set.
+    ///
+    /// Defaults to `10.0`.
+    #[uniform(2)]
+    pub distortion_frequency: f32,
+    /// How fast the distortion moves across the image, in UV coordinates per second.
+    ///
+    /// Defaults to [`Vec2::ZERO`].
+    #[uniform(2)]
+    pub distortion_scroll: Vec2,
+    /// A texture used to offset the image instead of ripples, e.g. a noise or normal map.
+    ///
+    /// Its red and green channels are mapped from `0.0..1.0` to an offset of `-1.0..1.0`, scaled
+    /// by [`PortalMaterial::distortion_amplitude`].
+    ///
+    /// Defaults to `None`.
+    #[texture(3)]
+    #[sampler(4)]
+    pub distortion_texture: Option<Handle<Image>>,
+    /// How far the image is twisted around the center of the mesh, in radians at the center.
+    ///
+    /// The twist falls off towards the edges, forming a vortex. Meshes without UVs don't swirl.
+    ///
+    /// Defaults to `0.0`.
+    #[uniform(2)]
+    pub swirl_strength: f32,
+    /// How fast the twist of [`PortalMaterial::swirl_strength`] sways back and forth, in radians
+    /// per second.
+    ///
+    /// Defaults to `0.0`.
+    #[uniform(2)]
+    pub swirl_speed: f32,
+    /// How far the image is blended to [`PortalMaterial::fade_color`], from `0.0` to `1.0`.
+    ///
+    /// This field's value is kept in sync with [`PortalDistanceFade`], if present.
+    ///
+    /// Defaults to `0.0`.
+    #[uniform(2)]
+    pub fade: f32,
+    /// The color the image is blended to by [`PortalMaterial::fade`].
+    ///
+    /// This field's value is kept in sync with [`PortalDistanceFade`], if present.
+    ///
+    /// Defaults to [`LinearRgba::BLACK`].
+    #[uniform(2)]
+    pub fade_color: LinearRgba,
+    /// How far the portal's mesh is dissolved, from `0.0` (solid) to `1.0` (gone).
+    ///
+    /// Parts of the mesh where the noise of [`PortalMaterial::dissolve_texture`] is below this
+    /// value are cut away, with the rim (see [`PortalMaterial::rim_width`]) along the cutoff.
+    /// Meshes without UVs don't dissolve.
+    ///
+    /// This field's value is kept in sync with [`PortalDissolve`], if present.
+    ///
+    /// Defaults to `0.0`.
+    #[uniform(2)]
+    pub dissolve: f32,
+    /// The width of the border along the dissolve cutoff, in noise values.
+    ///
+    /// Defaults to `0.05`.
+    #[uniform(2)]
+    pub dissolve_edge_width: f32,
+    /// The noise texture driving [`PortalMaterial::dissolve`], sampled by the mesh's UVs.
+    ///
+    /// Only its red channel is used. If set to `None`, a procedural noise is used instead.
+    ///
+    /// Defaults to `None`.
+    #[texture(5)]
+    #[sampler(6)]
+    pub dissolve_texture: Option<Handle<Image>>,
+    /// The alpha value of [`PortalMaterial::mask_texture`] below which fragments are discarded.
+    ///
+    /// Defaults to `0.5`.
+    #[uniform(2)]
+    pub mask_cutoff: f32,
+    /// How far the portal's mesh is pushed away from the camera while the camera is closer than
+    /// this to the mesh.
+    ///
+    /// This keeps the camera's near plane from cutting through the mesh as the camera walks
+    /// through the portal, which would otherwise show what is behind the portal for a frame. Since
+    /// the image is mapped by the position on screen, moving the mesh doesn't change what is shown.
+    /// The mesh is moved along its normals, so this only works for flat meshes.
+    ///
+    /// This field's value is inherited from the near plane of [`Portal::primary_camera`], but not
+    /// kept in sync.
+    ///
+    /// Defaults to `0.0`, which disables it.
+    #[uniform(2)]
+    pub crossing_offset: f32,
+    /// A texture shaping the portal's opening, sampled by the mesh's UVs.
+    ///
+    /// Fragments where its alpha channel is below [`PortalMaterial::mask_cutoff`] are discarded,
+    /// which allows round or irregular portals on a simple quad. The rim (see
+    /// [`PortalMaterial::rim_width`]) is drawn over the mask's soft edge, from the cutoff to fully
+    /// opaque. Meshes without UVs aren't masked.
+    ///
+    /// Defaults to `None`.
+    #[texture(7)]
+    #[sampler(8)]
+    pub mask_texture: Option<Handle<Image>>,
+}
+
+impl PortalMaterial {
+    /// Creates a new [`PortalMaterial`] sampling a given `image`, e.g. the image of a
+    /// [`PortalCamera`].
+    ///
+    /// Use this to show a portal's view on other meshes, such as a TV screen or a scope, with
+    /// [`PortalSurfaceMapping::MeshUv`].
+    #[inline]
+    #[must_use]
+    pub fn new(image: Handle<Image>) -> Self {
+        Self {
+            base_color_texture: Some(image),
+            cull_mode: Some(Face::Back),
+            mirror: false,
+            surface_mapping: PortalSurfaceMapping::ScreenSpace,
+            depth_write: true,
+            depth_bias: 0.0,
+            uv_rect: Vec4::new(0.0, 0.0, 1.0, 1.0),
+            aperture: 1.0,
+            planar_from_world: Mat4::IDENTITY,
+            rim_color: LinearRgba::NONE,
+            rim_width: 0.0,
+            tint: LinearRgba::WHITE,
+            brightness: 1.0,
+            contrast: 1.0,
+            blur_radius: 0.0,
+            distortion_amplitude: 0.0,
+            distortion_frequency: 10.0,
+            distortion_scroll: Vec2::ZERO,
+            distortion_texture: None,
+            swirl_strength: 0.0,
+            swirl_speed: 0.0,
+            fade: 0.0,
+            fade_color: LinearRgba::BLACK,
+            dissolve: 0.0,
+            dissolve_edge_width: 0.05,
+            dissolve_texture: None,
+            mask_cutoff: 0.5,
+            crossing_offset: 0.0,
+            mask_texture: None,
+        }
+    }
+
+    /// Applies a preset of an animated vortex with a glowing rim, the classic "portal look".
+    ///
+    /// This overrides the swirl, distortion and rim, which can be tweaked further with the other
+    /// `with_*` methods. To apply it to the material of a [`Portal`], replace the material in
+    /// [`Assets<PortalMaterial>`] by `material.clone().swirl()`.
+    #[inline]
+    #[must_use]
+    pub fn swirl(self) -> Self {
+        self.with_swirl(4.0, 1.5)
+            .with_distortion(0.004, Vec2::new(0.05, 0.1))
+            .with_rim(LinearRgba::rgb(0.6, 0.2, 4.0), 0.12)
+    }
+
+    /// Sets [`PortalMaterial::surface_mapping`].
+    #[inline]
+    #[must_use]
+    pub fn with_surface_mapping(mut self, surface_mapping: PortalSurfaceMapping) -> Self {
+        self.surface_mapping = surface_mapping;
+        self
+    }
+
+    /// Sets [`PortalMaterial::rim_color`] and [`PortalMaterial::rim_width`].
+    #[inline]
+    #[must_use]
+    pub fn with_rim(mut self, rim_color: impl Into<LinearRgba>, rim_width: f32) -> Self {
+        self.rim_color = rim_color.into();
+        self.rim_width = rim_width;
+        self
+    }
+
+    /// Sets [`PortalMaterial::tint`].
+    #[inline]
+    #[must_use]
+    pub fn with_tint(mut self, tint: impl Into<LinearRgba>) -> Self {
+        self.tint = tint.into();
+        self
+    }
+
+    /// Sets [`PortalMaterial::brightness`] and [`PortalMaterial::contrast`].
+    #[inline]
+    #[must_use]
+    pub fn with_brightness_contrast(mut self, brightness: f32, contrast: f32) -> Self {
+        self.brightness = brightness;
+        self.contrast = contrast;
+        self
+    }
+
+    /// Sets [`PortalMaterial::blur_radius`].
+    #[inline]
+    #[must_use]
+    pub fn with_blur(mut self, blur_radius: f32) -> Self {
+        self.blur_radius = blur_radius;
+        self
+    }
+
+    /// Sets [`PortalMaterial::distortion_amplitude`] and [`PortalMaterial::distortion_scroll`].
+    #[inline]
+    #[must_use]
+    pub fn with_distortion(mut self, amplitude: f32, scroll: Vec2) -> Self {
+        self.distortion_amplitude = amplitude;
+        self.distortion_scroll = scroll;
+        self
+    }
+
+    /// Sets [`PortalMaterial::mask_texture`].
+    #[inline]
+    #[must_use]
+    pub fn with_mask(mut self, mask_texture: Handle<Image>) -> Self {
+        self.mask_texture = Some(mask_texture);
+        self
+    }
+
+    /// Sets [`PortalMaterial::depth_write`] and [`PortalMaterial::depth_bias`].
+    #[inline]
+    #[must_use]
+    pub fn with_depth(mut self, depth_write: bool, depth_bias: f32) -> Self {
+        self.depth_write = depth_write;
+        self.depth_bias = depth_bias;
+        self
+    }
+
+    /// Sets [`PortalMaterial::swirl_strength`] and [`PortalMaterial::swirl_speed`].
+    #[inline]
+    #[must_use]
+    pub fn with_swirl(mut self, strength: f32, speed: f32) -> Self {
+        self.swirl_strength = strength;
+        self.swirl_speed = speed;
+        self
+    }
+
+    /// Sets [`PortalMaterial::crossing_offset`].
+    #[inline]
+    #[must_use]
+    pub fn with_crossing_offset(mut self, crossing_offset: f32) -> Self {
+        self.crossing_offset = crossing_offset;
+        self
+    }
+
+    /// Returns the image sampled by this material.
+    #[inline]
+    pub fn image(&self) -> Option<&Handle<Image>> {
+        self.base_color_texture.as_ref()
+    }
+}
+
+impl Material for PortalMaterial {
+    fn vertex_shader() -> ShaderRef {
+        PORTAL_SHADER_HANDLE.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        PORTAL_SHADER_HANDLE.into()
+    }
+
+    fn depth_bias(&self) -> f32 {
+        self.depth_bias
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        descriptor.primitive.cull_mode = key.bind_group_data.cull_mode;
+        if let Some(depth_stencil) = descriptor.depth_stencil.as_mut() {
+            depth_stencil.depth_write_enabled = key.bind_group_data.depth_write;
+            depth_stencil.bias.constant = key.bind_group_data.depth_bias;
+        }
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            if key.bind_group_data.mirror {
+                fragment.shader_defs.push("PORTAL_MIRROR".into());
+            }
+            if key.bind_group_data.distortion_texture {
+                fragment
+                    .shader_defs
+                    .push("PORTAL_DISTORTION_TEXTURE".into());
+            }
+            if key.bind_group_data.dissolve_texture {
+                fragment.shader_defs.push("PORTAL_DISSOLVE_TEXTURE".into());
+            }
+            if key.bind_group_data.mask_texture {
+                fragment.shader_defs.push("PORTAL_MASK_TEXTURE".into());
+            }
+            match key.bind_group_data.surface_mapping {
+                PortalSurfaceMapping::ScreenSpace => {}
+                PortalSurfaceMapping::Planar => {
+                    fragment.shader_defs.push("PORTAL_MAPPING_PLANAR".into());
+                }
+                PortalSurfaceMapping::MeshUv => {
+                    fragment.shader_defs.push("PORTAL_MAPPING_MESH_UV".into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct PortalMaterialKey {
+    cull_mode: Option<Face>,
+    mirror: bool,
+    surface_mapping: PortalSurfaceMapping,
+    depth_write: bool,
+    depth_bias: i32,
+    distortion_texture: bool,
+    dissolve_texture: bool,
+    mask_texture: bool,
+}
+
+impl From<&PortalMaterial> for PortalMaterialKey {
+    fn from(material: &PortalMaterial) -> Self {
+        Self {
+            cull_mode: material.cull_mode,
+            mirror: material.mirror,
+            surface_mapping: material.surface_mapping,
+            depth_write: material.depth_write,
+            depth_bias: material.depth_bias as i32,
+            distortion_texture: material.distortion_texture.is_some(),
+            dissolve_texture: material.dissolve_texture.is_some(),
+            mask_texture: material.mask_texture.is_some(),
+        }
+    }
+}
+
+/// A [`Material`] that renders a [`Portal`]'s image, i.e. a [`PortalMaterial`] or an extension of
+/// it.
+///
+/// See the [`extension`] module for details.
+pub trait AsPortalMaterial: Material {
+    /// Returns the [`PortalMaterial`] this material is based on.
+    fn portal_material(&self) -> &PortalMaterial;
+
+    /// Returns the [`PortalMaterial`] this material is based on, mutably.
+    fn portal_material_mut(&mut self) -> &mut PortalMaterial;
+}
+
+impl AsPortalMaterial for PortalMaterial {
+    fn portal_material(&self) -> &PortalMaterial {
+        self
+    }
+
+    fn portal_material_mut(&mut self) -> &mut PortalMaterial {
+        self
+    }
+}
+
+/// Adds the systems that keep materials of type `M` on [`Portal`]s up to date.
+pub(crate) fn add_portal_material_systems<M: AsPortalMaterial>(app: &mut App) {
+    app.add_systems(
+        PostUpdate,
+        (
+            update_portal_material_effects::<M>.after(PortalCameraSystems::UpdateActivity),
+            update_portal_planar_mappings::<M>
+                .after(TransformSystem::TransformPropagate)
+                .after(VisibilitySystems::CalculateBounds),
+            refresh_portal_materials::<M>,
+            sync_portal_material_uv_rects::<M>,
+            sync_changed_portal_materials::<M>,
+        ),
+    );
+}
+
+/// Observer that sets up a [`Portal`] once it is added to an entity.
+fn request_portal_setup(trigger: Trigger<OnAdd, Portal>, mut commands: Commands) {
+    commands.trigger_targets(
+        SetupPortal {
+            report_pending: true,
+        },
+        trigger.entity(),
+    );
+}
+
+/// System that retries setting up [`Portal`]s that are [`PortalSetupState::Pending`], or
+/// [`PortalSetupState::Failed`] and changed since.
+fn retry_portal_setups(
+    mut commands: Commands,
+    portal_query: Query<(Entity, Ref<Portal>, &PortalSetupState)>,
+) {
+    for (entity, portal, setup_state) in &portal_query {
+        let report_pending = match setup_state {
+            PortalSetupState::Pending => false,
+            PortalSetupState::Active => continue,
+            PortalSetupState::Failed if portal.is_changed() => true,
+            PortalSetupState::Failed => continue,
+        };
+        commands.trigger_targets(SetupPortal { report_pending }, entity);
+    }
+}
+
+/// System that is triggered whenever a [`Portal`] needs to be set up, see [`PortalSetupState`].
+///
+/// An image is created based on the primary camera's viewport size. Then, a [`PortalCamera`] is
+/// created, with [`Camera::target`] set to render the [`PortalCamera`]'s view to the image.
+///
+/// Finally, a [`PortalMaterial`] is added to the [`Portal`] entity.
+///
+/// # Notes
+///
+/// * The [`PortalCamera`] will inherit any properties currently present on the primary camera.
+fn setup_portal(
+    trigger: Trigger<SetupPortal>,
+    mut commands: Commands,
+    mut portal_query: Query<(
+        &mut Portal,
+        &mut PortalSetupState,
+        Option<&Mesh3d>,
+        Has<Mirror>,
+        Has<PortalBackFace>,
+        Option<&PortalActivationRange>,
+    )>,
+    primary_camera_query: Query<(
+        &Camera,
+        Option<&Camera3d>,
+        Option<&DebandDither>,
+        Option<&Tonemapping>,
+        Option<&ColorGrading>,
+        Option<&Exposure>,
+        Option<&Msaa>,
+        Option<&Projection>,
+    )>,
+    mut images: ResMut<Assets<Image>>,
+    mut image_pool: ResMut<PortalImagePool>,
+    mut atlas: ResMut<PortalAtlas>,
+    mut portal_materials: ResMut<Assets<PortalMaterial>>,
+    global_transform_query: Query<&GlobalTransform>,
+    render_layers_query: Query<&RenderLayers>,
+    mut view_render_layers: ResMut<ViewRenderLayers>,
+    mut camera_shares: ResMut<PortalCameraShares>,
+    viewport_size: ViewportSize,
+    entities: &Entities,
+    settings: Res<PortalPluginSettings>,
+    mut error_writer: EventWriter<PortalError>,
+) {
+    let entity = trigger.entity();
+    let mut report = |kind| {
+        error_writer.send(PortalError {
+            portal: entity,
+            kind,
+        });
+    };
+
+    let Ok((mut portal, mut setup_state, mesh, mirror, back_face, activation_range)) =
+        portal_query.get_mut(entity)
+    else {
+        return;
+    };
+    if *setup_state == PortalSetupState::Active {
+        return;
+    }
+
+    if mirror && portal.target != entity {
+        portal.target = entity;
+    }
+    // Back faces are turned around to cull the back of their portal, so they keep their cull mode
+    if !back_face && portal.cull_mode == Some(Face::Back) && settings.cull_mode != portal.cull_mode
+    {
+        portal.cull_mode = settings.cull_mode;
+    }
+
+    // Missing entities won't show up later, so only retry once the portal changes
+    let missing = [
+        Some(portal.primary_camera),
+        Some(portal.target),
+        portal.camera,
+    ]
+    .into_iter()
+    .flatten()
+    .find(|&required| !entities.contains(required));
+    if let Some(missing) = missing {
+        if *setup_state != PortalSetupState::Failed {
+            error!("could not setup portal {entity}: entity {missing} does not exist");
+            report(PortalErrorKind::MissingEntity(missing));
+            *setup_state = PortalSetupState::Failed;
+        }
+        return;
+    }
+    let report_pending = trigger.event().report_pending;
+
+    let Ok((
+        primary_camera,
+        camera_3d,
+        deband_dither,
+        tonemapping,
+        color_grading,
+        exposure,
+        msaa,
+        projection,
+    )) = primary_camera_query.get(portal.primary_camera)
+    else {
+        if report_pending {
+            warn!("could not setup portal {entity} yet: primary_camera has no Camera component");
+            report(PortalErrorKind::MissingCamera);
+        }
+        *setup_state = PortalSetupState::Pending;
+        return;
+    };
+
+    let Some(size) = viewport_size.get_viewport_size(primary_camera) else {
+        if report_pending {
+            warn!("could not compute viewport size for portal {entity} yet");
+            report(match primary_camera.target {
+                RenderTarget::Image(_) => PortalErrorKind::UnsupportedRenderTarget,
+                _ => PortalErrorKind::UnknownViewportSize,
+            });
+        }
+        *setup_state = PortalSetupState::Pending;
+        return;
+    };
+
+    let Ok(global_transform) = global_transform_query.get(portal.target).copied() else {
+        if report_pending {
+            warn!(
+                "could not setup portal {entity} yet: portal target is missing a GlobalTransform"
+            );
+            report(PortalErrorKind::MissingTargetTransform);
+        }
+        *setup_state = PortalSetupState::Pending;
+        return;
+    };
+    *setup_state = PortalSetupState::Active;
+
+    // The depth is kept for as long as the portal is set up, so the recursion setup of its camera
+    // and everything rendering it agree on it
+    let recursion_depth = Some(settings.max_recursion_depth)
+        .filter(|&depth| depth > 0 && recursion::has_recursion_proxies(&portal, mirror));
+    match recursion_depth {
+        Some(depth) => commands
+            .entity(entity)
+            .insert(recursion::PortalRecursionDepth(depth)),
+        None => commands
+            .entity(entity)
+            .remove::<recursion::PortalRecursionDepth>(),
+    };
+
+    // Portals sharing a camera render through the camera of the first of them
+    let share_key = (portal.primary_camera, portal.target);
+    let share_camera =
+        portal.share_camera && !portal.atlas && portal.camera.is_none() && portal.image.is_none();
+    let mut spawned_camera = None;
+    let portal_image = match camera_shares.0.get_mut(&share_key).filter(|_| share_camera) {
+        Some(share) => {
+            share.portals.push(entity);
+            share.image.clone()
+        }
+        None => {
+            let use_atlas = portal.atlas && portal.image.is_none();
+            let atlas_cell = use_atlas.then(|| atlas.allocate(entity)).flatten();
+            if use_atlas && atlas_cell.is_none() {
+                warn!("portal atlas is full, portal {entity} uses an image of its own instead");
+            }
+
+            let (image_handle, order, viewport, uv_rect) = match atlas_cell {
+                Some(cell) => {
+                    commands.entity(entity).insert(atlas::PortalAtlasCell(cell));
+                    let (image_handle, viewport) = atlas.cell_target(
+                        &mut image_pool,
+                        &mut images,
+                        cell,
+                        size,
+                        settings
+                            .texture_format
+                            .unwrap_or(TextureFormat::Bgra8UnormSrgb),
+                    );
+                    // Cameras rendering to the same image need distinct orders
+                    (
+                        image_handle,
+                        settings.camera_order_offset - cell as isize,
+                        Some(viewport),
+                        atlas.cell_uv_rect(cell),
+                    )
+                }
+                None => (
+                    match portal.image.clone() {
+                        Some(image_handle) => image_handle,
+                        None => image_pool.acquire(
+                            &mut images,
+                            size,
+                            portal.resolution_scale * settings.resolution_scale,
+                            portal
+                                .texture_format
+                                .or(settings.texture_format)
+                                .unwrap_or_else(|| portal.image_texture_format()),
+                            &portal.image_sampler,
+                        ),
+                    },
+                    settings.camera_order_offset,
+                    None,
+                    Vec4::new(0.0, 0.0, 1.0, 1.0),
+                ),
+            };
+            let projection = portal.limit_view_distance(projection.cloned().unwrap_or_default());
+            let mut portal_camera = match portal.camera {
+                // Only the image and projection of a camera of the user's own are managed
+                Some(camera) => {
+                    let target = RenderTarget::Image(image_handle.clone());
+                    commands
+                        .entity(camera)
+                        .queue(move |mut entity: EntityWorldMut| {
+                            let projection = entity.take::<Projection>().unwrap_or(projection);
+                            if let Some(mut camera) = entity.get_mut::<Camera>() {
+                                camera.target = target;
+                                camera.viewport = viewport;
+                            }
+                            entity.insert(PortalProjection {
+                                projection,
+                                near_clip_plane: None,
+                            });
+                        });
+                    commands.entity(camera)
+                }
+                None => commands.spawn((
+                    Name::new("Portal Camera"),
+                    Camera {
+                        order,
+                        target: RenderTarget::Image(image_handle.clone()),
+                        viewport,
+                        hdr: portal.hdr || primary_camera.hdr,
+                        ..primary_camera.clone()
+                    },
+                    global_transform.compute_transform(),
+                    global_transform,
+                    camera_3d.cloned().unwrap_or_default(),
+                    deband_dither.copied().unwrap_or_default(),
+                    if portal.hdr {
+                        Tonemapping::None
+                    } else {
+                        tonemapping.copied().unwrap_or_default()
+                    },
+                    color_grading.cloned().unwrap_or_default(),
+                    exposure.copied().unwrap_or_default(),
+                    portal.msaa.or(msaa.copied()).unwrap_or_default(),
+                    PortalProjection {
+                        projection,
+                        near_clip_plane: None,
+                    },
+                )),
+            };
+            if let Some(render_layers) = portal.render_layers.clone() {
+                portal_camera.insert(render_layers);
+            }
+            // Inserted last, so that the recursion setup sees the camera's render layers
+            portal_camera.insert(PortalCamera(entity));
+            spawned_camera = Some(portal_camera.id());
+
+            // Cameras render to a single mip level, so the mip chain is an image of its own
+            let mipmap_image = images
+                .get(&image_handle)
+                .filter(|_| portal.mipmaps && atlas_cell.is_none())
+                .map(|image| {
+                    mipmap::new_portal_mipmap_image(
+                        image.texture_descriptor.size,
+                        image.texture_descriptor.format,
+                        &portal.image_sampler,
+                    )
+                });
+            let image_handle = match mipmap_image {
+                Some(mipmap_image) => {
+                    let mipmap_handle = images.add(mipmap_image);
+                    portal_camera.insert(mipmap::PortalMipmaps(mipmap_handle.clone()));
+                    mipmap_handle
+                }
+                None => image_handle,
+            };
+            let portal_image = PortalImage {
+                image: image_handle,
+                uv_rect,
+            };
+            if share_camera {
+                camera_shares.0.insert(
+                    share_key,
+                    PortalCameraShare {
+                        camera: portal_camera.id(),
+                        image: portal_image.clone(),
+                        portals: vec![entity],
+                    },
+                );
+            }
+            portal_image
+        }
+    };
+    let (image_handle, uv_rect) = (portal_image.image.clone(), portal_image.uv_rect);
+    commands.entity(entity).insert((
+        portal_image,
+        PortalLinkedPrimaryCamera(portal.primary_camera),
+    ));
+    if !portal.manual_material && settings.insert_materials {
+        commands
+            .entity(entity)
+            .insert(MeshMaterial3d(portal_materials.add(PortalMaterial {
+                cull_mode: portal.material_cull_mode(),
+                mirror,
+                surface_mapping: portal.surface_mapping,
+                uv_rect,
+                crossing_offset: projection.map_or(0.0, near_plane_radius),
+                ..PortalMaterial::new(image_handle.clone())
+            })));
+    }
+    if let Some(camera) = spawned_camera {
+        commands.trigger_targets(PortalCameraSpawned { camera }, entity);
+    }
+
+    if let Some(back_target) = portal.back_target.filter(|_| !mirror) {
+        let Some(mesh) = mesh else {
+            error!("could not setup back face for portal {entity}: portal is missing a mesh");
+            report(PortalErrorKind::MissingMesh);
+            return;
+        };
+        let mut back_face = commands.spawn((
+            Name::new("Portal Back Face"),
+            PortalBackFace,
+            mesh.clone(),
+            // Turned around, so that the back face becomes the front face
+            Transform::from_rotation(Quat::from_rotation_y(PI)),
+            Portal {
+                target: back_target,
+                cull_mode: Some(Face::Back),
+                camera: None,
+                image: None,
+                secondary_cameras: portal.secondary_cameras.clone(),
+                render_layers: portal.render_layers.clone(),
+                image_sampler: portal.image_sampler.clone(),
+                back_target: None,
+                ..*portal
+            },
+        ));
+        if let Some(activation_range) = activation_range {
+            back_face.insert(*activation_range);
+        }
+        back_face.set_parent(entity);
+    }
+
+    // Recursion proxies stand in for the portal's mesh, which portal cameras mustn't see
+    let hide_from_portal_cameras =
+        recursion_depth.is_some() && !render_layers_query.contains(entity);
+    if portal.secondary_cameras.is_empty() && !hide_from_portal_cameras {
+        return;
+    }
+
+    // Show every primary camera only the portal rendered for it
+    let mut view_render_layer = |commands: &mut Commands, camera: Entity| {
+        view_render_layers.get_or_assign(commands, camera, &render_layers_query)
+    };
+
+    let render_layers =
+        RenderLayers::layer(view_render_layer(&mut commands, portal.primary_camera));
+    commands.entity(entity).insert(render_layers);
+
+    if portal.secondary_cameras.is_empty() {
+        return;
+    }
+
+    let Some(mesh) = mesh else {
+        error!("could not setup secondary cameras for portal {entity}: portal is missing a mesh");
+        report(PortalErrorKind::MissingMesh);
+        return;
+    };
+    for &camera in &portal.secondary_cameras {
+        let render_layers = RenderLayers::layer(view_render_layer(&mut commands, camera));
+        let mut view = commands.spawn((
+            Name::new("Portal View"),
+            mesh.clone(),
+            render_layers,
+            Portal {
+                render_layers: portal.render_layers.clone(),
+                resolution_scale: portal.resolution_scale,
+                msaa: portal.msaa,
+                hdr: portal.hdr,
+                texture_format: portal.texture_format,
+                image_sampler: portal.image_sampler.clone(),
+                mipmaps: portal.mipmaps,
+                scissor: portal.scissor,
+                atlas: portal.atlas,
+                sync_projection: portal.sync_projection,
+                clip_offset: portal.clip_offset,
+                max_view_distance: portal.max_view_distance,
+                surface_mapping: portal.surface_mapping,
+                manual_material: portal.manual_material,
+                share_camera: portal.share_camera,
+                ..Portal::new(camera, portal.target).with_cull_mode(portal.material_cull_mode())
+            },
+        ));
+        if mirror {
+            view.insert(Mirror);
+        }
+        if let Some(activation_range) = activation_range {
+            view.insert(*activation_range);
+        }
+        view.set_parent(entity);
+    }
+}
+
+/// System that tests each [`Portal`]'s world space [`Aabb`] against the [`Frustum`] of its primary
+/// camera.
+fn check_portal_frusta(
+    mut portal_query: Query<(&Portal, &GlobalTransform, &Aabb, &mut PortalInFrustum)>,
+    frustum_query: Query<&Frustum>,
+) {
+    for (portal, global_transform, aabb, mut in_frustum) in &mut portal_query {
+        let Ok(frustum) = frustum_query.get(portal.primary_camera) else {
+            continue;
+        };
+
+        let intersects = frustum.intersects_obb(aabb, &global_transform.affine(), true, true);
+        if in_frustum.0 != intersects {
+            in_frustum.0 = intersects;
+        }
+    }
+}
+
+/// System that checks whether the primary camera is within each [`PortalActivationRange`].
+fn update_portal_activation_ranges(
+    mut portal_query: Query<(
+        &Portal,
+        &GlobalTransform,
+        &PortalActivationRange,
+        &mut PortalInRange,
+    )>,
+    global_transform_query: Query<&GlobalTransform>,
+) {
+    for (portal, global_transform, activation_range, mut in_range) in &mut portal_query {
+        let Ok(primary_camera_transform) = global_transform_query.get(portal.primary_camera) else {
+            continue;
+        };
+
+        let distance = global_transform
+            .translation()
+            .distance(primary_camera_transform.translation());
+        if in_range.0 && distance > activation_range.max_distance {
+            in_range.0 = false;
+        } else if !in_range.0
+            && distance < activation_range.max_distance - activation_range.hysteresis
+        {
+            in_range.0 = true;
+        }
+    }
+}
+
+/// System that updates how far each [`Portal`] with a [`PortalDistanceFade`] is faded.
+fn update_portal_distance_fades(
+    mut portal_query: Query<(
+        &Portal,
+        &GlobalTransform,
+        &PortalDistanceFade,
+        &mut PortalFadeAmount,
+    )>,
+    global_transform_query: Query<&GlobalTransform>,
+) {
+    for (portal, global_transform, distance_fade, mut fade_amount) in &mut portal_query {
+        let Ok(primary_camera_transform) = global_transform_query.get(portal.primary_camera) else {
+            continue;
+        };
+
+        let distance = global_transform
+            .translation()
+            .distance(primary_camera_transform.translation());
+        let fade = ((distance - distance_fade.start)
+            / (distance_fade.end - distance_fade.start).max(f32::EPSILON))
+        .clamp(0.0, 1.0);
+        if fade_amount.0 != fade {
+            fade_amount.0 = fade;
+        }
+    }
+}
+
+/// System that sets [`Camera::is_active`] of a [`PortalCamera`] based on whether its [`Portal`]
+/// passed visibility checks this frame.
+///
+/// This avoids rendering portals that are not on screen, outside of their
+/// [`PortalActivationRange`], [`PortalDisabled`], without a primary camera or target, closed by
+/// their [`PortalAperture`], fully faded by their [`PortalDistanceFade`], or skipped this frame by
+/// their [`PortalUpdateRate`].
+/// Portals exceeding the [`PortalBudget`], or hidden behind other geometry (if
+/// [`PortalPlugin::occlusion_culling`] is enabled), are not rendered either.
+///
+/// # Notes
+///
+/// * A [`Portal`] that is only visible through another [`Portal`] is not rendered, since it is
+///   outside of the primary camera's frustum.
+pub(crate) fn update_portal_camera_activity(
+    portal_query: Query<(
+        Entity,
+        (&Portal, Option<&LinkedPortalCamera>),
+        &ViewVisibility,
+        (&PortalInFrustum, Option<&occlusion::PortalOccluded>),
+        Option<&PortalInRange>,
+        (
+            Has<PortalDisabled>,
+            Has<PortalOrphaned>,
+            Has<PortalTargetMissing>,
+        ),
+        Option<&PortalAperture>,
+        Option<&PortalFadeAmount>,
+        Option<&PortalUpdateRate>,
+        Option<&PortalPriority>,
+        (&GlobalTransform, Option<&Aabb>),
+        Option<&Parent>,
+    )>,
+    disabled_query: Query<(), With<PortalDisabled>>,
+    update_rate_query: Query<&PortalUpdateRate>,
+    priority_query: Query<&PortalPriority>,
+    frame_count: Res<FrameCount>,
+    aperture_query: Query<&PortalAperture>,
+    camera_shares: Res<PortalCameraShares>,
+    budget: Res<PortalBudget>,
+    primary_camera_query: Query<(&Camera, &GlobalTransform), Without<PortalCamera>>,
+    images: Res<Assets<Image>>,
+    mut camera_query: Query<&mut Camera, With<PortalCamera>>,
+) {
+    // Shared cameras are active as long as any of their portals needs them
+    let mut active_cameras: HashMap<Entity, bool> = HashMap::new();
+    // The highest priority and viewport coverage of the portals of each active camera
+    let mut camera_ranks: HashMap<Entity, (PortalPriority, f32)> = HashMap::new();
+    for (
+        entity,
+        (portal, linked_camera),
+        view_visibility,
+        (in_frustum, occluded),
+        in_range,
+        (disabled, orphaned, target_missing),
+        aperture,
+        fade_amount,
+        update_rate,
+        priority,
+        (global_transform, aabb),
+        parent,
+    ) in &portal_query
+    {
+        let Some(linked_camera) = linked_camera
+            .map(LinkedPortalCamera::get)
+            .or_else(|| camera_shares.camera(entity))
+        else {
+            continue;
+        };
+
+        // Secondary views follow the `Portal` they are spawned for
+        let disabled = disabled
+            || orphaned
+            || target_missing
+            || parent.is_some_and(|parent| disabled_query.contains(parent.get()));
+        let closed = PortalAperture::resolve(aperture, parent, &aperture_query) <= 0.0;
+        let faded = fade_amount.is_some_and(|fade_amount| fade_amount.0 >= 1.0);
+        // Views are scheduled by the `Portal` they are spawned for, to render along with it
+        let (update_rate, scheduled_portal) = match (update_rate, parent) {
+            (Some(update_rate), _) => (Some(update_rate), entity),
+            (None, Some(parent)) => (update_rate_query.get(parent.get()).ok(), parent.get()),
+            (None, None) => (None, entity),
+        };
+        let throttled = update_rate
+            .is_some_and(|update_rate| !update_rate.should_update(scheduled_portal, frame_count.0));
+        let is_active = !disabled
+            && !closed
+            && !faded
+            && !throttled
+            && view_visibility.get()
+            && in_frustum.0
+            && !occluded.is_some_and(|occluded| occluded.0)
+            && in_range.is_none_or(|in_range| in_range.0);
+        *active_cameras.entry(linked_camera).or_default() |= is_active;
+
+        if is_active && budget.is_limited() {
+            let priority = priority
+                .or_else(|| parent.and_then(|parent| priority_query.get(parent.get()).ok()))
+                .copied()
+                .unwrap_or_default();
+            let coverage = aabb.map_or(0.0, |aabb| {
+                portal_viewport_coverage(portal, global_transform, aabb, &primary_camera_query)
+            });
+            let rank = camera_ranks
+                .entry(linked_camera)
+                .or_insert((priority, coverage));
+            *rank = (rank.0.max(priority), rank.1.max(coverage));
+        }
+    }
+
+    if budget.is_limited() {
+        budget.deactivate_over_budget(camera_ranks, &mut active_cameras, |linked_camera| {
+            camera_query
+                .get(linked_camera)
+                .map_or(0, |camera| camera_image_memory(camera, &images))
+        });
+    }
+
+    for (linked_camera, is_active) in active_cameras {
+        let Ok(mut camera) = camera_query.get_mut(linked_camera) else {
+            continue;
+        };
+        if camera.is_active != is_active {
+            camera.is_active = is_active;
+        }
+    }
+}
+
+/// Returns the distance from the camera to the farthest corner of the near plane of `projection`.
+///
+/// Orthographic projections have no near plane to speak of around the camera, so `0.0` is returned.
+fn near_plane_radius(projection: &Projection) -> f32 {
+    match projection {
+        Projection::Perspective(perspective) => {
+            let half_height = perspective.near * (perspective.fov * 0.5).tan();
+            let half_width = half_height * perspective.aspect_ratio;
+            Vec3::new(half_width, half_height, perspective.near).length()
+        }
+        Projection::Orthographic(_) => 0.0,
+    }
+}
+
+/// System that applies each [`Portal`]'s [`PortalAperture`], [`PortalDistanceFade`] and
+/// [`PortalDissolve`] to its material.
+fn update_portal_material_effects<M: AsPortalMaterial>(
+    portal_query: Query<
+        (
+            &MeshMaterial3d<M>,
+            Option<&PortalAperture>,
+            Option<(&PortalDistanceFade, &PortalFadeAmount)>,
+            Option<&PortalDissolve>,
+            Option<&Parent>,
+        ),
+        With<Portal>,
+    >,
+    aperture_query: Query<&PortalAperture>,
+    mut materials: ResMut<Assets<M>>,
+) {
+    for (material_handle, aperture, distance_fade, dissolve, parent) in &portal_query {
+        let aperture = PortalAperture::resolve(aperture, parent, &aperture_query);
+        let fade = distance_fade.map(|(distance_fade, fade_amount)| {
+            (fade_amount.0, LinearRgba::from(distance_fade.color))
+        });
+
+        if materials
+            .get(material_handle)
+            .map(AsPortalMaterial::portal_material)
+            .is_none_or(|material| {
+                material.aperture == aperture
+                    && dissolve.is_none_or(|dissolve| material.dissolve == dissolve.0)
+                    && fade.is_none_or(|(fade, fade_color)| {
+                        material.fade == fade && material.fade_color == fade_color
+                    })
+            })
+        {
+            continue;
+        }
+        if let Some(material) = materials.get_mut(material_handle) {
+            let material = material.portal_material_mut();
+            material.aperture = aperture;
+            if let Some(dissolve) = dissolve {
+                material.dissolve = dissolve.0;
+            }
+            if let Some((fade, fade_color)) = fade {
+                material.fade = fade;
+                material.fade_color = fade_color;
+            }
+        }
+    }
+}
+
+/// System that updates [`PortalMaterial::planar_from_world`] for [`Portal`]s using
+/// [`PortalSurfaceMapping::Planar`].
+fn update_portal_planar_mappings<M: AsPortalMaterial>(
+    portal_query: Query<
+        (&GlobalTransform, &Aabb, &MeshMaterial3d<M>),
+        (
+            With<Portal>,
+            Or<(
+                Changed<GlobalTransform>,
+                Changed<Aabb>,
+                Changed<MeshMaterial3d<M>>,
+            )>,
+        ),
+    >,
+    mut materials: ResMut<Assets<M>>,
+) {
+    for (global_transform, aabb, material_handle) in &portal_query {
+        if materials
+            .get(material_handle)
+            .map(AsPortalMaterial::portal_material)
+            .is_none_or(|material| material.surface_mapping != PortalSurfaceMapping::Planar)
+        {
+            continue;
+        }
+
+        // Fit the local x and y extents of the mesh to UVs, with V pointing down
+        let (min, max) = (Vec3::from(aabb.min()), Vec3::from(aabb.max()));
+        let size = (max - min).max(Vec3::splat(f32::EPSILON));
+        let uv_from_local = Mat4::from_scale(Vec3::new(size.x.recip(), -size.y.recip(), 1.0))
+            * Mat4::from_translation(Vec3::new(-min.x, -max.y, 0.0));
+
+        if let Some(material) = materials.get_mut(material_handle) {
+            material.portal_material_mut().planar_from_world =
+                uv_from_local * global_transform.compute_matrix().inverse();
+        }
+    }
+}
+
+/// System that marks materials as changed whenever the image they sample is modified, e.g.
+/// resized.
+fn refresh_portal_materials<M: AsPortalMaterial>(
+    mut image_events: EventReader<AssetEvent<Image>>,
+    portal_query: Query<
+        &MeshMaterial3d<M>,
+        Or<(With<Portal>, With<recursion::PortalRecursionProxy>)>,
+    >,
+    mut materials: ResMut<Assets<M>>,
+) {
+    let modified = image_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    if modified.is_empty() {
+        return;
+    }
+
+    for material_handle in &portal_query {
+        if materials
+            .get(material_handle)
+            .and_then(|material| material.portal_material().image())
+            .is_some_and(|image| modified.contains(&image.id()))
+        {
+            // Blocked on https://github.com/bevyengine/bevy/issues/5069
+            materials.get_mut(material_handle);
+        }
+    }
+}
+
+/// System that keeps the `uv_rect` of each [`Portal`]'s material in sync with its
+/// [`PortalImage::uv_rect`].
+fn sync_portal_material_uv_rects<M: AsPortalMaterial>(
+    portal_query: Query<(&PortalImage, &MeshMaterial3d<M>), Changed<PortalImage>>,
+    mut materials: ResMut<Assets<M>>,
+) {
+    for (portal_image, material_handle) in &portal_query {
+        let Some(material) = materials.get(material_handle) else {
+            continue;
+        };
+        if material.portal_material().uv_rect == portal_image.uv_rect {
+            continue;
+        }
+        if let Some(material) = materials.get_mut(material_handle) {
+            material.portal_material_mut().uv_rect = portal_image.uv_rect;
+        }
+    }
+}
+
+/// System that keeps the material of each [`Portal`] in sync with [`Portal`] fields changed at
+/// runtime, i.e. [`Portal::cull_mode`], [`Portal::back_target`] and [`Portal::surface_mapping`],
+/// as well as the near plane of a new [`Portal::primary_camera`].
+fn sync_changed_portal_materials<M: AsPortalMaterial>(
+    portal_query: Query<(&Portal, &MeshMaterial3d<M>), Changed<Portal>>,
+    projection_query: Query<&Projection, Without<PortalCamera>>,
+    mut materials: ResMut<Assets<M>>,
+) {
+    for (portal, material_handle) in &portal_query {
+        if portal.manual_material {
+            continue;
+        }
+        let Some(material) = materials.get(material_handle).map(M::portal_material) else {
+            continue;
+        };
+        let cull_mode = portal.material_cull_mode();
+        let crossing_offset = projection_query
+            .get(portal.primary_camera)
+            .map_or(material.crossing_offset, near_plane_radius);
+        if material.cull_mode == cull_mode
+            && material.surface_mapping == portal.surface_mapping
+            && material.crossing_offset == crossing_offset
+        {
+            continue;
+        }
+        if let Some(material) = materials.get_mut(material_handle) {
+            let material = material.portal_material_mut();
+            material.cull_mode = cull_mode;
+            material.surface_mapping = portal.surface_mapping;
+            material.crossing_offset = crossing_offset;
+        }
+    }
+}
+
+/// System that is triggered whenever a [`Portal`] component is removed from an entity.
+///
+/// The [`PortalCamera`] is despawned, and its image is released to the [`PortalImagePool`] (or its
+/// cell is freed in the [`PortalAtlas`]). A shared [`PortalCamera`] is handed over to the next
+/// [`Portal`] sharing it instead, if any.
+fn despawn_portal_camera(
+    trigger: Trigger<OnRemove, Portal>,
+    mut commands: Commands,
+    portal_query: Query<(
+        &Portal,
+        Option<&LinkedPortalCamera>,
+        Has<atlas::PortalAtlasCell>,
+    )>,
+    camera_query: Query<&Camera, With<PortalCamera>>,
+    mut image_pool: ResMut<PortalImagePool>,
+    mut atlas: ResMut<PortalAtlas>,
+    mut camera_shares: ResMut<PortalCameraShares>,
+) {
+    let entity = trigger.entity();
+    let (portal, linked_camera, atlas_cell) = portal_query
+        .get(entity)
+        .expect("observer guarantees existence of component");
+    let (linked_camera, camera) = (linked_camera.map(LinkedPortalCamera::get), portal.camera);
+
+    camera_shares.0.retain(|_, share| {
+        share.portals.retain(|&portal| portal != entity);
+        !share.portals.is_empty()
+    });
+    let Some(linked_camera) = linked_camera else {
+        return;
+    };
+    let next_owner = camera_shares
+        .0
+        .values()
+        .find(|share| share.camera == linked_camera)
+        .map(|share| share.portals[0]);
+    if let Some(next_owner) = next_owner.filter(|&next_owner| portal_query.contains(next_owner)) {
+        // Links the camera to the next owner, see `link_portal_camera`
+        commands
+            .entity(linked_camera)
+            .insert(PortalCamera(next_owner));
+        return;
+    }
+
+    if atlas_cell {
+        atlas.free(entity);
+    } else if let Ok(Camera {
+        target: RenderTarget::Image(image_handle),
+        ..
+    }) = camera_query.get(linked_camera)
+    {
+        image_pool.release(image_handle.clone());
+    }
+    if camera == Some(linked_camera) {
+        // The image may be handed out again, so the camera must stop rendering to it
+        commands
+            .entity(linked_camera)
+            .remove::<(PortalCamera, PortalProjection, mipmap::PortalMipmaps)>()
+            .queue(|mut entity: EntityWorldMut| {
+                if let Some(mut camera) = entity.get_mut::<Camera>() {
+                    camera.is_active = false;
+                }
+            });
+        return;
+    }
+    commands.entity(linked_camera).despawn_recursive();
+}
+
+/// System that updates a [`PortalCamera`]'s translation and rotation based on the primary camera.
+///
+/// # Notes
+///
 /// * Both [`Transform`] and [`GlobalTransform`] are updated.
-fn update_portal_camera_transform(
+/// * [`PortalCamera`]s are only updated if the [`Portal`] changed or was linked to the camera, or
+///   if the portal, its target or the primary camera moved.
+pub(crate) fn update_portal_camera_transform(
     primary_camera_transform_query: Query<
-        &GlobalTransform,
+        Ref<GlobalTransform>,
         (With<Camera3d>, Without<PortalCamera>),
     >,
-    portal_query: Query<(&GlobalTransform, &Portal), (Without<Camera3d>, Without<PortalCamera>)>,
-    mut portal_camera_transform_query: Query<
-        (&mut GlobalTransform, &mut Transform),
-        With<PortalCamera>,
+    portal_query: Query<
+        (
+            Ref<GlobalTransform>,
+            Ref<Portal>,
+            Ref<LinkedPortalCamera>,
+            Has<Mirror>,
+        ),
+        (Without<Camera3d>, Without<PortalCamera>),
     >,
+    mut portal_camera_transform_query: Query<(
+        Entity,
+        &PortalCamera,
+        &mut GlobalTransform,
+        &mut Transform,
+    )>,
     target_global_transform_query: Query<
+        Ref<GlobalTransform>,
+        (Without<Camera3d>, Without<PortalCamera>, Without<Portal>),
+    >,
+) {
+    // Each camera only looks up its own portal, so they can be updated in parallel
+    portal_camera_transform_query.par_iter_mut().for_each(
+        |(
+            entity,
+            &PortalCamera(portal_entity),
+            mut portal_camera_global_transform,
+            mut portal_camera_transform,
+        )| {
+            let Ok((portal_global_transform, portal, linked_camera, mirror)) =
+                portal_query.get(portal_entity)
+            else {
+                return;
+            };
+            if linked_camera.get() != entity {
+                return;
+            }
+            let Ok(primary_camera_global_transform) =
+                primary_camera_transform_query.get(portal.primary_camera)
+            else {
+                return;
+            };
+
+            // Mirrors target themselves
+            let target_global_transform = target_global_transform_query.get(portal.target).ok();
+            let target_changed = !mirror
+                && target_global_transform
+                    .as_ref()
+                    .is_some_and(|target_global_transform| target_global_transform.is_changed());
+            if !portal.is_changed()
+                && !linked_camera.is_changed()
+                && !portal_global_transform.is_changed()
+                && !primary_camera_global_transform.is_changed()
+                && !target_changed
+            {
+                return;
+            }
+            let primary_camera_transform = primary_camera_global_transform.compute_transform();
+            let portal_transform = portal_global_transform.compute_transform();
+
+            if mirror {
+                *portal_camera_transform =
+                    reflect_through_mirror(&portal_transform, primary_camera_transform);
+                *portal_camera_global_transform = GlobalTransform::from(*portal_camera_transform);
+                return;
+            }
+
+            let Some(target_global_transform) = target_global_transform else {
+                return;
+            };
+            *portal_camera_transform = transform_through_portal(
+                &portal_transform,
+                &target_global_transform.compute_transform(),
+                primary_camera_transform,
+            );
+            *portal_camera_global_transform = GlobalTransform::from(*portal_camera_transform);
+        },
+    );
+}
+
+/// Maps a `transform` relative to the `portal` into the space of its `target`.
+pub(crate) fn transform_through_portal(
+    portal_transform: &Transform,
+    target_transform: &Transform,
+    transform: Transform,
+) -> Transform {
+    let translation =
+        transform.translation - portal_transform.translation + target_transform.translation;
+
+    let rotation = portal_transform
+        .rotation
+        .inverse()
+        .mul_quat(target_transform.rotation);
+
+    let mut transform = transform.with_translation(translation);
+    transform.rotate_around(target_transform.translation, rotation);
+    transform
+}
+
+/// Maps a linear velocity `linvel` and an angular velocity `angvel` relative to the `portal` into
+/// the space of its `target`, returning them in the same order.
+///
+/// This rotates the velocities the same way [`Portal`]s rotate entities passing through them, so
+/// that e.g. a body falling into a portal on the floor flies out of a portal on a wall. The speed
+/// is kept, but can be scaled by multiplying the results.
+pub fn portal_transform_velocity(
+    portal_transform: &Transform,
+    target_transform: &Transform,
+    linvel: Vec3,
+    angvel: Vec3,
+) -> (Vec3, Vec3) {
+    let rotation = portal_transform
+        .rotation
+        .inverse()
+        .mul_quat(target_transform.rotation);
+    (rotation * linvel, rotation * angvel)
+}
+
+/// Maps a `ray` relative to the `portal` into the space of its `target`.
+///
+/// This is how a ray hitting the portal's mesh continues on the other side, e.g. for bullets or
+/// laser sights. See [`PortalRayCast`](raycast::PortalRayCast) to follow a ray through several
+/// portals.
+pub fn portal_transform_ray(
+    portal_transform: &Transform,
+    target_transform: &Transform,
+    ray: Ray3d,
+) -> Ray3d {
+    let origin = transform_through_portal(
+        portal_transform,
+        target_transform,
+        Transform::from_translation(ray.origin),
+    )
+    .translation;
+    let (direction, _) = portal_transform_velocity(
+        portal_transform,
+        target_transform,
+        *ray.direction,
+        Vec3::ZERO,
+    );
+    // Rotating the direction keeps it normalized
+    Ray3d::new(origin, Dir3::new_unchecked(direction))
+}
+
+/// Computes the view space clipping plane for a camera looking out of `target_transform`.
+///
+/// Everything on the same side of the target as the camera is clipped, except for the `clip_offset`
+/// closest to the target (see [`Portal::clip_offset`]). Returns [`None`] if the camera is within
+/// `clip_offset` of the target's plane.
+pub(crate) fn portal_near_clip_plane(
+    target_transform: &GlobalTransform,
+    camera_transform: &GlobalTransform,
+    clip_offset: f32,
+) -> Option<Vec4> {
+    let normal = target_transform.forward();
+    let plane = normal.extend(-normal.dot(target_transform.translation()));
+    // Planes are transformed by the inverse transpose
+    let plane = camera_transform.compute_matrix().transpose() * plane;
+
+    // The camera is at the origin of view space, so its side of the plane is given by `w`
+    let plane = match plane.w {
+        w if w < 0.0 => plane,
+        w if w > 0.0 => -plane,
+        _ => return None,
+    };
+
+    // Move the plane towards the camera, which has to stay on the clipped side
+    let plane = plane + Vec4::W * clip_offset;
+    (plane.w < 0.0).then_some(plane)
+}
+
+/// System that updates the [`PortalProjection`] near clipping plane for [`PortalCamera`]s.
+///
+/// The [`Frustum`] is then updated by [`update_frusta`], using the clipped projection.
+///
+/// The clipping plane is only updated if the [`Portal`] changed or was linked to the camera, or if
+/// the [`PortalCamera`] or the target moved.
+///
+/// [`Frustum`]: bevy::render::primitives::Frustum
+pub(crate) fn update_portal_camera_frusta(
+    portal_query: Query<(Ref<Portal>, Ref<LinkedPortalCamera>)>,
+    mut projection_query: Query<(
+        Entity,
+        &PortalCamera,
+        Ref<GlobalTransform>,
+        &mut PortalProjection,
+    )>,
+    global_transform_query: Query<Ref<GlobalTransform>, Without<PortalCamera>>,
+) {
+    // Each camera only looks up its own portal, so they can be updated in parallel
+    projection_query.par_iter_mut().for_each(
+        |(entity, &PortalCamera(portal_entity), portal_camera_transform, mut projection)| {
+            let Ok((portal, linked_camera)) = portal_query.get(portal_entity) else {
+                return;
+            };
+            if linked_camera.get() != entity {
+                return;
+            }
+            let Ok(target_transform) = global_transform_query.get(portal.target) else {
+                return;
+            };
+            if !portal.is_changed()
+                && !linked_camera.is_changed()
+                && !portal_camera_transform.is_changed()
+                && !target_transform.is_changed()
+            {
+                return;
+            }
+
+            projection.near_clip_plane = portal_near_clip_plane(
+                &target_transform,
+                &portal_camera_transform,
+                portal.clip_offset,
+            );
+        },
+    );
+}
+
+/// System that fits the side planes of each [`PortalCamera`]'s [`Frustum`] to its [`Portal`].
+///
+/// Only the part of the scene seen through the portal's opening is visible, so the planes go
+/// through the [`PortalCamera`] and the edges of the [`Portal`]'s [`Aabb`], mapped to the target.
+/// The near and far planes are kept.
+///
+/// # Notes
+///
+/// * The [`Aabb`] is assumed to be flat along its local z-axis, like a [`Rectangle`].
+/// * [`PortalCamera`]s shared by several portals are skipped.
+pub(crate) fn fit_portal_camera_frusta(
+    portal_query: Query<(
+        Entity,
+        &Portal,
+        &LinkedPortalCamera,
         &GlobalTransform,
-        (Without<Camera3d>, Without<PortalCamera>, Without<Portal>),
-    >,
+        &Aabb,
+    )>,
+    target_query: Query<&GlobalTransform, Without<PortalCamera>>,
+    mut camera_query: Query<(&GlobalTransform, &mut Frustum), With<PortalCamera>>,
+    camera_shares: Res<PortalCameraShares>,
 ) {
-    for (portal_global_transform, portal) in &portal_query {
-        let Ok(primary_camera_transform) = primary_camera_transform_query
-            .get(portal.primary_camera)
-            .map(GlobalTransform::compute_transform)
-        else {
+    'portals: for (entity, portal, linked_camera, global_transform, aabb) in &portal_query {
+        // Shared cameras see through every portal sharing them
+        if camera_shares.is_shared(entity) {
+            continue;
+        }
+        let Ok((camera_transform, mut frustum)) = camera_query.get_mut(linked_camera.get()) else {
             continue;
         };
-
-        let Some(linked_camera) = portal.linked_camera else {
+        let Ok(target_transform) = target_query.get(portal.target) else {
             continue;
         };
 
-        // `PortalCamera` requires `Camera3d`
-        let (mut portal_camera_global_transform, mut portal_camera_transform) =
-            portal_camera_transform_query
-                .get_mut(linked_camera)
-                .unwrap();
+        let portal_transform = global_transform.compute_transform();
+        let target_transform = target_transform.compute_transform();
+        let (center, half_extents) = (Vec3::from(aabb.center), Vec3::from(aabb.half_extents));
+        let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(x, y)| {
+            let corner =
+                global_transform.transform_point(center + half_extents * Vec3::new(x, y, 0.0));
+            transform_through_portal(
+                &portal_transform,
+                &target_transform,
+                Transform::from_translation(corner),
+            )
+            .translation
+        });
+        let opening_center = corners.iter().sum::<Vec3>() / 4.0;
 
-        let portal_transform = portal_global_transform.compute_transform();
-        // If the `Portal` has a valid `linked_camera`, this is guaranteed.
-        let target_transform = target_global_transform_query
-            .get(portal.target)
-            .unwrap()
-            .compute_transform();
+        let origin = camera_transform.translation();
+        let mut half_spaces = [HalfSpace::default(); 4];
+        for (i, half_space) in half_spaces.iter_mut().enumerate() {
+            // The camera is on the plane of the portal, so there's nothing to fit to
+            let Some(normal) = (corners[i] - origin)
+                .cross(corners[(i + 1) % 4] - origin)
+                .try_normalize()
+            else {
+                continue 'portals;
+            };
+            // Face the normal towards the inside of the frustum
+            let normal = match normal.dot(opening_center - origin) {
+                distance if distance < 0.0 => -normal,
+                _ => normal,
+            };
+            *half_space = HalfSpace::new(normal.extend(-normal.dot(origin)));
+        }
 
-        let translation = primary_camera_transform.translation - portal_transform.translation
-            + target_transform.translation;
+        frustum.half_spaces[..4].copy_from_slice(&half_spaces);
+    }
+}
 
-        let rotation = portal_transform
-            .rotation
-            .inverse()
-            .mul_quat(target_transform.rotation);
+/// System that copies the primary camera's [`Projection`] to [`PortalProjection::projection`] of
+/// [`PortalCamera`]s whenever it changes.
+///
+/// The far plane is limited to [`Portal::max_view_distance`], which is also applied if only the
+/// [`Portal`] changed. [`Portal`]s with [`Portal::sync_projection`] disabled are skipped.
+pub(crate) fn sync_portal_camera_projections(
+    portal_query: Query<(Ref<Portal>, Ref<LinkedPortalCamera>)>,
+    primary_camera_query: Query<Ref<Projection>, Without<PortalCamera>>,
+    mut projection_query: Query<&mut PortalProjection, With<PortalCamera>>,
+) {
+    for (portal, linked_camera) in &portal_query {
+        if !portal.sync_projection {
+            continue;
+        }
+        let Ok(projection) = primary_camera_query.get(portal.primary_camera) else {
+            continue;
+        };
+        if !projection.is_changed() && !portal.is_changed() && !linked_camera.is_changed() {
+            continue;
+        }
+        let Ok(mut portal_projection) = projection_query.get_mut(linked_camera.get()) else {
+            continue;
+        };
 
-        *portal_camera_transform = primary_camera_transform.with_translation(translation);
-        portal_camera_transform.rotate_around(target_transform.translation, rotation);
-        *portal_camera_global_transform = GlobalTransform::from(*portal_camera_transform);
+        portal_projection.projection = portal.limit_view_distance((*projection).clone());
     }
 }
 
-/// System that updates [`Frustum`] for [`PortalCamera`]s.
+/// System that restricts the [`Viewport`] of [`PortalCamera`]s to the screen space bounding
+/// rectangle of their [`Portal`], as seen by the primary camera.
 ///
-/// [`update_frusta`]: bevy::render::view::update_frusta
-fn update_portal_camera_frusta(
-    portal_query: Query<&Portal>,
-    mut frustum_query: Query<&mut Frustum, With<PortalCamera>>,
-    global_transform_query: Query<&GlobalTransform>,
+/// A matching [`SubCameraView`] is set, so the rendered area lines up with the rest of the image.
+///
+/// # Notes
+///
+/// * The whole image is rendered if any corner of the [`Portal`]'s [`Aabb`] is behind the primary
+///   camera, or if the [`PortalCamera`] is shared by several portals.
+pub(crate) fn update_portal_camera_viewports(
+    portal_query: Query<
+        (
+            Entity,
+            &Portal,
+            &LinkedPortalCamera,
+            &GlobalTransform,
+            &Aabb,
+            Has<Mirror>,
+            Has<recursion::PortalRecursionDepth>,
+        ),
+        Without<atlas::PortalAtlasCell>,
+    >,
+    primary_camera_query: Query<(&Camera, &GlobalTransform), Without<PortalCamera>>,
+    mut portal_camera_query: Query<&mut Camera, With<PortalCamera>>,
+    camera_shares: Res<PortalCameraShares>,
+    image_pool: Res<PortalImagePool>,
 ) {
-    for portal in &portal_query {
-        let Some(linked_camera) = portal.linked_camera else {
+    for (entity, portal, linked_camera, global_transform, aabb, mirror, recursive) in &portal_query
+    {
+        let Ok(mut camera) = portal_camera_query.get_mut(linked_camera.get()) else {
+            continue;
+        };
+        let Some(image_size) = camera.physical_target_size() else {
             continue;
         };
+        // Over-allocated images are only rendered to in their top-left corner
+        let target_size = match &camera.target {
+            RenderTarget::Image(image_handle) => image_pool
+                .used_size(image_handle)
+                .map_or(image_size, |used_size| used_size.min(image_size)),
+            _ => image_size,
+        };
+
+        // Recursion proxies and other surface mappings sample the whole image
+        let scissor = portal.scissor
+            && portal.surface_mapping == PortalSurfaceMapping::ScreenSpace
+            && !recursive;
+        let sub_view = if scissor {
+            let Ok((primary_camera, primary_camera_transform)) =
+                primary_camera_query.get(portal.primary_camera)
+            else {
+                continue;
+            };
+            let clip_from_world = primary_camera.clip_from_view()
+                * primary_camera_transform.compute_matrix().inverse()
+                * global_transform.compute_matrix();
+            // Shared cameras render the whole image, for every portal sharing them
+            portal_screen_rect(&clip_from_world, aabb, target_size, mirror)
+                .filter(|_| !camera_shares.is_shared(entity))
+                .map(|(offset, size)| SubCameraView {
+                    full_size: target_size,
+                    offset: offset.as_vec2(),
+                    size,
+                })
+        } else {
+            None
+        };
+        let viewport = match sub_view {
+            Some(sub_view) => Some((sub_view.offset.as_uvec2(), sub_view.size)),
+            None => (target_size != image_size).then_some((UVec2::ZERO, target_size)),
+        };
+
+        let current_viewport = camera
+            .viewport
+            .as_ref()
+            .map(|viewport| (viewport.physical_position, viewport.physical_size));
+        if camera.sub_camera_view != sub_view || current_viewport != viewport {
+            camera.viewport = viewport.map(|(physical_position, physical_size)| Viewport {
+                physical_position,
+                physical_size,
+                ..default()
+            });
+            camera.sub_camera_view = sub_view;
+        }
+    }
+}
 
-        // `PortalCamera` requires `Camera3d`.
-        let mut frustum = frustum_query.get_mut(linked_camera).unwrap();
+/// Computes the offset and size of the rectangle an [`Aabb`] covers within an image of a given
+/// `target_size`, flipped horizontally if `mirror` is set.
+///
+/// Returns [`None`] if any corner is behind the camera, or if the [`Aabb`] is off screen.
+fn portal_screen_rect(
+    clip_from_local: &Mat4,
+    aabb: &Aabb,
+    target_size: UVec2,
+    mirror: bool,
+) -> Option<(UVec2, UVec2)> {
+    let mut min = Vec2::splat(f32::INFINITY);
+    let mut max = Vec2::splat(f32::NEG_INFINITY);
+    for corner in 0..8 {
+        let sign = Vec3A::new(
+            if corner & 1 == 0 { -1.0 } else { 1.0 },
+            if corner & 2 == 0 { -1.0 } else { 1.0 },
+            if corner & 4 == 0 { -1.0 } else { 1.0 },
+        );
+        let clip = *clip_from_local * (aabb.center + sign * aabb.half_extents).extend(1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let ndc = clip.xy() / clip.w;
+        min = min.min(ndc);
+        max = max.max(ndc);
+    }
 
-        // If the `Portal` has a valid `linked_camera`, this is guaranteed.
-        let (target_transform, portal_camera_transform) = global_transform_query
-            .get_many([portal.target, linked_camera])
-            .map(|[t, c]| (t.compute_transform(), c.compute_transform()))
-            .unwrap();
+    let min = min.clamp(Vec2::NEG_ONE, Vec2::ONE);
+    let max = max.clamp(Vec2::NEG_ONE, Vec2::ONE);
+    if min.cmpge(max).any() {
+        return None;
+    }
 
-        // Set the near clip plane
-        let normal = -target_transform.forward().normalize_or_zero();
-        let distance =
-            -((target_transform.translation - portal_camera_transform.translation).dot(normal));
-        frustum.half_spaces[4] = HalfSpace::new(normal.extend(distance));
+    // Normalized device coordinates have y pointing up, whereas pixels have y pointing down
+    let target_size_f32 = target_size.as_vec2();
+    let to_pixels = |ndc: Vec2| Vec2::new(ndc.x + 1.0, 1.0 - ndc.y) * 0.5 * target_size_f32;
+    let mut end = to_pixels(Vec2::new(max.x, min.y))
+        .ceil()
+        .as_uvec2()
+        .min(target_size);
+    let mut start = to_pixels(Vec2::new(min.x, max.y))
+        .floor()
+        .as_uvec2()
+        .min(end - UVec2::ONE);
+    if mirror {
+        (start.x, end.x) = (target_size.x - end.x, target_size.x - start.x);
     }
+
+    Some((start, (end - start).max(UVec2::ONE)))
 }
 
-fn resize_portal_images(
-    mut resized_reader: EventReader<WindowResized>,
-    window_query: Query<&Window>,
-    portal_query: Query<(&Portal, &MeshMaterial3d<PortalMaterial>)>,
-    camera_query: Query<&Camera>,
+/// System that deactivates [`Portal`]s whose [`Portal::primary_camera`] no longer exists, and
+/// links them to a new primary camera once one is assigned.
+///
+/// # Notes
+///
+/// * When linked to a new primary camera, the image is resized to its viewport, but the
+///   [`PortalCamera`] keeps the other properties inherited from the previous primary camera.
+fn update_portal_primary_cameras(
+    mut commands: Commands,
+    portal_query: Query<(
+        Entity,
+        &Portal,
+        Option<&LinkedPortalCamera>,
+        Has<PortalOrphaned>,
+        Has<atlas::PortalAtlasCell>,
+    )>,
+    primary_camera_query: Query<&Camera, Without<PortalCamera>>,
+    camera_query: Query<&Camera, With<PortalCamera>>,
+    mut lost_writer: EventWriter<PortalPrimaryCameraLost>,
+    mut image_pool: ResMut<PortalImagePool>,
+    viewport_size: ViewportSize,
     mut images: ResMut<Assets<Image>>,
-    mut portal_materials: ResMut<Assets<PortalMaterial>>,
 ) {
-    for event in resized_reader.read() {
-        let window_size = window_query.get(event.window).unwrap().physical_size();
-        let size = Extent3d {
-            width: window_size.x,
-            height: window_size.y,
-            ..default()
+    for (entity, portal, linked_camera, orphaned, atlas_cell) in &portal_query {
+        let primary_camera = primary_camera_query.get(portal.primary_camera).ok();
+        match (primary_camera, orphaned) {
+            (None, false) => {
+                warn!(
+                    "primary camera {} of portal {entity} was despawned, deactivating its camera",
+                    portal.primary_camera
+                );
+                commands.entity(entity).insert(PortalOrphaned);
+                lost_writer.send(PortalPrimaryCameraLost {
+                    portal: entity,
+                    primary_camera: portal.primary_camera,
+                });
+            }
+            (Some(primary_camera), true) => {
+                commands.entity(entity).remove::<PortalOrphaned>();
+
+                // The atlas image follows the window size instead
+                if atlas_cell {
+                    continue;
+                }
+                let Some(size) = viewport_size.get_viewport_size(primary_camera) else {
+                    continue;
+                };
+                let Some(Camera {
+                    target: RenderTarget::Image(image_handle),
+                    ..
+                }) = linked_camera
+                    .and_then(|linked_camera| camera_query.get(linked_camera.get()).ok())
+                else {
+                    continue;
+                };
+                image_pool.resize_image(&mut images, image_handle, size);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// System that sets up the [`PortalCamera`] of each [`Portal`] again whose
+/// [`Portal::primary_camera`] was changed.
+///
+/// The [`PortalCamera`] inherits the properties of the new primary camera, and its image is resized
+/// to the new viewport. A new [`Portal::target`] needs no setup, since the [`PortalCamera`] follows
+/// it from the next frame on.
+///
+/// # Notes
+///
+/// * [`Portal`]s sharing a [`PortalCamera`] (see [`Portal::share_camera`]) keep rendering through
+///   it, so their primary camera shouldn't be changed.
+fn relink_changed_portals(
+    mut commands: Commands,
+    mut portal_query: Query<
+        (
+            Entity,
+            &Portal,
+            Option<&LinkedPortalCamera>,
+            &mut PortalLinkedPrimaryCamera,
+            Has<atlas::PortalAtlasCell>,
+        ),
+        Changed<Portal>,
+    >,
+    primary_camera_query: Query<
+        (
+            &Camera,
+            Option<&Camera3d>,
+            Option<&DebandDither>,
+            Option<&Tonemapping>,
+            Option<&ColorGrading>,
+            Option<&Exposure>,
+            Option<&Msaa>,
+            Option<&Projection>,
+        ),
+        Without<PortalCamera>,
+    >,
+    mut camera_query: Query<&mut Camera, With<PortalCamera>>,
+    camera_shares: Res<PortalCameraShares>,
+    mut image_pool: ResMut<PortalImagePool>,
+    viewport_size: ViewportSize,
+    mut images: ResMut<Assets<Image>>,
+) {
+    for (entity, portal, linked_camera, mut linked_primary_camera, atlas_cell) in &mut portal_query
+    {
+        let primary_camera = primary_camera_query.get(portal.primary_camera).ok();
+        if linked_primary_camera.0 == portal.primary_camera || camera_shares.is_shared(entity) {
+            continue;
+        }
+        let Some((
+            primary_camera,
+            camera_3d,
+            deband_dither,
+            tonemapping,
+            color_grading,
+            exposure,
+            msaa,
+            projection,
+        )) = primary_camera
+        else {
+            // Missing primary cameras are handled by `update_portal_primary_cameras`
+            continue;
+        };
+        let Some(linked_camera) = linked_camera.map(LinkedPortalCamera::get) else {
+            continue;
+        };
+        let Ok(mut camera) = camera_query.get_mut(linked_camera) else {
+            continue;
         };
+        linked_primary_camera.0 = portal.primary_camera;
 
-        for (portal, portal_material_handle) in &portal_query {
-            let Some(camera) = portal.linked_camera.and_then(|c| camera_query.get(c).ok()) else {
-                continue;
+        // Cameras of the user's own keep their properties
+        if portal.camera != Some(linked_camera) {
+            *camera = Camera {
+                order: camera.order,
+                target: camera.target.clone(),
+                viewport: camera.viewport.clone(),
+                is_active: camera.is_active,
+                sub_camera_view: camera.sub_camera_view,
+                hdr: portal.hdr || primary_camera.hdr,
+                ..primary_camera.clone()
             };
+            commands.entity(linked_camera).insert((
+                camera_3d.cloned().unwrap_or_default(),
+                deband_dither.copied().unwrap_or_default(),
+                if portal.hdr {
+                    Tonemapping::None
+                } else {
+                    tonemapping.copied().unwrap_or_default()
+                },
+                color_grading.cloned().unwrap_or_default(),
+                exposure.copied().unwrap_or_default(),
+                portal.msaa.or(msaa.copied()).unwrap_or_default(),
+                PortalProjection {
+                    projection: portal.limit_view_distance(projection.cloned().unwrap_or_default()),
+                    near_clip_plane: None,
+                },
+            ));
+        }
 
-            let RenderTarget::Image(ref image_handle) = camera.target else {
-                continue;
-            };
+        // The atlas image follows the window size instead
+        if atlas_cell {
+            continue;
+        }
+        let (Some(size), RenderTarget::Image(image_handle)) = (
+            viewport_size.get_viewport_size(primary_camera),
+            &camera.target,
+        ) else {
+            continue;
+        };
+        image_pool.resize_image(&mut images, image_handle, size);
+    }
+}
 
-            let Some(image) = images.get_mut(image_handle) else {
-                continue;
-            };
+/// System that keeps the [`PortalTarget`] of each [`Portal::target`] up to date.
+fn register_portal_targets(
+    mut commands: Commands,
+    portal_query: Query<(Entity, &Portal, Option<&PortalRegisteredTarget>), Changed<Portal>>,
+    mut target_query: Query<&mut PortalTarget>,
+) {
+    // Targets without a `PortalTarget` yet may be used by several new portals
+    let mut new_targets: HashMap<Entity, Vec<Entity>> = HashMap::new();
+    for (entity, portal, registered_target) in &portal_query {
+        let registered_target = registered_target.map(|registered_target| registered_target.0);
+        if registered_target == Some(portal.target) {
+            continue;
+        }
 
-            image.resize(size);
-            // Blocked on https://github.com/bevyengine/bevy/issues/5069
-            portal_materials.get_mut(portal_material_handle);
+        if let Some(mut target) = registered_target
+            .and_then(|registered_target| target_query.get_mut(registered_target).ok())
+        {
+            target.portals.retain(|&portal| portal != entity);
+        }
+        match target_query.get_mut(portal.target) {
+            Ok(mut target) => target.portals.push(entity),
+            Err(_) => new_targets.entry(portal.target).or_default().push(entity),
+        }
+        commands
+            .entity(entity)
+            .insert(PortalRegisteredTarget(portal.target));
+    }
+
+    for (target, portals) in new_targets {
+        if let Some(mut target) = commands.get_entity(target) {
+            target.try_insert(PortalTarget { portals });
+        }
+    }
+}
+
+/// Observer that removes a [`Portal`] from the [`PortalTarget`] of its target when the [`Portal`]
+/// is removed.
+fn unregister_portal_target(
+    trigger: Trigger<OnRemove, Portal>,
+    portal_query: Query<&PortalRegisteredTarget>,
+    mut target_query: Query<&mut PortalTarget>,
+) {
+    let entity = trigger.entity();
+    let Ok(registered_target) = portal_query.get(entity) else {
+        return;
+    };
+    if let Ok(mut target) = target_query.get_mut(registered_target.0) {
+        target.portals.retain(|&portal| portal != entity);
+    }
+}
+
+/// System that deactivates [`Portal`]s whose [`Portal::target`] is missing, and resumes them once
+/// the target is back.
+fn update_portal_targets(
+    mut commands: Commands,
+    portal_query: Query<(Entity, &Portal, Has<PortalTargetMissing>)>,
+    target_query: Query<(), With<GlobalTransform>>,
+    mut lost_writer: EventWriter<PortalTargetLost>,
+) {
+    for (entity, portal, missing) in &portal_query {
+        match (target_query.contains(portal.target), missing) {
+            (false, false) => {
+                error!(
+                    "target {} of portal {entity} has no GlobalTransform, deactivating its camera",
+                    portal.target
+                );
+                commands.entity(entity).insert(PortalTargetMissing);
+                lost_writer.send(PortalTargetLost {
+                    portal: entity,
+                    target: portal.target,
+                });
+            }
+            (true, true) => {
+                commands.entity(entity).remove::<PortalTargetMissing>();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// System that updates the level of each [`PortalResolutionLod`] based on the distance to the
+/// primary camera, resizing the [`Portal`]'s image whenever it changes.
+pub(crate) fn update_portal_resolution_lods(
+    mut portal_query: Query<
+        (
+            &Portal,
+            Option<&LinkedPortalCamera>,
+            &GlobalTransform,
+            &PortalResolutionLod,
+            &mut PortalLodLevel,
+        ),
+        Without<atlas::PortalAtlasCell>,
+    >,
+    primary_camera_query: Query<(&Camera, &GlobalTransform), Without<PortalCamera>>,
+    camera_query: Query<&Camera, With<PortalCamera>>,
+    mut image_pool: ResMut<PortalImagePool>,
+    viewport_size: ViewportSize,
+    mut images: ResMut<Assets<Image>>,
+    settings: Res<PortalPluginSettings>,
+) {
+    for (portal, linked_camera, global_transform, resolution_lod, mut lod_level) in
+        &mut portal_query
+    {
+        let Ok((primary_camera, primary_camera_transform)) =
+            primary_camera_query.get(portal.primary_camera)
+        else {
+            continue;
+        };
+
+        let distance = global_transform
+            .translation()
+            .distance(primary_camera_transform.translation());
+        let levels = &resolution_lod.levels;
+        let mut level = lod_level.0.min(levels.len());
+        while level < levels.len() && distance > levels[level].0 {
+            level += 1;
+        }
+        while level > 0 && distance < levels[level - 1].0 - resolution_lod.hysteresis {
+            level -= 1;
+        }
+        if level == lod_level.0 {
+            continue;
         }
+
+        let Some(size) = viewport_size.get_viewport_size(primary_camera) else {
+            continue;
+        };
+        let Some(Camera {
+            target: RenderTarget::Image(image_handle),
+            ..
+        }) = linked_camera.and_then(|linked_camera| camera_query.get(linked_camera.get()).ok())
+        else {
+            continue;
+        };
+
+        lod_level.0 = level;
+        image_pool.rescale_image(
+            &mut images,
+            image_handle,
+            size,
+            portal.resolution_scale * settings.resolution_scale * resolution_lod.scale(level),
+        );
+    }
+}
+
+/// Returns the window a `camera` renders to, if any.
+///
+/// Pass the entity of the [`PrimaryWindow`] as `primary_window`, to resolve [`WindowRef::Primary`].
+pub(crate) fn camera_window(camera: &Camera, primary_window: Option<Entity>) -> Option<Entity> {
+    match camera.target.normalize(primary_window) {
+        Some(NormalizedRenderTarget::Window(window_ref)) => Some(window_ref.entity()),
+        _ => None,
     }
 }
 
 #[derive(SystemParam)]
-struct ViewportSize<'w, 's> {
+pub(crate) struct ViewportSize<'w, 's> {
     primary_window_query: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
     window_query: Query<'w, 's, &'static Window>,
+    manual_texture_views: Res<'w, ManualTextureViews>,
+    image_size: Option<Res<'w, PortalImageSize>>,
 }
 
 impl ViewportSize<'_, '_> {
     /// Retrieves the size of the viewport of a given `camera`.
     ///
-    /// Returns [`None`] if no sizing could be obtained, or for any [`RenderTarget`] variant other
-    /// than [`RenderTarget::Window`].
-    fn get_viewport_size(&self, camera: &Camera) -> Option<Extent3d> {
+    /// For [`RenderTarget::TextureView`], e.g. used by XR integrations, the size of the view in
+    /// [`ManualTextureViews`] is used.
+    ///
+    /// If [`PortalImageSize::Fixed`] is used, that size is returned instead.
+    ///
+    /// Returns [`None`] if no sizing could be obtained, or for [`RenderTarget::Image`].
+    pub(crate) fn get_viewport_size(&self, camera: &Camera) -> Option<Extent3d> {
+        let fixed_size = match self.image_size.as_deref() {
+            Some(&PortalImageSize::Fixed(size)) => Some(size),
+            _ => None,
+        };
+        fixed_size
+            .or_else(|| self.get_camera_viewport_size(camera))
+            .map(|size| Extent3d {
+                width: size.x,
+                height: size.y,
+                ..default()
+            })
+    }
+
+    /// Retrieves the size of the viewport of a given `camera`, ignoring [`PortalImageSize`].
+    fn get_camera_viewport_size(&self, camera: &Camera) -> Option<UVec2> {
         match camera.viewport.as_ref() {
             Some(viewport) => Some(viewport.physical_size),
             None => match &camera.target {
@@ -429,13 +3549,225 @@ impl ViewportSize<'_, '_> {
                     WindowRef::Entity(entity) => self.window_query.get(*entity).ok(),
                 })
                 .map(Window::physical_size),
-                _ => None,
+                RenderTarget::TextureView(handle) => self
+                    .manual_texture_views
+                    .get(handle)
+                    .map(|texture_view| texture_view.size),
+                RenderTarget::Image(_) => None,
             },
         }
-        .map(|size| Extent3d {
-            width: size.x,
-            height: size.y,
-            ..default()
-        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+
+    /// A portal at the origin, and a target turned around and offset along the x-axis.
+    fn turned_around_portal() -> (Transform, Transform) {
+        (
+            Transform::IDENTITY,
+            Transform::from_xyz(10.0, 0.0, 0.0).with_rotation(Quat::from_rotation_y(PI)),
+        )
+    }
+
+    #[test]
+    fn transform_through_portal_round_trips() {
+        let portal = Transform::from_xyz(1.0, 2.0, 3.0).with_rotation(Quat::from_rotation_y(0.3));
+        let target = Transform::from_xyz(-4.0, 0.5, 8.0).with_rotation(Quat::from_euler(
+            EulerRot::YXZ,
+            1.2,
+            0.4,
+            0.0,
+        ));
+        let transform =
+            Transform::from_xyz(2.0, 3.0, 5.0).looking_to(Vec3::new(1.0, -0.5, -1.0), Vec3::Y);
+
+        let through = transform_through_portal(&portal, &target, transform);
+        let back = transform_through_portal(&target, &portal, through);
+
+        assert!(back.translation.abs_diff_eq(transform.translation, 1e-4));
+        assert!(back.rotation.angle_between(transform.rotation) < 1e-3);
+    }
+
+    #[test]
+    fn transform_through_portal_turns_around() {
+        let (portal, target) = turned_around_portal();
+        let transform = Transform::from_xyz(1.0, 2.0, 3.0);
+
+        let through = transform_through_portal(&portal, &target, transform);
+
+        assert!(through
+            .translation
+            .abs_diff_eq(Vec3::new(9.0, 2.0, -3.0), 1e-5));
+        assert!(through.forward().as_vec3().abs_diff_eq(Vec3::Z, 1e-5));
+    }
+
+    #[test]
+    fn transform_through_portal_keeps_non_uniform_scale() {
+        let (portal, target) = turned_around_portal();
+        let transform = Transform::from_xyz(1.0, 0.0, 1.0)
+            .with_rotation(Quat::from_rotation_x(0.5))
+            .with_scale(Vec3::new(1.0, 2.0, 3.0));
+
+        let through = transform_through_portal(&portal, &target, transform);
+        // The scale of the portal itself doesn't affect the mapping either
+        let scaled_portal = portal.with_scale(Vec3::new(2.0, 1.0, 0.5));
+        let through_scaled = transform_through_portal(&scaled_portal, &target, transform);
+
+        assert_eq!(through.scale, transform.scale);
+        assert_eq!(through_scaled, through);
+    }
+
+    #[test]
+    fn portal_transform_velocity_keeps_direction_relative_to_portal() {
+        let portal = Transform::IDENTITY;
+        let target =
+            Transform::from_xyz(5.0, 0.0, 0.0).with_rotation(Quat::from_rotation_y(FRAC_PI_2));
+        let linvel = Vec3::new(0.5, -1.0, -2.0);
+
+        let (through_linvel, through_angvel) =
+            portal_transform_velocity(&portal, &target, linvel, Vec3::X);
+        // A transform moving along `linvel` keeps moving forward on the other side
+        let transform = Transform::from_xyz(0.0, 0.0, 1.0).looking_to(linvel, Vec3::Y);
+        let through = transform_through_portal(&portal, &target, transform);
+
+        assert!(through_linvel
+            .normalize()
+            .abs_diff_eq(through.forward().as_vec3(), 1e-5));
+        assert!((through_linvel.length() - linvel.length()).abs() < 1e-5);
+        assert!(through_angvel.abs_diff_eq(Vec3::NEG_Z, 1e-5));
+    }
+
+    #[test]
+    fn portal_transform_ray_matches_transform_and_velocity() {
+        let (portal, target) = turned_around_portal();
+        let ray = Ray3d::new(Vec3::new(1.0, 2.0, 3.0), Dir3::NEG_Z);
+
+        let through = portal_transform_ray(&portal, &target, ray);
+
+        assert!(through.origin.abs_diff_eq(Vec3::new(9.0, 2.0, -3.0), 1e-5));
+        assert!(through.direction.as_vec3().abs_diff_eq(Vec3::Z, 1e-5));
+    }
+
+    /// Returns the distance of a world space `point` from a view space `plane` of `camera`.
+    fn plane_distance(plane: Vec4, camera: &GlobalTransform, point: Vec3) -> f32 {
+        let point = camera.affine().inverse().transform_point3(point);
+        plane.dot(point.extend(1.0))
+    }
+
+    #[test]
+    fn oblique_projection_without_plane_is_unchanged() {
+        let clip_from_view = PerspectiveProjection::default().get_clip_from_view();
+
+        assert_eq!(
+            PortalProjection::default().oblique(clip_from_view),
+            clip_from_view
+        );
+    }
+
+    #[test]
+    fn oblique_projection_clips_at_plane() {
+        let projection = PortalProjection {
+            projection: Projection::Perspective(PerspectiveProjection::default()),
+            // Clips everything closer than 5 units in front of the camera
+            near_clip_plane: Some(Vec4::new(0.0, 0.0, -1.0, -5.0)),
+        };
+        let clip_from_view = projection.get_clip_from_view();
+        let depth = |point: Vec3| clip_from_view.project_point3(point).z;
+
+        // With reverse-z, the near plane is at a depth of 1
+        assert!((depth(Vec3::new(1.0, -2.0, -5.0)) - 1.0).abs() < 1e-5);
+        assert!(depth(Vec3::new(0.0, 0.0, -2.0)) > 1.0);
+        assert!((0.0..1.0).contains(&depth(Vec3::new(0.0, 0.0, -10.0))));
+    }
+
+    #[test]
+    fn portal_near_clip_plane_clips_camera_side() {
+        let target = GlobalTransform::from_xyz(0.0, 0.0, -5.0);
+        for camera in [
+            GlobalTransform::IDENTITY,
+            // Looking at the target from its other side
+            GlobalTransform::from(
+                Transform::from_xyz(1.0, 0.0, -10.0).looking_at(Vec3::new(0.0, 0.0, -5.0), Vec3::Y),
+            ),
+        ] {
+            let plane = portal_near_clip_plane(&target, &camera, 0.0).unwrap();
+            let camera_side = camera.translation().z.signum();
+
+            assert!(plane_distance(plane, &camera, Vec3::new(2.0, 1.0, -5.0)).abs() < 1e-4);
+            assert!(plane_distance(plane, &camera, Vec3::new(0.0, 0.0, -5.0 + camera_side)) < 0.0);
+            assert!(plane_distance(plane, &camera, Vec3::new(0.0, 0.0, -5.0 - camera_side)) > 0.0);
+        }
+    }
+
+    #[test]
+    fn portal_near_clip_plane_applies_clip_offset() {
+        let target = GlobalTransform::from_xyz(0.0, 0.0, -5.0);
+        let camera = GlobalTransform::IDENTITY;
+
+        assert_eq!(
+            portal_near_clip_plane(&target, &camera, 1.0),
+            Some(Vec4::new(0.0, 0.0, -1.0, -4.0))
+        );
+        // The camera is within the offset of the target's plane
+        assert_eq!(portal_near_clip_plane(&target, &camera, 6.0), None);
+        assert_eq!(portal_near_clip_plane(&target, &target, 0.0), None);
+    }
+
+    #[test]
+    fn portal_screen_rect_covers_aabb() {
+        let aabb = Aabb::from_min_max(Vec3::new(-0.5, -0.5, 0.0), Vec3::new(0.5, 0.5, 0.0));
+
+        assert_eq!(
+            portal_screen_rect(&Mat4::IDENTITY, &aabb, UVec2::new(100, 100), false),
+            Some((UVec2::new(25, 25), UVec2::new(50, 50)))
+        );
+    }
+
+    #[test]
+    fn portal_screen_rect_flips_mirrors() {
+        let aabb = Aabb::from_min_max(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.5, 0.5, 0.0));
+        let target_size = UVec2::new(100, 100);
+
+        assert_eq!(
+            portal_screen_rect(&Mat4::IDENTITY, &aabb, target_size, false),
+            Some((UVec2::new(50, 25), UVec2::new(25, 50)))
+        );
+        assert_eq!(
+            portal_screen_rect(&Mat4::IDENTITY, &aabb, target_size, true),
+            Some((UVec2::new(25, 25), UVec2::new(25, 50)))
+        );
+    }
+
+    #[test]
+    fn portal_screen_rect_clamps_to_screen() {
+        let target_size = UVec2::new(100, 100);
+        let partially_off_screen =
+            Aabb::from_min_max(Vec3::new(0.5, -0.5, 0.0), Vec3::new(1.5, 0.5, 0.0));
+        let off_screen = Aabb::from_min_max(Vec3::new(2.5, -0.5, 0.0), Vec3::new(3.5, 0.5, 0.0));
+
+        assert_eq!(
+            portal_screen_rect(&Mat4::IDENTITY, &partially_off_screen, target_size, false),
+            Some((UVec2::new(75, 25), UVec2::new(25, 50)))
+        );
+        assert_eq!(
+            portal_screen_rect(&Mat4::IDENTITY, &off_screen, target_size, false),
+            None
+        );
+    }
+
+    #[test]
+    fn portal_screen_rect_rejects_corners_behind_camera() {
+        let clip_from_view = PerspectiveProjection::default().get_clip_from_view();
+        // Cameras look along -z, so this straddles the camera
+        let aabb = Aabb::from_min_max(Vec3::new(-0.5, -0.5, -1.0), Vec3::new(0.5, 0.5, 1.0));
+
+        assert_eq!(
+            portal_screen_rect(&clip_from_view, &aabb, UVec2::new(100, 100), false),
+            None
+        );
     }
 }
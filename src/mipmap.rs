@@ -0,0 +1,378 @@
+//! Generating mipmaps for the images of [`Portal`]s.
+//!
+//! Set [`Portal::mipmaps`] to give a [`Portal`]'s image a full mip chain. Cameras can only render
+//! to a single mip level, so the [`PortalCamera`]'s image is copied into the first level of a
+//! separate image after rendering, and each further level is downsampled from the previous one.
+//! The portal samples this image with trilinear and anisotropic filtering, so that portals far
+//! away or seen at glancing angles don't shimmer.
+//!
+//! # Notes
+//!
+//! * This is ignored for portals rendering into the [`PortalAtlas`](crate::atlas::PortalAtlas),
+//!   since lower mip levels would blend neighboring cells.
+//! * The [`PortalImage`] of the portal is the image with the mip chain, not the image the
+//!   [`PortalCamera`] renders to.
+//! * Mipmaps only make a difference where the image is minified, i.e. for
+//!   [`PortalSurfaceMapping::Planar`] and [`PortalSurfaceMapping::MeshUv`], or a
+//!   [`Portal::resolution_scale`] above `1.0`.
+//!
+//! [`Portal`]: crate::Portal
+//! [`Portal::mipmaps`]: crate::Portal::mipmaps
+//! [`Portal::resolution_scale`]: crate::Portal::resolution_scale
+//! [`PortalCamera`]: crate::PortalCamera
+//! [`PortalImage`]: crate::PortalImage
+//! [`PortalSurfaceMapping::Planar`]: crate::PortalSurfaceMapping::Planar
+//! [`PortalSurfaceMapping::MeshUv`]: crate::PortalSurfaceMapping::MeshUv
+
+use bevy::{
+    asset::load_internal_asset,
+    core_pipeline::{
+        core_3d::graph::{Core3d, Node3d},
+        fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+    },
+    ecs::query::QueryItem,
+    image::{
+        ImageFilterMode, ImageSampler, ImageSamplerDescriptor, TextureFormatPixelInfo, Volume,
+    },
+    prelude::*,
+    render::{
+        camera::{ExtractedCamera, NormalizedRenderTarget, RenderTarget},
+        extract_component::{ExtractComponent, ExtractComponentPlugin},
+        render_asset::RenderAssets,
+        render_graph::{
+            NodeRunError, RenderGraphApp, RenderGraphContext, RenderLabel, ViewNode, ViewNodeRunner,
+        },
+        render_resource::{
+            binding_types::{sampler, texture_2d},
+            BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, CachedRenderPipelineId,
+            ColorTargetState, ColorWrites, Extent3d, FilterMode, FragmentState, LoadOp, Operations,
+            PipelineCache, RenderPassColorAttachment, RenderPassDescriptor,
+            RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages,
+            SpecializedRenderPipeline, SpecializedRenderPipelines, StoreOp, TextureDescriptor,
+            TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+            TextureViewDescriptor,
+        },
+        renderer::{RenderContext, RenderDevice},
+        texture::GpuImage,
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::PortalCameraSystems;
+
+const PORTAL_MIPMAP_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(85113623121764904570576530315117102048);
+
+/// Plugin that generates the mipmaps of [`PortalCamera`](crate::PortalCamera) images.
+///
+/// This is added by [`PortalPlugin`](crate::PortalPlugin), and shouldn't be added manually.
+pub(crate) struct PortalMipmapPlugin;
+
+impl Plugin for PortalMipmapPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            PORTAL_MIPMAP_SHADER_HANDLE,
+            concat!(env!("CARGO_MANIFEST_DIR"), "/assets/portal_mipmap.wgsl"),
+            Shader::from_wgsl
+        );
+
+        app.add_plugins(ExtractComponentPlugin::<PortalMipmaps>::default())
+            .add_systems(
+                PreUpdate,
+                resize_portal_mipmap_images.after(PortalCameraSystems::ResizeImage),
+            );
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<SpecializedRenderPipelines<PortalMipmapPipeline>>()
+            .add_systems(
+                Render,
+                prepare_portal_mipmap_pipelines.in_set(RenderSet::Prepare),
+            )
+            .add_render_graph_node::<ViewNodeRunner<PortalMipmapNode>>(Core3d, PortalMipmapLabel)
+            // The image is complete once upscaling wrote to it
+            .add_render_graph_edges(Core3d, (Node3d::Upscaling, PortalMipmapLabel));
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app.init_resource::<PortalMipmapPipeline>();
+    }
+}
+
+/// Component added to [`PortalCamera`](crate::PortalCamera)s whose image gets mipmaps, holding
+/// the image with the mip chain.
+///
+/// This is inserted for [`Portal`](crate::Portal)s with
+/// [`Portal::mipmaps`](crate::Portal::mipmaps) enabled.
+#[derive(Component, ExtractComponent, Debug, Clone)]
+pub struct PortalMipmaps(pub Handle<Image>);
+
+/// Creates an image with a full mip chain, that a portal image of a given `size` and `format` can
+/// be copied into.
+///
+/// The image is sampled with trilinear and anisotropic filtering, unless `sampler` is set.
+pub(crate) fn new_portal_mipmap_image(
+    size: Extent3d,
+    format: TextureFormat,
+    sampler: &ImageSampler,
+) -> Image {
+    let sampler = match sampler {
+        ImageSampler::Default => ImageSampler::Descriptor(ImageSamplerDescriptor {
+            mag_filter: ImageFilterMode::Linear,
+            min_filter: ImageFilterMode::Linear,
+            mipmap_filter: ImageFilterMode::Linear,
+            anisotropy_clamp: 16,
+            ..default()
+        }),
+        sampler => sampler.clone(),
+    };
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: None,
+            size,
+            dimension: TextureDimension::D2,
+            format,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        },
+        sampler,
+        ..default()
+    };
+    resize_portal_mipmap_image(&mut image, size);
+    image
+}
+
+/// Resizes an image created by [`new_portal_mipmap_image`], along with its mip chain.
+fn resize_portal_mipmap_image(image: &mut Image, size: Extent3d) {
+    let mip_level_count = 32 - size.width.max(size.height).max(1).leading_zeros();
+    let data_len = (0..mip_level_count)
+        .map(|mip_level| {
+            size.mip_level_size(mip_level, TextureDimension::D2)
+                .volume()
+        })
+        .sum::<usize>()
+        * image.texture_descriptor.format.pixel_size();
+
+    image.texture_descriptor.size = size;
+    image.texture_descriptor.mip_level_count = mip_level_count;
+    image.data.resize(data_len, 0);
+}
+
+/// System that resizes the images of [`PortalMipmaps`] to match the image their
+/// [`PortalCamera`](crate::PortalCamera) renders to.
+///
+/// Sizes are compared every frame, so this follows the image however it is resized.
+fn resize_portal_mipmap_images(
+    camera_query: Query<(&Camera, &PortalMipmaps)>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    for (camera, mipmaps) in &camera_query {
+        let RenderTarget::Image(image_handle) = &camera.target else {
+            continue;
+        };
+        let Some(size) = images
+            .get(image_handle)
+            .map(|image| image.texture_descriptor.size)
+        else {
+            continue;
+        };
+        if images
+            .get(&mipmaps.0)
+            .is_none_or(|image| image.texture_descriptor.size == size)
+        {
+            continue;
+        }
+        if let Some(image) = images.get_mut(&mipmaps.0) {
+            resize_portal_mipmap_image(image, size);
+        }
+    }
+}
+
+/// The render pipeline downsampling one mip level of a portal image into the next.
+#[derive(Resource)]
+struct PortalMipmapPipeline {
+    layout: BindGroupLayout,
+    sampler: Sampler,
+}
+
+impl FromWorld for PortalMipmapPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let layout = render_device.create_bind_group_layout(
+            "portal_mipmap_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    sampler(SamplerBindingType::Filtering),
+                ),
+            ),
+        );
+        let sampler = render_device.create_sampler(&SamplerDescriptor {
+            label: Some("portal_mipmap_sampler"),
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..default()
+        });
+        Self { layout, sampler }
+    }
+}
+
+impl SpecializedRenderPipeline for PortalMipmapPipeline {
+    type Key = TextureFormat;
+
+    fn specialize(&self, format: Self::Key) -> RenderPipelineDescriptor {
+        RenderPipelineDescriptor {
+            label: Some("portal_mipmap_pipeline".into()),
+            layout: vec![self.layout.clone()],
+            push_constant_ranges: Vec::new(),
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: PORTAL_MIPMAP_SHADER_HANDLE,
+                shader_defs: Vec::new(),
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: default(),
+            depth_stencil: None,
+            multisample: default(),
+            zero_initialize_workgroup_memory: false,
+        }
+    }
+}
+
+/// The [`PortalMipmapPipeline`] specialized for the format of a view's [`PortalMipmaps`].
+#[derive(Component)]
+struct PortalMipmapPipelineId(CachedRenderPipelineId);
+
+/// System that specializes the [`PortalMipmapPipeline`] for each view with [`PortalMipmaps`].
+fn prepare_portal_mipmap_pipelines(
+    mut commands: Commands,
+    pipeline_cache: Res<PipelineCache>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<PortalMipmapPipeline>>,
+    mipmap_pipeline: Res<PortalMipmapPipeline>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    view_query: Query<(Entity, &PortalMipmaps)>,
+) {
+    for (entity, mipmaps) in &view_query {
+        let Some(gpu_image) = gpu_images.get(&mipmaps.0) else {
+            continue;
+        };
+
+        let pipeline_id =
+            pipelines.specialize(&pipeline_cache, &mipmap_pipeline, gpu_image.texture_format);
+        commands
+            .entity(entity)
+            .insert(PortalMipmapPipelineId(pipeline_id));
+    }
+}
+
+/// Label for the [`PortalMipmapNode`].
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+struct PortalMipmapLabel;
+
+/// Render graph node that copies a view's image into the first mip level of its
+/// [`PortalMipmaps`], and downsamples each further level from the previous one.
+#[derive(Default)]
+struct PortalMipmapNode;
+
+impl ViewNode for PortalMipmapNode {
+    type ViewQuery = (
+        &'static ExtractedCamera,
+        &'static PortalMipmaps,
+        &'static PortalMipmapPipelineId,
+    );
+
+    fn run<'w>(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext<'w>,
+        (camera, mipmaps, pipeline_id): QueryItem<'w, Self::ViewQuery>,
+        world: &'w World,
+    ) -> Result<(), NodeRunError> {
+        let Some(NormalizedRenderTarget::Image(image_handle)) = &camera.target else {
+            return Ok(());
+        };
+        let gpu_images = world.resource::<RenderAssets<GpuImage>>();
+        let (Some(source_image), Some(gpu_image)) =
+            (gpu_images.get(image_handle), gpu_images.get(&mipmaps.0))
+        else {
+            return Ok(());
+        };
+        // The images are briefly out of sync while resizing
+        if source_image.size != gpu_image.size
+            || source_image.texture_format != gpu_image.texture_format
+        {
+            return Ok(());
+        }
+        let Some(pipeline) = world
+            .resource::<PipelineCache>()
+            .get_render_pipeline(pipeline_id.0)
+        else {
+            return Ok(());
+        };
+        let mipmap_pipeline = world.resource::<PortalMipmapPipeline>();
+
+        render_context.command_encoder().copy_texture_to_texture(
+            source_image.texture.as_image_copy(),
+            gpu_image.texture.as_image_copy(),
+            Extent3d {
+                width: gpu_image.size.x,
+                height: gpu_image.size.y,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        for mip_level in 1..gpu_image.mip_level_count {
+            let mip_view = |mip_level| {
+                gpu_image.texture.create_view(&TextureViewDescriptor {
+                    label: Some("portal_mipmap_view"),
+                    base_mip_level: mip_level,
+                    mip_level_count: Some(1),
+                    ..default()
+                })
+            };
+            let source = mip_view(mip_level - 1);
+            let destination = mip_view(mip_level);
+
+            let bind_group = render_context.render_device().create_bind_group(
+                "portal_mipmap_bind_group",
+                &mipmap_pipeline.layout,
+                &BindGroupEntries::sequential((&source, &mipmap_pipeline.sampler)),
+            );
+            let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+                label: Some("portal_mipmap_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &destination,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(default()),
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_render_pipeline(pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        Ok(())
+    }
+}
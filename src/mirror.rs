@@ -0,0 +1,88 @@
+//! Turning [`Portal`]s into mirrors.
+//!
+//! Add [`Mirror`] next to a [`Portal`] to reflect the view across the portal's mesh, instead of
+//! showing the view from [`Portal::target`].
+
+use bevy::prelude::*;
+
+#[cfg(doc)]
+use crate::{teleport::PortalTeleporter, Portal, PortalCamera, PortalMaterial};
+
+/// Component used to turn a [`Portal`] into a mirror.
+///
+/// Instead of being offset into the space of [`Portal::target`], the [`PortalCamera`] is reflected
+/// across the plane of the portal's mesh, and [`Portal::target`] is set to the portal itself.
+///
+/// A reflection can't be represented by a [`Transform`], so the [`PortalCamera`] is flipped
+/// horizontally instead, and its image is flipped back by the [`PortalMaterial`]. This keeps the
+/// winding order of everything in the reflection intact.
+///
+/// # Notes
+///
+/// * Mirrors are not rendered recursively.
+/// * Mirrors can't be used with [`PortalTeleporter`].
+#[derive(Component, Reflect, Debug, Default, Clone, Copy)]
+#[reflect(Component, Default)]
+pub struct Mirror;
+
+/// Reflects a `transform` across the plane of a [`Mirror`].
+///
+/// The result is flipped horizontally, so that it can be represented by a [`Transform`].
+pub(crate) fn reflect_through_mirror(
+    mirror_transform: &Transform,
+    transform: Transform,
+) -> Transform {
+    let normal = *mirror_transform.back();
+    let reflection = Mat3::IDENTITY
+        - 2.0 * Mat3::from_cols(normal * normal.x, normal * normal.y, normal * normal.z);
+
+    let distance = (transform.translation - mirror_transform.translation).dot(normal);
+    let rotation = reflection
+        * Mat3::from_quat(transform.rotation)
+        * Mat3::from_diagonal(Vec3::new(-1.0, 1.0, 1.0));
+
+    Transform {
+        translation: transform.translation - 2.0 * distance * normal,
+        rotation: Quat::from_mat3(&rotation),
+        scale: transform.scale,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reflect_through_mirror_mirrors_across_plane() {
+        let mirror = Transform::from_xyz(0.0, 0.0, -5.0);
+        let transform = Transform::from_xyz(1.0, 2.0, 0.0).with_scale(Vec3::new(1.0, 2.0, 3.0));
+
+        let reflected = reflect_through_mirror(&mirror, transform);
+
+        assert!(reflected
+            .translation
+            .abs_diff_eq(Vec3::new(1.0, 2.0, -10.0), 1e-5));
+        // Looking at the mirror from behind it, flipped horizontally
+        assert!(reflected.forward().as_vec3().abs_diff_eq(Vec3::Z, 1e-5));
+        assert!(reflected.up().as_vec3().abs_diff_eq(Vec3::Y, 1e-5));
+        assert_eq!(reflected.scale, transform.scale);
+    }
+
+    #[test]
+    fn reflect_through_mirror_twice_is_identity() {
+        let mirror = Transform::from_xyz(3.0, -1.0, 2.0).with_rotation(Quat::from_euler(
+            EulerRot::YXZ,
+            0.7,
+            -0.3,
+            0.2,
+        ));
+        let transform =
+            Transform::from_xyz(-2.0, 4.0, 1.0).looking_to(Vec3::new(0.3, -0.2, -1.0), Vec3::Y);
+
+        let reflected = reflect_through_mirror(&mirror, transform);
+        let back = reflect_through_mirror(&mirror, reflected);
+
+        assert!(back.translation.abs_diff_eq(transform.translation, 1e-4));
+        assert!(back.rotation.angle_between(transform.rotation) < 1e-3);
+    }
+}
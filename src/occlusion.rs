@@ -0,0 +1,533 @@
+//! Skipping [`Portal`]s hidden behind other geometry.
+//!
+//! Enable [`PortalPlugin::occlusion_culling`] to test each [`Portal`] against the depth buffer of
+//! its primary camera. After the main passes, a box around the [`Portal`]'s [`Aabb`] is drawn with
+//! an occlusion query, and if none of it passes the depth test, the [`PortalCamera`] is
+//! deactivated. This catches portals behind closed doors or walls, which frustum checks can't.
+//!
+//! # Notes
+//!
+//! * Results are read back from the GPU asynchronously, so they lag a couple of frames behind. A
+//!   portal coming out from behind a wall shows its last rendered image for those frames.
+//! * Portals only visible through other [`Portal`]s are not tested, since they aren't rendered
+//!   anyway.
+//!
+//! [`Portal`]: crate::Portal
+//! [`PortalCamera`]: crate::PortalCamera
+//! [`PortalPlugin::occlusion_culling`]: crate::PortalPlugin::occlusion_culling
+
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc, Mutex,
+};
+
+use bevy::{
+    asset::load_internal_asset,
+    core_pipeline::core_3d::{
+        graph::{Core3d, Node3d},
+        CORE_3D_DEPTH_FORMAT,
+    },
+    ecs::query::QueryItem,
+    prelude::*,
+    render::{
+        primitives::Aabb,
+        render_graph::{
+            NodeRunError, RenderGraphApp, RenderGraphContext, RenderLabel, ViewNode, ViewNodeRunner,
+        },
+        render_resource::{
+            Buffer, BufferDescriptor, BufferInitDescriptor, BufferUsages, CachedRenderPipelineId,
+            CompareFunction, DepthStencilState, MapMode, MultisampleState, PipelineCache,
+            PrimitiveState, PrimitiveTopology, RenderPassDescriptor, RenderPipelineDescriptor,
+            SpecializedRenderPipeline, SpecializedRenderPipelines, StoreOp, VertexAttribute,
+            VertexBufferLayout, VertexFormat, VertexState, VertexStepMode,
+        },
+        renderer::{RenderContext, RenderDevice},
+        sync_world::MainEntity,
+        view::{ExtractedView, Msaa, ViewDepthTexture},
+        Extract, ExtractSchedule, Render, RenderApp, RenderSet,
+    },
+    utils::HashMap,
+};
+use wgpu::{QuerySet, QuerySetDescriptor, QueryType};
+
+use crate::{Portal, PortalCameraSystems};
+
+const PORTAL_OCCLUSION_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(311842773101943279541660583138617668241);
+
+/// How far the box tested for occlusion extends beyond a [`Portal`]'s [`Aabb`], in the
+/// [`Portal`]'s local space.
+///
+/// This keeps the box from fighting with the depth of the [`Portal`]'s own mesh.
+const OCCLUDER_MARGIN: f32 = 0.01;
+
+/// The number of occlusion queries each view can have in flight, waiting to be read back.
+const MAX_QUERIES_IN_FLIGHT: usize = 3;
+
+/// Plugin that deactivates the [`PortalCamera`](crate::PortalCamera)s of occluded
+/// [`Portal`]s.
+///
+/// This is added by [`PortalPlugin`](crate::PortalPlugin) if
+/// [`PortalPlugin::occlusion_culling`](crate::PortalPlugin::occlusion_culling) is enabled, and
+/// shouldn't be added manually.
+pub(crate) struct PortalOcclusionPlugin;
+
+impl Plugin for PortalOcclusionPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            PORTAL_OCCLUSION_SHADER_HANDLE,
+            concat!(env!("CARGO_MANIFEST_DIR"), "/assets/portal_occlusion.wgsl"),
+            Shader::from_wgsl
+        );
+
+        let results = PortalOcclusionResults::default();
+        app.register_required_components::<Portal, PortalOccluded>()
+            .insert_resource(results.clone())
+            .add_systems(
+                PostUpdate,
+                update_portal_occlusion.before(PortalCameraSystems::UpdateActivity),
+            );
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .insert_resource(results)
+            .init_resource::<ExtractedPortalOccluders>()
+            .init_resource::<PortalOcclusionQueries>()
+            .init_resource::<SpecializedRenderPipelines<PortalOcclusionPipeline>>()
+            .add_systems(ExtractSchedule, extract_portal_occluders)
+            .add_systems(
+                Render,
+                (
+                    prepare_portal_occlusion_queries.in_set(RenderSet::Prepare),
+                    map_portal_occlusion_queries.in_set(RenderSet::Cleanup),
+                ),
+            )
+            .add_render_graph_node::<ViewNodeRunner<PortalOcclusionNode>>(
+                Core3d,
+                PortalOcclusionLabel,
+            )
+            // The depth buffer is complete once the main passes wrote to it
+            .add_render_graph_edges(Core3d, (Node3d::EndMainPass, PortalOcclusionLabel));
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app.init_resource::<PortalOcclusionPipeline>();
+    }
+}
+
+/// Whether a [`Portal`]'s mesh is hidden behind other geometry, as seen by its primary camera.
+#[derive(Component, Default, PartialEq)]
+pub(crate) struct PortalOccluded(pub(crate) bool);
+
+/// Resource shared by the main and render world, through which the render world reports whether
+/// each tested [`Portal`] is visible.
+#[derive(Resource, Default, Clone)]
+struct PortalOcclusionResults(Arc<Mutex<Vec<(Entity, bool)>>>);
+
+/// System that applies the occlusion results read back from the GPU to each [`Portal`].
+fn update_portal_occlusion(
+    results: Res<PortalOcclusionResults>,
+    mut portal_query: Query<&mut PortalOccluded>,
+) {
+    let Ok(mut results) = results.0.lock() else {
+        return;
+    };
+    for (entity, visible) in results.drain(..) {
+        if let Ok(mut occluded) = portal_query.get_mut(entity) {
+            occluded.set_if_neq(PortalOccluded(!visible));
+        }
+    }
+}
+
+/// The [`Portal`]s tested for occlusion by each primary camera, along with the transform from a
+/// box spanning `-1.0` to `1.0` to the box tested for the [`Portal`].
+#[derive(Resource, Default)]
+struct ExtractedPortalOccluders(HashMap<Entity, Vec<(Entity, Mat4)>>);
+
+/// System that extracts the boxes of [`Portal`]s to test for occlusion.
+fn extract_portal_occluders(
+    mut occluders: ResMut<ExtractedPortalOccluders>,
+    portal_query: Extract<Query<(Entity, &Portal, &GlobalTransform, &Aabb)>>,
+) {
+    occluders.0.clear();
+    for (entity, portal, global_transform, aabb) in &portal_query {
+        let box_from_aabb = Mat4::from_scale_rotation_translation(
+            Vec3::from(aabb.half_extents) + OCCLUDER_MARGIN,
+            Quat::IDENTITY,
+            aabb.center.into(),
+        );
+        occluders
+            .0
+            .entry(portal.primary_camera)
+            .or_default()
+            .push((entity, global_transform.compute_matrix() * box_from_aabb));
+    }
+}
+
+/// The states an occlusion query goes through, stored in [`PortalOcclusionQuery::state`].
+mod query_state {
+    /// The query can be used for a new frame.
+    pub const AVAILABLE: u8 = 0;
+    /// The boxes to draw are uploaded, waiting for the render graph.
+    pub const PREPARED: u8 = 1;
+    /// The queries are encoded, and copied to the readback buffer.
+    pub const ENCODED: u8 = 2;
+    /// The readback buffer is being mapped.
+    pub const MAPPING: u8 = 3;
+    /// The readback buffer is mapped, and can be read.
+    pub const MAPPED: u8 = 4;
+    /// The readback buffer failed to map.
+    pub const FAILED: u8 = 5;
+}
+
+/// The occlusion queries of a view for a single frame, along with the buffers to read them back.
+struct PortalOcclusionQuery {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readback_buffer: Buffer,
+    /// The number of queries in [`PortalOcclusionQuery::query_set`].
+    capacity: u32,
+    /// The [`Portal`]s tested, in the order of the queries.
+    portals: Vec<Entity>,
+    /// One of the states in [`query_state`].
+    state: Arc<AtomicU8>,
+}
+
+impl PortalOcclusionQuery {
+    fn new(render_device: &RenderDevice, capacity: u32) -> Self {
+        let size = u64::from(capacity) * size_of::<u64>() as u64;
+        Self {
+            query_set: render_device
+                .wgpu_device()
+                .create_query_set(&QuerySetDescriptor {
+                    label: Some("portal_occlusion_query_set"),
+                    ty: QueryType::Occlusion,
+                    count: capacity,
+                }),
+            resolve_buffer: render_device.create_buffer(&BufferDescriptor {
+                label: Some("portal_occlusion_resolve_buffer"),
+                size,
+                usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback_buffer: render_device.create_buffer(&BufferDescriptor {
+                label: Some("portal_occlusion_readback_buffer"),
+                size,
+                usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            capacity,
+            portals: Vec::new(),
+            state: Arc::new(AtomicU8::new(query_state::AVAILABLE)),
+        }
+    }
+
+    /// Returns the size in bytes of the results of the queries in use.
+    fn results_size(&self) -> u64 {
+        self.portals.len() as u64 * size_of::<u64>() as u64
+    }
+}
+
+/// Resource holding the occlusion queries of each primary camera, keyed by its main world entity.
+#[derive(Resource, Default)]
+struct PortalOcclusionQueries(HashMap<Entity, Vec<PortalOcclusionQuery>>);
+
+/// Component added to views whose [`Portal`]s are tested for occlusion this frame.
+#[derive(Component)]
+struct ViewPortalOcclusion {
+    /// The index of the query used, within the view's [`PortalOcclusionQueries`].
+    query: usize,
+    /// The transforms from a box spanning `-1.0` to `1.0` to clip space, one per query.
+    instance_buffer: Buffer,
+    pipeline_id: CachedRenderPipelineId,
+}
+
+/// System that reads back the results of mapped occlusion queries, and prepares a query for
+/// each view with [`Portal`]s to test.
+fn prepare_portal_occlusion_queries(
+    mut commands: Commands,
+    occluders: Res<ExtractedPortalOccluders>,
+    mut queries: ResMut<PortalOcclusionQueries>,
+    results: Res<PortalOcclusionResults>,
+    render_device: Res<RenderDevice>,
+    pipeline_cache: Res<PipelineCache>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<PortalOcclusionPipeline>>,
+    occlusion_pipeline: Res<PortalOcclusionPipeline>,
+    view_query: Query<(Entity, &MainEntity, &ExtractedView, &Msaa), With<ViewDepthTexture>>,
+) {
+    let Ok(mut results) = results.0.lock() else {
+        return;
+    };
+
+    for query in queries.0.values_mut().flatten() {
+        match query.state.load(Ordering::Acquire) {
+            query_state::MAPPED => {
+                let slice = query.readback_buffer.slice(..query.results_size());
+                let data = slice.get_mapped_range();
+                results.extend(query.portals.iter().zip(data.chunks_exact(8)).map(
+                    |(&portal, count)| {
+                        let count = u64::from_ne_bytes(count.try_into().unwrap_or_default());
+                        (portal, count > 0)
+                    },
+                ));
+                drop(data);
+                query.readback_buffer.unmap();
+            }
+            query_state::FAILED => {}
+            _ => continue,
+        }
+        query.portals.clear();
+        query.state.store(query_state::AVAILABLE, Ordering::Release);
+    }
+    // Queries of removed cameras are dropped, even if they are still in flight
+    queries.0.retain(|view, _| occluders.0.contains_key(view));
+
+    for (entity, main_entity, view, msaa) in &view_query {
+        let Some(view_occluders) = occluders.0.get(&main_entity.id()) else {
+            commands.entity(entity).remove::<ViewPortalOcclusion>();
+            continue;
+        };
+
+        let clip_from_world = view.clip_from_world.unwrap_or_else(|| {
+            view.clip_from_view * view.world_from_view.compute_matrix().inverse()
+        });
+        let view_position = view.world_from_view.translation();
+        let mut portals = Vec::with_capacity(view_occluders.len());
+        let mut instances = Vec::with_capacity(view_occluders.len() * size_of::<Mat4>());
+        for &(portal, world_from_box) in view_occluders {
+            // Views inside the box would only see its back faces clipped by the near plane
+            let box_position = world_from_box.inverse().transform_point3(view_position);
+            if box_position.abs().max_element() <= 1.0 {
+                results.push((portal, true));
+                continue;
+            }
+            portals.push(portal);
+            instances.extend(
+                (clip_from_world * world_from_box)
+                    .to_cols_array()
+                    .into_iter()
+                    .flat_map(f32::to_ne_bytes),
+            );
+        }
+        if portals.is_empty() {
+            commands.entity(entity).remove::<ViewPortalOcclusion>();
+            continue;
+        }
+
+        let view_queries = queries.0.entry(main_entity.id()).or_default();
+        let available = view_queries.iter().position(|query| {
+            query.state.load(Ordering::Acquire) == query_state::AVAILABLE
+                && query.capacity >= portals.len() as u32
+        });
+        let index = match available {
+            Some(index) => index,
+            None if view_queries.len() < MAX_QUERIES_IN_FLIGHT => {
+                view_queries.push(PortalOcclusionQuery::new(
+                    &render_device,
+                    portals.len().next_power_of_two() as u32,
+                ));
+                view_queries.len() - 1
+            }
+            None => {
+                // Replace an available query that is too small, if any
+                let Some(index) = view_queries.iter().position(|query| {
+                    query.state.load(Ordering::Acquire) == query_state::AVAILABLE
+                }) else {
+                    commands.entity(entity).remove::<ViewPortalOcclusion>();
+                    continue;
+                };
+                view_queries[index] = PortalOcclusionQuery::new(
+                    &render_device,
+                    portals.len().next_power_of_two() as u32,
+                );
+                index
+            }
+        };
+        let query = &mut view_queries[index];
+        query.portals = portals;
+        query.state.store(query_state::PREPARED, Ordering::Release);
+
+        let instance_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("portal_occlusion_instance_buffer"),
+            contents: &instances,
+            usage: BufferUsages::VERTEX,
+        });
+        let pipeline_id =
+            pipelines.specialize(&pipeline_cache, &occlusion_pipeline, msaa.samples());
+        commands.entity(entity).insert(ViewPortalOcclusion {
+            query: index,
+            instance_buffer,
+            pipeline_id,
+        });
+    }
+}
+
+/// System that starts mapping the readback buffers of the occlusion queries encoded this frame.
+///
+/// This runs after the render graph is submitted, since buffers can't be mapped while in use.
+fn map_portal_occlusion_queries(
+    queries: Res<PortalOcclusionQueries>,
+    render_device: Res<RenderDevice>,
+) {
+    for query in queries.0.values().flatten() {
+        match query.state.load(Ordering::Acquire) {
+            query_state::ENCODED => {
+                query.state.store(query_state::MAPPING, Ordering::Release);
+                let state = query.state.clone();
+                render_device.map_buffer(
+                    &query.readback_buffer.slice(..query.results_size()),
+                    MapMode::Read,
+                    move |result| {
+                        let mapped = match result {
+                            Ok(()) => query_state::MAPPED,
+                            Err(_) => query_state::FAILED,
+                        };
+                        state.store(mapped, Ordering::Release);
+                    },
+                );
+            }
+            // The view wasn't rendered, so there is nothing to read back
+            query_state::PREPARED => {
+                query.state.store(query_state::FAILED, Ordering::Release);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The render pipeline drawing the boxes tested for occlusion against a view's depth.
+#[derive(Resource, Default)]
+struct PortalOcclusionPipeline;
+
+impl SpecializedRenderPipeline for PortalOcclusionPipeline {
+    /// The sample count of the view.
+    type Key = u32;
+
+    fn specialize(&self, samples: Self::Key) -> RenderPipelineDescriptor {
+        RenderPipelineDescriptor {
+            label: Some("portal_occlusion_pipeline".into()),
+            layout: Vec::new(),
+            push_constant_ranges: Vec::new(),
+            vertex: VertexState {
+                shader: PORTAL_OCCLUSION_SHADER_HANDLE,
+                shader_defs: Vec::new(),
+                entry_point: "vertex".into(),
+                buffers: vec![VertexBufferLayout {
+                    array_stride: size_of::<Mat4>() as u64,
+                    step_mode: VertexStepMode::Instance,
+                    attributes: (0..4)
+                        .map(|column| VertexAttribute {
+                            format: VertexFormat::Float32x4,
+                            offset: column * size_of::<Vec4>() as u64,
+                            shader_location: column as u32,
+                        })
+                        .collect(),
+                }],
+            },
+            // Only the depth test is needed to count samples
+            fragment: None,
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleStrip,
+                cull_mode: None,
+                ..default()
+            },
+            depth_stencil: Some(DepthStencilState {
+                format: CORE_3D_DEPTH_FORMAT,
+                depth_write_enabled: false,
+                // Bevy uses reversed depth, so closer samples are greater
+                depth_compare: CompareFunction::GreaterEqual,
+                stencil: default(),
+                bias: default(),
+            }),
+            multisample: MultisampleState {
+                count: samples,
+                ..default()
+            },
+            zero_initialize_workgroup_memory: false,
+        }
+    }
+}
+
+/// Label for the [`PortalOcclusionNode`].
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+struct PortalOcclusionLabel;
+
+/// Render graph node that draws the box of each [`Portal`] of a view against its depth, counting
+/// the samples that pass with an occlusion query.
+#[derive(Default)]
+struct PortalOcclusionNode;
+
+impl ViewNode for PortalOcclusionNode {
+    type ViewQuery = (
+        &'static MainEntity,
+        &'static ViewDepthTexture,
+        &'static ViewPortalOcclusion,
+    );
+
+    fn run<'w>(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext<'w>,
+        (main_entity, depth, occlusion): QueryItem<'w, Self::ViewQuery>,
+        world: &'w World,
+    ) -> Result<(), NodeRunError> {
+        let Some(query) = world
+            .resource::<PortalOcclusionQueries>()
+            .0
+            .get(&main_entity.id())
+            .and_then(|view_queries| view_queries.get(occlusion.query))
+        else {
+            return Ok(());
+        };
+        if query.state.load(Ordering::Acquire) != query_state::PREPARED {
+            return Ok(());
+        }
+        let Some(pipeline) = world
+            .resource::<PipelineCache>()
+            .get_render_pipeline(occlusion.pipeline_id)
+        else {
+            return Ok(());
+        };
+
+        let command_encoder = render_context.command_encoder();
+        {
+            let mut render_pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("portal_occlusion_pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(depth.get_attachment(StoreOp::Store)),
+                timestamp_writes: None,
+                occlusion_query_set: Some(&query.query_set),
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_vertex_buffer(0, *occlusion.instance_buffer.slice(..));
+            for index in 0..query.portals.len() as u32 {
+                render_pass.begin_occlusion_query(index);
+                render_pass.draw(0..14, index..index + 1);
+                render_pass.end_occlusion_query();
+            }
+        }
+
+        command_encoder.resolve_query_set(
+            &query.query_set,
+            0..query.portals.len() as u32,
+            &query.resolve_buffer,
+            0,
+        );
+        command_encoder.copy_buffer_to_buffer(
+            &query.resolve_buffer,
+            0,
+            &query.readback_buffer,
+            0,
+            query.results_size(),
+        );
+        query.state.store(query_state::ENCODED, Ordering::Release);
+
+        Ok(())
+    }
+}
@@ -5,19 +5,46 @@
 //! This module does *not* provide any backend for you. It provides custom inputs that are
 //! compatible with any backend. The entity containing the [`Portal`] will need to be picked via a
 //! backend, hits will then be sent "through" the target.
+//!
+//! Presses are sent through the hovered portal, after which the pointer's inputs keep being sent
+//! through it until the button is released, so that drags continue across the portal's edge.
+//! Once a pointer leaves a portal (or stops existing), its virtual pointer is moved off the image,
+//! and its pressed buttons are released, so that entities on the far side receive `Out` and `Up`.
+//!
+//! Every pointer gets a virtual pointer of its own for each portal it is sent through, so multiple
+//! pointers (e.g. fingers on a touch screen) can interact through portals at the same time.
+//! Virtual pointers are marked with a [`PortalPointer`], and the hits of their backends are sent
+//! as [`PortalPointerHits`] events. The closest hit is also sent as a [`PortalPointerHit`] event,
+//! to tell picks through a portal apart from direct ones.
+//!
+//! UI nodes rendered by a [`PortalCamera`](crate::PortalCamera) (i.e. with a
+//! `TargetCamera` of the [`LinkedPortalCamera`]) can be interacted with through the portal as well,
+//! given that the UI picking backend is enabled.
+//!
+//! Add a [`PortalPickingFilter`] to a [`Portal`] to control which entities on its far side can be
+//! picked through it.
+
+use std::sync::Arc;
 
 use bevy::{
+    ecs::event::EventMutator,
     picking::{
+        backend::{HitData, PointerHits},
         focus::HoverMap,
-        pointer::{Location, PointerAction, PointerId, PointerInput, PointerLocation},
+        pointer::{
+            Location, PointerAction, PointerButton, PointerId, PointerInput, PointerLocation,
+            PressDirection,
+        },
         PickSet,
     },
     prelude::*,
-    utils::HashSet,
+    render::{primitives::Aabb, view::RenderLayers},
+    utils::{HashMap, HashSet},
+    window::PrimaryWindow,
 };
 use uuid::Uuid;
 
-use crate::{Portal, PortalCamera};
+use crate::{LinkedPortalCamera, Portal};
 
 /// Enables picking "through" [`Portal`]s.
 pub struct PortalPickingPlugin;
@@ -25,14 +52,29 @@ pub struct PortalPickingPlugin;
 impl Plugin for PortalPickingPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<PortalInput>()
+            .add_event::<PortalPointerHits>()
+            .add_event::<PortalPointerHit>()
+            .init_resource::<PortalDrags>()
+            .init_resource::<PortalPointers>()
+            .init_resource::<PortalHovers>()
             .add_systems(
                 PreUpdate,
                 (
                     portal_inputs.in_set(PickSet::Input),
-                    portal_hover.in_set(PickSet::PostFocus),
+                    filter_portal_pointer_hits
+                        .after(PickSet::Backend)
+                        .before(PickSet::Focus),
+                    (
+                        despawn_portal_pointers,
+                        portal_hover,
+                        leave_portal_pointers,
+                        send_portal_pointer_hits,
+                    )
+                        .chain()
+                        .in_set(PickSet::PostFocus),
                 ),
             )
-            .add_observer(add_pointer);
+            .add_observer(remove_portal_pointers);
     }
 }
 
@@ -43,22 +85,266 @@ struct PortalInput {
     action: PointerAction,
 }
 
-fn add_pointer(
-    trigger: Trigger<OnAdd, PortalCamera>,
+/// Component used to mark the virtual pointer a pointer's inputs are sent through a [`Portal`] by.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct PortalPointer {
+    /// The [`Portal`] the inputs are sent through.
+    pub portal: Entity,
+    /// The pointer whose inputs are sent through the portal.
+    pub pointer_id: PointerId,
+    /// The UV coordinates on the portal's mesh the pointer was last sent through at.
+    ///
+    /// These are computed from the mesh's [`Aabb`], so they only match the mesh's own UVs for flat
+    /// meshes like [`Rectangle`]s.
+    pub uv: Vec2,
+}
+
+/// Component used to control which entities on the far side of a [`Portal`] can be picked through
+/// it.
+///
+/// Hits of entities that don't pass the filter are discarded before they reach
+/// [`HoverMap`], so these entities receive no pointer events through the portal.
+#[derive(Component, Clone)]
+pub enum PortalPickingFilter {
+    /// Only the given entities can be picked.
+    Allow(HashSet<Entity>),
+    /// All entities except the given ones can be picked.
+    Deny(HashSet<Entity>),
+    /// Only entities with [`RenderLayers`] intersecting the given ones can be picked.
+    ///
+    /// Entities without [`RenderLayers`] are on the default layer.
+    RenderLayers(RenderLayers),
+    /// Only entities the predicate returns `true` for can be picked.
+    Predicate(Arc<dyn Fn(Entity) -> bool + Send + Sync>),
+}
+
+impl PortalPickingFilter {
+    /// Creates a [`PortalPickingFilter::Predicate`] from a given `predicate`.
+    #[inline]
+    #[must_use]
+    pub fn predicate(predicate: impl Fn(Entity) -> bool + Send + Sync + 'static) -> Self {
+        Self::Predicate(Arc::new(predicate))
+    }
+
+    /// Returns whether `entity`, with the given `render_layers`, passes the filter.
+    pub fn allows(&self, entity: Entity, render_layers: Option<&RenderLayers>) -> bool {
+        match self {
+            Self::Allow(entities) => entities.contains(&entity),
+            Self::Deny(entities) => !entities.contains(&entity),
+            Self::RenderLayers(layers) => {
+                layers.intersects(render_layers.unwrap_or(&RenderLayers::default()))
+            }
+            Self::Predicate(predicate) => predicate(entity),
+        }
+    }
+}
+
+/// Event sent with the hits of a [`PortalPointer`], i.e. of a pointer through a [`Portal`].
+///
+/// Since the [`PortalPointer`]'s hits are found from the [`PortalCamera`](crate::PortalCamera),
+/// their [`HitData::position`] and [`HitData::normal`] are in the space of [`Portal::target`],
+/// e.g. to place a marker on the far side of the portal at the picked point.
+#[derive(Event, Debug, Clone)]
+pub struct PortalPointerHits {
+    /// The [`Portal`] the pointer picks through.
+    pub portal: Entity,
+    /// The pointer picking through the portal.
+    pub pointer_id: PointerId,
+    /// The entities hit on the far side of the portal, along with their [`HitData`].
+    pub picks: Vec<(Entity, HitData)>,
+}
+
+/// Event sent for the closest hit of a [`PortalPointer`], i.e. whenever a pick crosses a
+/// [`Portal`].
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PortalPointerHit {
+    /// The [`Portal`] the pointer picks through.
+    pub portal: Entity,
+    /// The pointer picking through the portal.
+    pub pointer_id: PointerId,
+    /// The entity hit on the far side of the portal.
+    pub entity: Entity,
+    /// The UV coordinates on the portal's mesh the pointer picks through at.
+    ///
+    /// See [`PortalPointer::uv`].
+    pub uv: Vec2,
+    /// The position of the hit in the space of [`Portal::target`], if the backend reports it.
+    pub position: Option<Vec3>,
+}
+
+/// The virtual pointer used for each combination of a [`Portal`] and a pointer.
+#[derive(Resource, Default)]
+struct PortalPointers(HashMap<(Entity, PointerId), (Entity, PointerId)>);
+
+impl PortalPointers {
+    /// Returns the entity and [`PointerId`] of the virtual pointer used for `pointer_id` on
+    /// `portal`, spawning it if needed.
+    fn get_or_spawn(
+        &mut self,
+        commands: &mut Commands,
+        portal: Entity,
+        pointer_id: PointerId,
+        location: Location,
+        uv: Vec2,
+    ) -> (Entity, PointerId) {
+        *self.0.entry((portal, pointer_id)).or_insert_with(|| {
+            let virtual_pointer_id = PointerId::Custom(Uuid::new_v4());
+            let entity = commands
+                .spawn((
+                    Name::new("Portal Pointer"),
+                    PortalPointer {
+                        portal,
+                        pointer_id,
+                        uv,
+                    },
+                    virtual_pointer_id,
+                    PointerLocation::new(location),
+                ))
+                .id();
+            (entity, virtual_pointer_id)
+        })
+    }
+}
+
+/// The combinations of a [`Portal`] and a pointer that the pointer was inside of last frame.
+#[derive(Resource, Default)]
+struct PortalHovers(HashSet<(Entity, PointerId)>);
+
+/// Returns `location` moved off its render target, so that the pointer hovers nothing.
+fn outside(location: &Location) -> Location {
+    Location {
+        target: location.target.clone(),
+        position: Vec2::NEG_ONE,
+    }
+}
+
+/// System that despawns the virtual pointers of pointers that no longer exist, e.g. touches that
+/// ended.
+///
+/// The virtual pointers are moved off their image and released first, and despawned a frame later
+/// once these inputs have been processed.
+fn despawn_portal_pointers(
     mut commands: Commands,
-    query: Query<(&PortalCamera, &Camera)>,
+    mut portal_pointers: ResMut<PortalPointers>,
+    mut drags: ResMut<PortalDrags>,
+    mut hovers: ResMut<PortalHovers>,
+    pointer_query: Query<&PointerId>,
+    location_query: Query<&PointerLocation>,
+    mut portal_inputs: EventWriter<PortalInput>,
+    mut despawned: Local<Vec<Entity>>,
 ) {
-    let (marker, camera) = query.get(trigger.entity()).unwrap();
+    for entity in despawned.drain(..) {
+        if let Some(mut entity) = commands.get_entity(entity) {
+            entity.despawn();
+        }
+    }
 
-    let location = Location {
-        target: camera.target.normalize(None).unwrap(),
-        position: Vec2::ZERO,
-    };
+    let pointer_ids: HashSet<PointerId> = pointer_query.iter().copied().collect();
+    portal_pointers
+        .0
+        .retain(|&(portal, pointer_id), &mut (entity, virtual_pointer_id)| {
+            if pointer_ids.contains(&pointer_id) {
+                return true;
+            }
+            hovers.0.remove(&(portal, pointer_id));
+            if let Some(location) = location_query
+                .get(entity)
+                .ok()
+                .and_then(PointerLocation::location)
+            {
+                let location = outside(location);
+                for (&(_, button), _) in drags
+                    .0
+                    .iter()
+                    .filter(|&(&(id, _), &dragged)| id == pointer_id && dragged == portal)
+                {
+                    portal_inputs.send(PortalInput {
+                        pointer_id: virtual_pointer_id,
+                        location: location.clone(),
+                        action: PointerAction::Pressed {
+                            direction: PressDirection::Up,
+                            button,
+                        },
+                    });
+                }
+                portal_inputs.send(PortalInput {
+                    pointer_id: virtual_pointer_id,
+                    location,
+                    action: PointerAction::Moved { delta: Vec2::ZERO },
+                });
+            }
+            despawned.push(entity);
+            false
+        });
+    drags
+        .0
+        .retain(|(pointer_id, _), _| pointer_ids.contains(pointer_id));
+}
+
+/// System that moves the virtual pointer of a pointer off its image once the pointer leaves the
+/// [`Portal`], so that the entity it hovered on the far side receives `Out`.
+///
+/// Pointers dragging through a portal aren't considered to leave it until they are released.
+fn leave_portal_pointers(
+    portal_query: Query<(), With<Portal>>,
+    location_query: Query<&PointerLocation>,
+    hover_map: Res<HoverMap>,
+    drags: Res<PortalDrags>,
+    portal_pointers: Res<PortalPointers>,
+    mut hovers: ResMut<PortalHovers>,
+    mut portal_inputs: EventWriter<PortalInput>,
+) {
+    let hovered: HashSet<(Entity, PointerId)> = hover_map
+        .iter()
+        .flat_map(|(&pointer_id, hits)| hits.keys().map(move |&entity| (entity, pointer_id)))
+        .filter(|&(entity, _)| portal_query.contains(entity))
+        .collect();
 
-    commands.entity(marker.0).insert((
-        PointerId::Custom(Uuid::new_v4()),
-        PointerLocation::new(location),
-    ));
+    for (&(portal, pointer_id), &(entity, virtual_pointer_id)) in &portal_pointers.0 {
+        let inside = hovered.contains(&(portal, pointer_id))
+            || drags
+                .0
+                .iter()
+                .any(|(&(id, _), &dragged)| id == pointer_id && dragged == portal);
+        if inside {
+            hovers.0.insert((portal, pointer_id));
+            continue;
+        }
+        if !hovers.0.remove(&(portal, pointer_id)) {
+            continue;
+        }
+        let Some(location) = location_query
+            .get(entity)
+            .ok()
+            .and_then(PointerLocation::location)
+        else {
+            continue;
+        };
+        portal_inputs.send(PortalInput {
+            pointer_id: virtual_pointer_id,
+            location: outside(location),
+            action: PointerAction::Moved { delta: Vec2::ZERO },
+        });
+    }
+}
+
+/// Observer that despawns the virtual pointers of a [`Portal`] when it is removed.
+fn remove_portal_pointers(
+    trigger: Trigger<OnRemove, Portal>,
+    mut commands: Commands,
+    mut portal_pointers: ResMut<PortalPointers>,
+    mut drags: ResMut<PortalDrags>,
+    mut hovers: ResMut<PortalHovers>,
+) {
+    let portal = trigger.entity();
+    portal_pointers.0.retain(|&(entity, _), &mut (pointer, _)| {
+        if entity == portal {
+            commands.entity(pointer).despawn();
+        }
+        entity != portal
+    });
+    drags.0.retain(|_, &mut entity| entity != portal);
+    hovers.0.retain(|&(entity, _)| entity != portal);
 }
 
 fn portal_inputs(
@@ -74,58 +360,108 @@ fn portal_inputs(
     }
 }
 
+/// The [`Portal`] each pressed button of a pointer was pressed through.
+///
+/// Inputs of a pointer keep being sent through the portal until the button is released, so that
+/// drags stay coherent when the pointer leaves the portal.
+#[derive(Resource, Default)]
+struct PortalDrags(HashMap<(PointerId, PointerButton), Entity>);
+
 fn portal_hover(
-    portal_query: Query<(&Portal, &Transform, &PointerId, &PointerLocation)>,
+    mut commands: Commands,
+    portal_query: Query<(
+        &Portal,
+        Option<&LinkedPortalCamera>,
+        &Transform,
+        &GlobalTransform,
+        &Aabb,
+    )>,
+    mut portal_pointer_query: Query<&mut PortalPointer>,
     camera_global_transform_query: Query<(&Camera, &GlobalTransform)>,
     camera_query: Query<&Camera>,
+    primary_window_query: Query<Entity, With<PrimaryWindow>>,
     hover_map: Res<HoverMap>,
+    mut drags: ResMut<PortalDrags>,
+    mut portal_pointers: ResMut<PortalPointers>,
     mut pointer_inputs: EventReader<PointerInput>,
     mut portal_inputs: EventWriter<PortalInput>,
-    mut drag_events: EventReader<Pointer<Drag>>,
-    mut drag_end_events: EventReader<Pointer<DragEnd>>,
 ) {
-    let mut portals: HashSet<(PointerId, Entity)> = HashSet::new();
+    let primary_window = primary_window_query.get_single().ok();
+    for input in pointer_inputs.read() {
+        let hovered: HashSet<Entity> = hover_map
+            .get(&input.pointer_id)
+            .into_iter()
+            .flat_map(|hits| hits.keys())
+            .copied()
+            .filter(|&entity| portal_query.contains(entity))
+            .collect();
+        let dragged = drags
+            .0
+            .iter()
+            .filter(|((pointer_id, _), _)| *pointer_id == input.pointer_id)
+            .map(|(_, &entity)| entity);
+        let portals: HashSet<Entity> = hovered.iter().copied().chain(dragged).collect();
 
-    for (hover_pointer_id, hits) in hover_map.iter() {
-        for (entity, _hit_data) in hits.iter() {
-            if portal_query.contains(*entity) {
-                portals.insert((*hover_pointer_id, *entity));
+        for entity in portals {
+            // Presses are only sent through hovered portals, and releases only through the portal
+            // the button was pressed through
+            let send = match input.action {
+                PointerAction::Pressed {
+                    direction: PressDirection::Down,
+                    button,
+                } => {
+                    let hovered = hovered.contains(&entity);
+                    if hovered {
+                        drags.0.insert((input.pointer_id, button), entity);
+                    }
+                    hovered
+                }
+                PointerAction::Pressed {
+                    direction: PressDirection::Up,
+                    button,
+                } => {
+                    let pressed = drags.0.get(&(input.pointer_id, button)) == Some(&entity);
+                    if pressed {
+                        drags.0.remove(&(input.pointer_id, button));
+                    }
+                    pressed
+                }
+                PointerAction::Moved { .. } => true,
+                PointerAction::Canceled => {
+                    drags
+                        .0
+                        .retain(|(pointer_id, _), _| *pointer_id != input.pointer_id);
+                    true
+                }
+            };
+            if !send {
+                continue;
             }
-        }
-    }
-
-    // Currently, we have only retrieved portal entities if they are being hovered. However, this
-    // does not allow dragging in-and-out of portals.
-    for event in drag_events
-        .read()
-        .filter(|event| portal_query.contains(event.target))
-    {
-        portals.insert((event.pointer_id, event.target));
-    }
-    for event in drag_end_events
-        .read()
-        .filter(|event| portal_query.contains(event.target))
-    {
-        portals.insert((event.pointer_id, event.target));
-    }
-
-    for (pointer_id, entity) in portals {
-        let (portal, &portal_transform, &portal_pointer_id, portal_pointer_location) =
-            portal_query.get(entity).unwrap();
-
-        let portal_camera = camera_query.get(portal.linked_camera.unwrap()).unwrap();
-        let Ok((primary_camera, primary_camera_transform)) =
-            camera_global_transform_query.get(portal.primary_camera)
-        else {
-            continue;
-        };
-        let target = portal_pointer_location.location().cloned().unwrap().target;
 
-        for input in pointer_inputs.read() {
-            // We only care about inputs related to the hovering pointer
-            if input.pointer_id != pointer_id {
+            let Ok((portal, linked_camera, &portal_transform, portal_global_transform, aabb)) =
+                portal_query.get(entity)
+            else {
+                continue;
+            };
+            let Some(Ok(portal_camera)) =
+                linked_camera.map(|linked_camera| camera_query.get(linked_camera.get()))
+            else {
+                continue;
+            };
+            let Ok((primary_camera, primary_camera_transform)) =
+                camera_global_transform_query.get(portal.primary_camera)
+            else {
+                continue;
+            };
+            // Pointers in other windows, e.g. tool windows, can't interact with this portal
+            if primary_camera.target.normalize(primary_window).as_ref()
+                != Some(&input.location.target)
+            {
                 continue;
             }
+            let Some(target) = portal_camera.target.normalize(None) else {
+                continue;
+            };
 
             // Manually retrieve the current pointer's position, so that it doesn't lag a frame
             // behind
@@ -140,20 +476,106 @@ fn portal_hover(
             ) else {
                 continue;
             };
-            let Ok(position) =
-                portal_camera.world_to_viewport(primary_camera_transform, ray.get_point(distance))
+            let point = ray.get_point(distance);
+            let Ok(viewport_position) =
+                portal_camera.world_to_viewport(primary_camera_transform, point)
             else {
                 continue;
             };
+            // Locations are relative to the render target, not to the camera's (scissored or atlas
+            // cell) viewport, which backends like the UI backend rely on
+            let viewport_min = portal_camera
+                .logical_viewport_rect()
+                .map_or(Vec2::ZERO, |rect| rect.min);
+            let position = viewport_min + viewport_position;
+
+            let local_point = portal_global_transform
+                .affine()
+                .inverse()
+                .transform_point3(point);
+            let min = Vec3::from(aabb.min()).truncate();
+            let size = Vec3::from(aabb.half_extents).truncate() * 2.0;
+            let uv = (local_point.truncate() - min) / size.max(Vec2::splat(f32::EPSILON));
+            let uv = Vec2::new(uv.x, 1.0 - uv.y);
 
+            let location = Location { target, position };
+            let (pointer, pointer_id) = portal_pointers.get_or_spawn(
+                &mut commands,
+                entity,
+                input.pointer_id,
+                location.clone(),
+                uv,
+            );
+            if let Ok(mut portal_pointer) = portal_pointer_query.get_mut(pointer) {
+                portal_pointer.uv = uv;
+            }
             portal_inputs.send(PortalInput {
-                pointer_id: portal_pointer_id,
-                location: Location {
-                    target: target.clone(),
-                    position,
-                },
+                pointer_id,
+                location,
                 action: input.action,
             });
         }
     }
 }
+
+/// System that sends a [`PortalPointerHits`] event for the hits of every [`PortalPointer`], and a
+/// [`PortalPointerHit`] event for the closest of them.
+fn send_portal_pointer_hits(
+    pointer_query: Query<(&PointerId, &PortalPointer)>,
+    mut pointer_hits: EventReader<PointerHits>,
+    mut portal_pointer_hits: EventWriter<PortalPointerHits>,
+    mut portal_pointer_hit: EventWriter<PortalPointerHit>,
+) {
+    let portal_pointers: HashMap<PointerId, PortalPointer> = pointer_query
+        .iter()
+        .map(|(&pointer_id, &portal_pointer)| (pointer_id, portal_pointer))
+        .collect();
+
+    for hits in pointer_hits.read() {
+        let Some(portal_pointer) = portal_pointers.get(&hits.pointer) else {
+            continue;
+        };
+        if let Some((entity, hit)) = hits
+            .picks
+            .iter()
+            .min_by(|(_, a), (_, b)| a.depth.total_cmp(&b.depth))
+        {
+            portal_pointer_hit.send(PortalPointerHit {
+                portal: portal_pointer.portal,
+                pointer_id: portal_pointer.pointer_id,
+                entity: *entity,
+                uv: portal_pointer.uv,
+                position: hit.position,
+            });
+        }
+        portal_pointer_hits.send(PortalPointerHits {
+            portal: portal_pointer.portal,
+            pointer_id: portal_pointer.pointer_id,
+            picks: hits.picks.clone(),
+        });
+    }
+}
+
+/// System that discards the hits of [`PortalPointer`]s that don't pass the
+/// [`PortalPickingFilter`] of their [`Portal`].
+fn filter_portal_pointer_hits(
+    pointer_query: Query<(&PointerId, &PortalPointer)>,
+    filter_query: Query<&PortalPickingFilter>,
+    render_layers_query: Query<&RenderLayers>,
+    mut pointer_hits: EventMutator<PointerHits>,
+) {
+    let filters: HashMap<PointerId, &PortalPickingFilter> = pointer_query
+        .iter()
+        .filter_map(|(&pointer_id, portal_pointer)| {
+            Some((pointer_id, filter_query.get(portal_pointer.portal).ok()?))
+        })
+        .collect();
+
+    for hits in pointer_hits.read() {
+        let Some(filter) = filters.get(&hits.pointer) else {
+            continue;
+        };
+        hits.picks
+            .retain(|&(entity, _)| filter.allows(entity, render_layers_query.get(entity).ok()));
+    }
+}
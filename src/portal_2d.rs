@@ -0,0 +1,551 @@
+//! Portals for 2D scenes.
+//!
+//! Add the [`Portal2dPlugin`], then use the [`Portal2d`] component on an entity with a [`Mesh2d`].
+//! This works the same way as a [`Portal`](crate::Portal), but with a [`Camera2d`] as the primary
+//! camera.
+//!
+//! [`PortalTeleporter`] and [`PortalTraveler`] work with [`Portal2d`]s too: a traveler is moved
+//! into the space of [`Portal2d::target`] as soon as it enters the portal's mesh.
+
+use bevy::{
+    asset::load_internal_asset,
+    ecs::{component::ComponentId, entity::Entities, world::DeferredWorld},
+    image::ImageSampler,
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        primitives::Aabb,
+        render_resource::{AsBindGroup, ShaderRef, TextureFormat},
+    },
+    sprite::{Material2d, Material2dPlugin},
+    utils::{HashMap, HashSet},
+    window::{PrimaryWindow, WindowResized},
+};
+
+use crate::{
+    camera_window,
+    image_pool::{self, PendingImageResizes},
+    teleport::{PortalTeleportSystems, PortalTeleporter, PortalTraveler},
+    transform_through_portal, PortalCameraSystems, PortalError, PortalErrorKind, PortalImagePool,
+    PortalPluginSettings, PortalSetupState, SetupPortal, ViewportSize,
+};
+
+const PORTAL_2D_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(92252998967241081383031717488501611122);
+
+/// A plugin that provides the required systems to make a [`Portal2d`] work.
+pub struct Portal2dPlugin;
+
+impl Plugin for Portal2dPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            PORTAL_2D_SHADER_HANDLE,
+            concat!(env!("CARGO_MANIFEST_DIR"), "/assets/portal_2d.wgsl"),
+            Shader::from_wgsl
+        );
+
+        // Set up by `PortalPlugin` too, whichever is added first
+        image_pool::init_image_pool(app);
+        // Replaced by the settings of `PortalPlugin`, if it is added too
+        app.init_resource::<PortalPluginSettings>();
+
+        app.add_plugins(Material2dPlugin::<Portal2dMaterial>::default())
+            .add_event::<PortalError>()
+            .add_systems(
+                PreUpdate,
+                (
+                    resize_portal_2d_images
+                        .before(image_pool::apply_pending_image_resizes)
+                        .in_set(PortalCameraSystems::ResizeImage),
+                    retry_portal_2d_setups,
+                    refresh_portal_2d_materials,
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    teleport_travelers_2d
+                        .in_set(PortalTeleportSystems)
+                        .before(TransformSystem::TransformPropagate),
+                    update_portal_2d_camera_transform.after(TransformSystem::TransformPropagate),
+                ),
+            )
+            .add_observer(request_portal_2d_setup)
+            .add_observer(setup_portal_2d)
+            .add_observer(despawn_portal_2d_camera)
+            .register_required_components::<PortalTraveler, PortalTravelerTranslations2d>()
+            .register_type::<(
+                Portal2d,
+                Portal2dCamera,
+                LinkedPortal2dCamera,
+                PortalTeleporter,
+                PortalTraveler,
+                PortalSetupState,
+            )>();
+    }
+}
+
+/// Component used to create a 2D portal.
+///
+/// Adding this to an entity causes a camera (marked with [`Portal2dCamera`], and with
+/// [`RenderTarget::Image`]) to be spawned, inheriting the primary camera's properties.
+///
+/// A [`Portal2dMaterial`] is also inserted on the entity. The camera's order and the image's format
+/// follow [`PortalPluginSettings::camera_order_offset`] and
+/// [`PortalPluginSettings::texture_format`].
+///
+/// Like a [`Portal`](crate::Portal), setup is retried until the primary camera and target are
+/// ready, see [`PortalSetupState`].
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+#[require(Transform, PortalSetupState)]
+pub struct Portal2d {
+    /// The entity with the primary render [`Camera2d`].
+    ///
+    /// In other words, the [`Camera2d`] used to look at this portal.
+    pub primary_camera: Entity,
+    /// The target entity that should be used to decide the camera's position.
+    ///
+    /// This entity should contain a [`Transform`] component.
+    pub target: Entity,
+}
+
+impl Portal2d {
+    /// Creates a new [`Portal2d`] from a given `primary_camera` and `target`.
+    ///
+    /// # See Also
+    ///
+    /// * [`Portal2d::primary_camera`]
+    /// * [`Portal2d::target`]
+    #[inline]
+    #[must_use]
+    pub fn new(primary_camera: Entity, target: Entity) -> Self {
+        Self {
+            primary_camera,
+            target,
+        }
+    }
+}
+
+/// Component used to mark a [`Portal2d`]'s associated camera, holding the [`Portal2d`] entity.
+///
+/// Inserting it inserts a matching [`LinkedPortal2dCamera`] on the [`Portal2d`], and removing it
+/// removes that [`LinkedPortal2dCamera`] again.
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+#[require(Camera2d)]
+#[component(on_insert = link_portal_2d_camera, on_replace = unlink_portal_2d_camera)]
+pub struct Portal2dCamera(pub Entity);
+
+/// Component holding the entity with a [`Portal2d`]'s [`Portal2dCamera`].
+///
+/// This is kept in sync with [`Portal2dCamera`], so it can't be inserted manually.
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+#[reflect(Component)]
+pub struct LinkedPortal2dCamera(Entity);
+
+impl LinkedPortal2dCamera {
+    /// Returns the entity with the [`Portal2dCamera`].
+    #[inline]
+    #[must_use]
+    pub fn get(&self) -> Entity {
+        self.0
+    }
+}
+
+/// Hook that inserts a [`LinkedPortal2dCamera`] on the [`Portal2d`] of an inserted
+/// [`Portal2dCamera`].
+fn link_portal_2d_camera(mut world: DeferredWorld, camera: Entity, _: ComponentId) {
+    let Some(&Portal2dCamera(portal)) = world.get::<Portal2dCamera>(camera) else {
+        return;
+    };
+    world.commands().queue(move |world: &mut World| {
+        if let Ok(mut portal) = world.get_entity_mut(portal) {
+            portal.insert(LinkedPortal2dCamera(camera));
+        }
+    });
+}
+
+/// Hook that removes the [`LinkedPortal2dCamera`] of the [`Portal2d`] of a removed or replaced
+/// [`Portal2dCamera`], unless the [`Portal2d`] was linked to another camera since.
+fn unlink_portal_2d_camera(mut world: DeferredWorld, camera: Entity, _: ComponentId) {
+    let Some(&Portal2dCamera(portal)) = world.get::<Portal2dCamera>(camera) else {
+        return;
+    };
+    // The portal may be in the middle of being despawned
+    world.commands().queue(move |world: &mut World| {
+        let Ok(mut portal) = world.get_entity_mut(portal) else {
+            return;
+        };
+        if portal.get::<LinkedPortal2dCamera>() == Some(&LinkedPortal2dCamera(camera)) {
+            portal.remove::<LinkedPortal2dCamera>();
+        }
+    });
+}
+
+/// Material used for a [`Portal2d`]'s mesh.
+#[derive(Asset, AsBindGroup, Clone, TypePath)]
+pub struct Portal2dMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    base_color_texture: Option<Handle<Image>>,
+}
+
+impl Material2d for Portal2dMaterial {
+    fn fragment_shader() -> ShaderRef {
+        PORTAL_2D_SHADER_HANDLE.into()
+    }
+}
+
+/// Observer that sets up a [`Portal2d`] once it is added to an entity.
+fn request_portal_2d_setup(trigger: Trigger<OnAdd, Portal2d>, mut commands: Commands) {
+    commands.trigger_targets(
+        SetupPortal {
+            report_pending: true,
+        },
+        trigger.entity(),
+    );
+}
+
+/// System that retries setting up [`Portal2d`]s that are [`PortalSetupState::Pending`], or
+/// [`PortalSetupState::Failed`] and changed since.
+fn retry_portal_2d_setups(
+    mut commands: Commands,
+    portal_query: Query<(Entity, Ref<Portal2d>, &PortalSetupState)>,
+) {
+    for (entity, portal, setup_state) in &portal_query {
+        let report_pending = match setup_state {
+            PortalSetupState::Pending => false,
+            PortalSetupState::Active => continue,
+            PortalSetupState::Failed if portal.is_changed() => true,
+            PortalSetupState::Failed => continue,
+        };
+        commands.trigger_targets(SetupPortal { report_pending }, entity);
+    }
+}
+
+/// System that is triggered whenever a [`Portal2d`] needs to be set up, see [`PortalSetupState`].
+///
+/// An image is created based on the primary camera's viewport size. Then, a [`Portal2dCamera`] is
+/// created, with [`Camera::target`] set to render the [`Portal2dCamera`]'s view to the image.
+///
+/// Finally, a [`Portal2dMaterial`] is added to the [`Portal2d`] entity.
+fn setup_portal_2d(
+    trigger: Trigger<SetupPortal>,
+    mut commands: Commands,
+    mut portal_query: Query<(&Portal2d, &mut PortalSetupState)>,
+    primary_camera_query: Query<(&Camera, Option<&OrthographicProjection>)>,
+    mut images: ResMut<Assets<Image>>,
+    mut image_pool: ResMut<PortalImagePool>,
+    mut portal_materials: ResMut<Assets<Portal2dMaterial>>,
+    global_transform_query: Query<&GlobalTransform>,
+    viewport_size: ViewportSize,
+    entities: &Entities,
+    settings: Res<PortalPluginSettings>,
+    mut error_writer: EventWriter<PortalError>,
+) {
+    let entity = trigger.entity();
+    let mut report = |kind| {
+        error_writer.send(PortalError {
+            portal: entity,
+            kind,
+        });
+    };
+
+    let Ok((portal, mut setup_state)) = portal_query.get_mut(entity) else {
+        return;
+    };
+    if *setup_state == PortalSetupState::Active {
+        return;
+    }
+
+    // Missing entities won't show up later, so only retry once the portal changes
+    let missing = [portal.primary_camera, portal.target]
+        .into_iter()
+        .find(|&required| !entities.contains(required));
+    if let Some(missing) = missing {
+        if *setup_state != PortalSetupState::Failed {
+            error!("could not setup portal {entity}: entity {missing} does not exist");
+            report(PortalErrorKind::MissingEntity(missing));
+            *setup_state = PortalSetupState::Failed;
+        }
+        return;
+    }
+    let report_pending = trigger.event().report_pending;
+
+    let Ok((primary_camera, projection)) = primary_camera_query.get(portal.primary_camera) else {
+        if report_pending {
+            warn!("could not setup portal {entity} yet: primary_camera has no Camera component");
+            report(PortalErrorKind::MissingCamera);
+        }
+        *setup_state = PortalSetupState::Pending;
+        return;
+    };
+
+    let Some(size) = viewport_size.get_viewport_size(primary_camera) else {
+        if report_pending {
+            warn!("could not compute viewport size for portal {entity} yet");
+            report(match primary_camera.target {
+                RenderTarget::Image(_) => PortalErrorKind::UnsupportedRenderTarget,
+                _ => PortalErrorKind::UnknownViewportSize,
+            });
+        }
+        *setup_state = PortalSetupState::Pending;
+        return;
+    };
+
+    let Ok(global_transform) = global_transform_query.get(portal.target).copied() else {
+        if report_pending {
+            warn!(
+                "could not setup portal {entity} yet: portal target is missing a GlobalTransform"
+            );
+            report(PortalErrorKind::MissingTargetTransform);
+        }
+        *setup_state = PortalSetupState::Pending;
+        return;
+    };
+    *setup_state = PortalSetupState::Active;
+
+    let image_handle = image_pool.acquire(
+        &mut images,
+        size,
+        1.0,
+        settings
+            .texture_format
+            .unwrap_or(TextureFormat::Bgra8UnormSrgb),
+        &ImageSampler::Default,
+    );
+    commands.spawn((
+        Name::new("Portal 2D Camera"),
+        Camera {
+            order: settings.camera_order_offset,
+            target: RenderTarget::Image(image_handle.clone()),
+            ..primary_camera.clone()
+        },
+        global_transform.compute_transform(),
+        global_transform,
+        projection
+            .cloned()
+            .unwrap_or_else(OrthographicProjection::default_2d),
+        Portal2dCamera(entity),
+    ));
+
+    commands
+        .entity(entity)
+        .insert(MeshMaterial2d(portal_materials.add(Portal2dMaterial {
+            base_color_texture: Some(image_handle),
+        })));
+}
+
+/// System that is triggered whenever a [`Portal2d`] component is removed from an entity.
+///
+/// The [`Portal2dCamera`] is despawned, its image is released to the [`PortalImagePool`], and the
+/// [`Portal2dMaterial`] is removed.
+fn despawn_portal_2d_camera(
+    trigger: Trigger<OnRemove, Portal2d>,
+    mut commands: Commands,
+    portal_query: Query<&LinkedPortal2dCamera>,
+    camera_query: Query<&Camera, With<Portal2dCamera>>,
+    mut image_pool: ResMut<PortalImagePool>,
+) {
+    let entity = trigger.entity();
+
+    if let Ok(linked_camera) = portal_query.get(entity) {
+        if let Ok(Camera {
+            target: RenderTarget::Image(image_handle),
+            ..
+        }) = camera_query.get(linked_camera.get())
+        {
+            image_pool.release(image_handle.clone());
+        }
+        commands.entity(linked_camera.get()).despawn_recursive();
+    }
+    // The entity may be in the middle of being despawned
+    commands.queue(move |world: &mut World| {
+        if let Ok(mut entity) = world.get_entity_mut(entity) {
+            entity.remove::<MeshMaterial2d<Portal2dMaterial>>();
+        }
+    });
+}
+
+/// System that updates a [`Portal2dCamera`]'s translation and rotation based on the primary
+/// camera.
+///
+/// # Notes
+///
+/// * Both [`Transform`] and [`GlobalTransform`] are updated.
+/// * The depth of the primary camera is kept, so that the same entities are in view.
+fn update_portal_2d_camera_transform(
+    portal_query: Query<
+        (&GlobalTransform, &Portal2d, &LinkedPortal2dCamera),
+        Without<Portal2dCamera>,
+    >,
+    global_transform_query: Query<&GlobalTransform, Without<Portal2dCamera>>,
+    mut portal_camera_transform_query: Query<
+        (&mut GlobalTransform, &mut Transform),
+        With<Portal2dCamera>,
+    >,
+) {
+    for (portal_global_transform, portal, linked_camera) in &portal_query {
+        let Ok([primary_camera_transform, target_transform]) = global_transform_query
+            .get_many([portal.primary_camera, portal.target])
+            .map(|transforms| transforms.map(GlobalTransform::compute_transform))
+        else {
+            continue;
+        };
+        let Ok((mut portal_camera_global_transform, mut portal_camera_transform)) =
+            portal_camera_transform_query.get_mut(linked_camera.get())
+        else {
+            continue;
+        };
+
+        let mut transform = transform_through_portal(
+            &portal_global_transform.compute_transform(),
+            &target_transform,
+            primary_camera_transform,
+        );
+        transform.translation.z = primary_camera_transform.translation.z;
+
+        *portal_camera_transform = transform;
+        *portal_camera_global_transform = GlobalTransform::from(transform);
+    }
+}
+
+/// The translation of a [`PortalTraveler`] relative to each [`Portal2d`] last frame.
+#[derive(Component, Default)]
+struct PortalTravelerTranslations2d(HashMap<Entity, Vec3>);
+
+/// System that teleports [`PortalTraveler`]s which entered the mesh of a [`Portal2d`] with a
+/// [`PortalTeleporter`] since last frame.
+///
+/// Travelers are moved to the same place relative to [`Portal2d::target`], which is where they are
+/// shown in the portal's image.
+fn teleport_travelers_2d(
+    portal_query: Query<(Entity, &Portal2d, &GlobalTransform, &Aabb), With<PortalTeleporter>>,
+    global_transform_query: Query<&GlobalTransform>,
+    mut traveler_query: Query<
+        (&mut Transform, &mut PortalTravelerTranslations2d),
+        With<PortalTraveler>,
+    >,
+) {
+    for (mut transform, mut translations) in &mut traveler_query {
+        let mut previous_translations = std::mem::take(&mut translations.0);
+        for (entity, portal, portal_global_transform, aabb) in &portal_query {
+            let local_translation = portal_global_transform
+                .affine()
+                .inverse()
+                .transform_point3(transform.translation);
+            translations.0.insert(entity, local_translation);
+
+            let inside = |translation: Vec3| {
+                let offset = (translation - Vec3::from(aabb.center)).truncate();
+                offset
+                    .abs()
+                    .cmple(Vec3::from(aabb.half_extents).truncate())
+                    .all()
+            };
+            let Some(previous_translation) = previous_translations.remove(&entity) else {
+                continue;
+            };
+            if inside(previous_translation) || !inside(local_translation) {
+                continue;
+            }
+
+            let Ok(target_global_transform) = global_transform_query.get(portal.target) else {
+                continue;
+            };
+
+            let mut teleported = transform_through_portal(
+                &portal_global_transform.compute_transform(),
+                &target_global_transform.compute_transform(),
+                *transform,
+            );
+            teleported.translation.z = transform.translation.z;
+            *transform = teleported;
+
+            // Translations relative to other portals are no longer meaningful
+            translations.0.clear();
+            break;
+        }
+    }
+}
+
+/// System that resizes the image of each [`Portal2d`] to the viewport size of its primary camera
+/// whenever the window the primary camera renders to is resized.
+///
+/// Images are only resized once their size has settled, see
+/// [`PortalPlugin::resize_delay_frames`](crate::PortalPlugin::resize_delay_frames).
+fn resize_portal_2d_images(
+    mut resized_reader: EventReader<WindowResized>,
+    primary_window_query: Query<Entity, With<PrimaryWindow>>,
+    portal_query: Query<(&Portal2d, &LinkedPortal2dCamera)>,
+    camera_query: Query<&Camera>,
+    mut image_pool: ResMut<PortalImagePool>,
+    mut pending_resizes: ResMut<PendingImageResizes>,
+    viewport_size: ViewportSize,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if resized_reader.is_empty() {
+        return;
+    }
+    let resized_windows = resized_reader
+        .read()
+        .map(|event| event.window)
+        .collect::<HashSet<_>>();
+    let primary_window = primary_window_query.get_single().ok();
+
+    for (portal, linked_camera) in &portal_query {
+        let Ok(primary_camera) = camera_query.get(portal.primary_camera) else {
+            continue;
+        };
+        let window_resized = camera_window(primary_camera, primary_window)
+            .is_some_and(|window| resized_windows.contains(&window));
+        if !window_resized {
+            continue;
+        }
+        let Some(size) = viewport_size.get_viewport_size(primary_camera) else {
+            continue;
+        };
+
+        let Ok(Camera {
+            target: RenderTarget::Image(image_handle),
+            ..
+        }) = camera_query.get(linked_camera.get())
+        else {
+            continue;
+        };
+
+        pending_resizes.resize_image(&mut image_pool, &mut images, image_handle, size);
+    }
+}
+
+/// System that marks [`Portal2dMaterial`]s as changed whenever their image is modified, e.g.
+/// resized.
+fn refresh_portal_2d_materials(
+    mut image_events: EventReader<AssetEvent<Image>>,
+    portal_query: Query<&MeshMaterial2d<Portal2dMaterial>, With<Portal2d>>,
+    mut portal_materials: ResMut<Assets<Portal2dMaterial>>,
+) {
+    let modified = image_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    if modified.is_empty() {
+        return;
+    }
+
+    for material_handle in &portal_query {
+        if portal_materials
+            .get(material_handle)
+            .and_then(|material| material.base_color_texture.as_ref())
+            .is_some_and(|image| modified.contains(&image.id()))
+        {
+            // Blocked on https://github.com/bevyengine/bevy/issues/5069
+            portal_materials.get_mut(material_handle);
+        }
+    }
+}
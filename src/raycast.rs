@@ -0,0 +1,225 @@
+//! Following rays through [`Portal`]s.
+//!
+//! Use the [`PortalRayCast`] system parameter to follow a ray through the [`Portal`]s it hits, e.g.
+//! for hitscan weapons or laser sights. The ray is split into [`PortalRaySegment`]s, which can be
+//! cast against the scene in order (e.g. with the ray casts of a physics engine) until something
+//! is hit.
+//!
+//! Add the [`PortalRayCastPlugin`] to follow the forward ray of every [`PortalRayCaster`] each
+//! frame.
+//!
+//! # Notes
+//!
+//! * Only the meshes of [`Portal`]s are tested, so a ray isn't stopped by anything else in the
+//!   scene.
+//! * Rays pass through [`Mirror`]s, and through the sides of [`Portal`]s that are culled.
+
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+    render::{primitives::Aabb, render_resource::Face},
+};
+
+use crate::{portal_transform_ray, transform_through_portal, Mirror, Portal, PortalBackFace};
+
+/// Plugin that follows the forward ray of every [`PortalRayCaster`] through [`Portal`]s.
+pub struct PortalRayCastPlugin;
+
+impl Plugin for PortalRayCastPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            update_portal_ray_segments.after(TransformSystem::TransformPropagate),
+        )
+        .register_type::<PortalRayCaster>();
+    }
+}
+
+/// Component used to follow the forward ray of an entity through [`Portal`]s every frame.
+///
+/// The result is stored in [`PortalRaySegments`]. This requires the [`PortalRayCastPlugin`].
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component, Default)]
+#[require(PortalRaySegments)]
+pub struct PortalRayCaster {
+    /// The total length of the ray, across all [`Portal`]s.
+    ///
+    /// Defaults to `100.0`.
+    pub max_distance: f32,
+    /// The number of [`Portal`]s the ray can pass through.
+    ///
+    /// Defaults to `8`.
+    pub max_depth: usize,
+}
+
+impl Default for PortalRayCaster {
+    fn default() -> Self {
+        Self {
+            max_distance: 100.0,
+            max_depth: 8,
+        }
+    }
+}
+
+/// The [`PortalRaySegment`]s of the forward ray of a [`PortalRayCaster`].
+#[derive(Component, Debug, Default, Clone, Deref)]
+pub struct PortalRaySegments(pub Vec<PortalRaySegment>);
+
+/// A straight part of a ray followed through [`Portal`]s.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PortalRaySegment {
+    /// The ray this segment is part of, starting at the start of the segment.
+    pub ray: Ray3d,
+    /// The length of the segment along [`PortalRaySegment::ray`].
+    pub length: f32,
+    /// The [`Portal`] entity the segment ends at, if any.
+    pub portal: Option<Entity>,
+}
+
+impl PortalRaySegment {
+    /// Returns the point the segment ends at.
+    #[inline]
+    pub fn end(&self) -> Vec3 {
+        self.ray.get_point(self.length)
+    }
+}
+
+/// [`SystemParam`] used to follow rays through [`Portal`]s.
+#[derive(SystemParam)]
+pub struct PortalRayCast<'w, 's> {
+    portal_query: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Portal,
+            &'static GlobalTransform,
+            &'static Aabb,
+            Option<&'static Parent>,
+            Has<PortalBackFace>,
+        ),
+        Without<Mirror>,
+    >,
+    global_transform_query: Query<'w, 's, &'static GlobalTransform>,
+}
+
+impl PortalRayCast<'_, '_> {
+    /// Returns the closest [`Portal`] entering `ray` within `max_distance`, along with the
+    /// distance to it.
+    ///
+    /// # Notes
+    ///
+    /// * Secondary views of a [`Portal`] share its mesh, and are skipped.
+    pub fn first_hit(&self, ray: Ray3d, max_distance: f32) -> Option<(Entity, f32)> {
+        let mut closest: Option<(Entity, f32)> = None;
+        for (entity, portal, global_transform, aabb, parent, back_face) in &self.portal_query {
+            if !back_face && parent.is_some_and(|parent| self.portal_query.contains(parent.get())) {
+                continue;
+            }
+
+            // Positive values are in front of the portal's mesh
+            let local_from_world = global_transform.affine().inverse();
+            let origin = local_from_world.transform_point3(ray.origin);
+            let direction = local_from_world.transform_vector3(*ray.direction);
+            if direction.z.abs() < f32::EPSILON {
+                continue;
+            }
+            let front = origin.z > 0.0;
+            let can_enter = match portal.cull_mode {
+                Some(Face::Back) => front,
+                Some(Face::Front) => !front,
+                None => true,
+            };
+            // Rays entering from the back are handled by the other face
+            if !can_enter || ((back_face || portal.back_target.is_some()) && !front) {
+                continue;
+            }
+
+            // The affine transform is linear, so distances along the ray are the same locally
+            let distance = -origin.z / direction.z;
+            if distance <= 1e-4
+                || distance > max_distance
+                || closest.is_some_and(|(_, closest)| distance >= closest)
+            {
+                continue;
+            }
+            let point = (origin + direction * distance).truncate();
+            let min = Vec3::from(aabb.min()).truncate();
+            let max = Vec3::from(aabb.max()).truncate();
+            if point.cmplt(min).any() || point.cmpgt(max).any() {
+                continue;
+            }
+            closest = Some((entity, distance));
+        }
+        closest
+    }
+
+    /// Follows `ray` through up to `max_depth` [`Portal`]s, over a total length of
+    /// `max_distance`.
+    ///
+    /// The returned segments are in order, each one continuing the previous one in the space of
+    /// the previous [`Portal::target`]. Only the last segment doesn't end at a [`Portal`].
+    pub fn cast_ray(
+        &self,
+        mut ray: Ray3d,
+        max_distance: f32,
+        max_depth: usize,
+    ) -> Vec<PortalRaySegment> {
+        let mut segments = Vec::new();
+        let mut remaining_distance = max_distance;
+        for _ in 0..max_depth {
+            let Some((portal_entity, distance)) = self.first_hit(ray, remaining_distance) else {
+                break;
+            };
+            let Ok((_, portal, portal_global_transform, ..)) = self.portal_query.get(portal_entity)
+            else {
+                break;
+            };
+            let Ok(target_global_transform) = self.global_transform_query.get(portal.target) else {
+                break;
+            };
+
+            segments.push(PortalRaySegment {
+                ray,
+                length: distance,
+                portal: Some(portal_entity),
+            });
+            remaining_distance -= distance;
+            ray = portal_transform_ray(
+                &portal_global_transform.compute_transform(),
+                &target_global_transform.compute_transform(),
+                Ray3d::new(ray.get_point(distance), ray.direction),
+            );
+        }
+        segments.push(PortalRaySegment {
+            ray,
+            length: remaining_distance,
+            portal: None,
+        });
+        segments
+    }
+
+    /// Maps a `transform` relative to a given `portal` into the space of its [`Portal::target`].
+    ///
+    /// Returns `None` if `portal` is not a [`Portal`], or its target has no [`GlobalTransform`].
+    pub fn transform_through(&self, portal: Entity, transform: Transform) -> Option<Transform> {
+        let (_, portal, portal_global_transform, ..) = self.portal_query.get(portal).ok()?;
+        let target_global_transform = self.global_transform_query.get(portal.target).ok()?;
+        Some(transform_through_portal(
+            &portal_global_transform.compute_transform(),
+            &target_global_transform.compute_transform(),
+            transform,
+        ))
+    }
+}
+
+/// System that follows the forward ray of each [`PortalRayCaster`] through [`Portal`]s.
+fn update_portal_ray_segments(
+    mut caster_query: Query<(&PortalRayCaster, &GlobalTransform, &mut PortalRaySegments)>,
+    ray_cast: PortalRayCast,
+) {
+    for (caster, global_transform, mut segments) in &mut caster_query {
+        let ray = Ray3d::new(global_transform.translation(), global_transform.forward());
+        segments.0 = ray_cast.cast_ray(ray, caster.max_distance, caster.max_depth);
+    }
+}
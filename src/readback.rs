@@ -0,0 +1,151 @@
+//! Reading the image of a [`Portal`] back to the CPU.
+//!
+//! Add the [`PortalReadbackPlugin`], then spawn a [`PortalReadback`] to copy what a [`Portal`]
+//! shows to the CPU, e.g. to analyze what a security camera sees, or to compare against a golden
+//! image in tests. This wraps Bevy's [`Readback`], and hands back the data as an [`Image`] through
+//! a [`PortalReadbackComplete`] event triggered on the [`PortalReadback`] entity:
+//!
+//! ```no_run
+//! # use bevy::prelude::*;
+//! use bevy_easy_portals::readback::{PortalReadback, PortalReadbackComplete};
+//!
+//! fn read_back(mut commands: Commands, portal: Entity) {
+//!     commands.spawn(PortalReadback(portal)).observe(
+//!         |trigger: Trigger<PortalReadbackComplete>, mut commands: Commands| {
+//!             info!("portal image is {:?}", trigger.event().0.size());
+//!             // Stop reading back after the first image
+//!             commands.entity(trigger.entity()).despawn();
+//!         },
+//!     );
+//! }
+//! ```
+//!
+//! # Notes
+//!
+//! * Like [`Readback`], the image is read back every frame until the [`PortalReadback`] entity is
+//!   despawned, and arrives a few frames after it was rendered.
+//! * The whole image the [`PortalCamera`] renders to is read back. For portals in the
+//!   [`PortalAtlas`], that is the whole atlas, and with [`PortalImageAllocation::MaxMonitorSize`],
+//!   that includes the unused area of the image.
+//!
+//! [`PortalCamera`]: crate::PortalCamera
+//! [`PortalAtlas`]: crate::atlas::PortalAtlas
+//! [`PortalImageAllocation::MaxMonitorSize`]: crate::PortalImageAllocation::MaxMonitorSize
+
+use bevy::{
+    image::TextureFormatPixelInfo,
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        gpu_readback::{Readback, ReadbackComplete},
+        render_asset::RenderAssetUsages,
+        render_resource::TextureDimension,
+        renderer::RenderDevice,
+    },
+};
+
+use crate::{LinkedPortalCamera, Portal, PortalCamera};
+
+/// Plugin that reads back the images of [`PortalReadback`]s.
+pub struct PortalReadbackPlugin;
+
+impl Plugin for PortalReadbackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PostUpdate, request_portal_readbacks)
+            .add_observer(complete_portal_readback)
+            .register_type::<PortalReadback>();
+    }
+}
+
+/// Component used to read back the image of a [`Portal`] entity to the CPU.
+///
+/// A [`Readback`] of the image is inserted next to this component once the [`Portal`] is set up.
+/// Each time the data arrives, a [`PortalReadbackComplete`] is triggered on this entity.
+///
+/// This requires the [`PortalReadbackPlugin`].
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component)]
+pub struct PortalReadback(pub Entity);
+
+/// Event triggered on a [`PortalReadback`] entity with the image read back from the GPU.
+///
+/// The [`Image`] has the size and [`TextureFormat`] of the [`Portal`]'s image, and isn't added to
+/// [`Assets<Image>`].
+///
+/// [`TextureFormat`]: bevy::render::render_resource::TextureFormat
+#[derive(Event, Debug, Clone)]
+pub struct PortalReadbackComplete(pub Image);
+
+/// System that requests a [`Readback`] for each [`PortalReadback`] whose [`Portal`] has an image.
+fn request_portal_readbacks(
+    mut commands: Commands,
+    readback_query: Query<(Entity, &PortalReadback), Without<Readback>>,
+    portal_query: Query<Option<&LinkedPortalCamera>, With<Portal>>,
+    camera_query: Query<&Camera, With<PortalCamera>>,
+) {
+    for (entity, readback) in &readback_query {
+        let Ok(linked_camera) = portal_query.get(readback.0) else {
+            warn!(
+                "could not read back portal image: entity {} is not a portal",
+                readback.0
+            );
+            commands.entity(entity).despawn();
+            continue;
+        };
+        // The camera's target is the image itself, not its mip chain
+        let Some(Camera {
+            target: RenderTarget::Image(image_handle),
+            ..
+        }) = linked_camera.and_then(|linked_camera| camera_query.get(linked_camera.get()).ok())
+        else {
+            continue;
+        };
+
+        commands
+            .entity(entity)
+            .insert(Readback::texture(image_handle.clone()));
+    }
+}
+
+/// Observer that turns the data of a [`Readback`] of a [`PortalReadback`] into an [`Image`].
+fn complete_portal_readback(
+    trigger: Trigger<ReadbackComplete>,
+    mut commands: Commands,
+    readback_query: Query<&Readback, With<PortalReadback>>,
+    images: Res<Assets<Image>>,
+) {
+    let entity = trigger.entity();
+    let Ok(Readback::Texture(image_handle)) = readback_query.get(entity) else {
+        return;
+    };
+    let Some(image) = images.get(image_handle) else {
+        return;
+    };
+
+    let descriptor = &image.texture_descriptor;
+    let size = descriptor.size;
+    let bytes_per_row = size.width as usize * descriptor.format.pixel_size();
+    let padded_bytes_per_row = RenderDevice::align_copy_bytes_per_row(bytes_per_row);
+    let data = &trigger.event().0;
+    // Rows are padded for the copy from the texture
+    let data = if data.len() == padded_bytes_per_row * size.height as usize {
+        data.chunks_exact(padded_bytes_per_row)
+            .flat_map(|row| &row[..bytes_per_row])
+            .copied()
+            .collect()
+    } else if data.len() == bytes_per_row * size.height as usize {
+        data.clone()
+    } else {
+        // The image was resized while the data was on its way
+        return;
+    };
+
+    let image = Image::new(
+        size,
+        TextureDimension::D2,
+        data,
+        descriptor.format,
+        RenderAssetUsages::default(),
+    );
+    commands.trigger_targets(PortalReadbackComplete(image), entity);
+}
@@ -0,0 +1,551 @@
+//! Recursive rendering of [`Portal`]s.
+//!
+//! Without recursion, a [`PortalCamera`] can't show a [`Portal`] within its own view, since the
+//! image being rendered to can't be sampled at the same time. This module renders each [`Portal`]
+//! a further [`PortalPluginSettings::max_recursion_depth`] times, one extra camera and image per
+//! level.
+//!
+//! At each level `n`, every [`Portal`] gets a proxy mesh on the render layer
+//! [`RECURSION_RENDER_LAYER`]` + n`, which is only seen by portal cameras of the same level. The
+//! proxy samples the image of the next level, and the last level falls back to a flat color.
+//!
+//! # Notes
+//!
+//! * The [`Portal`]'s own mesh is hidden from portal cameras by moving it to one of the
+//!   [`VIEW_RENDER_LAYERS`], which is added to the primary camera. This is skipped if the
+//!   [`Portal`] already has [`RenderLayers`], in which case portal cameras shouldn't see them.
+//! * A level is rendered through the [`Portal`] closest in front of the previous level's camera,
+//!   so each level is only correct for one [`Portal`] at a time.
+//! * [`Mirror`]s are not rendered recursively.
+//! * The depth is read when a [`Portal`] is set up, so changing it only affects [`Portal`]s set up
+//!   afterwards.
+//!
+//! [`VIEW_RENDER_LAYERS`]: crate::VIEW_RENDER_LAYERS
+
+use bevy::{
+    core_pipeline::tonemapping::{DebandDither, Tonemapping},
+    prelude::*,
+    render::{
+        camera::{Exposure, RenderTarget},
+        primitives::{Aabb, Frustum},
+        view::{ColorGrading, RenderLayers},
+    },
+    utils::HashMap,
+};
+
+use crate::{
+    image_pool::PendingImageResizes, portal_near_clip_plane, transform_through_portal,
+    LinkedPortalCamera, Mirror, Portal, PortalCamera, PortalCameraSystems, PortalImagePool,
+    PortalMaterial, PortalPluginSettings, PortalProjection, PortalSurfaceMapping,
+};
+
+/// The first render layer used by recursion proxies.
+///
+/// Layers from this value up to (and including) this value plus
+/// [`PortalPluginSettings::max_recursion_depth`] are reserved when recursion is enabled.
+pub const RECURSION_RENDER_LAYER: usize = 24;
+
+/// Plugin that sets up recursive rendering of [`Portal`]s.
+///
+/// This is added by [`PortalPlugin`](crate::PortalPlugin), and shouldn't be added manually.
+pub(crate) struct PortalRecursionPlugin {
+    pub(crate) fallback_color: Color,
+}
+
+impl Plugin for PortalRecursionPlugin {
+    fn build(&self, app: &mut App) {
+        let fallback_material = app
+            .world_mut()
+            .resource_mut::<Assets<StandardMaterial>>()
+            .add(StandardMaterial {
+                base_color: self.fallback_color,
+                unlit: true,
+                ..default()
+            });
+
+        app.insert_resource(PortalRecursion { fallback_material })
+            .add_systems(
+                PreUpdate,
+                (
+                    resize_recursion_images.after(PortalCameraSystems::ResizeImage),
+                    update_recursion_fallback_color
+                        .run_if(resource_changed::<PortalPluginSettings>),
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    update_recursion_camera_transforms
+                        .in_set(PortalCameraSystems::UpdateTransform)
+                        .after(crate::update_portal_camera_transform),
+                    update_recursion_camera_frusta
+                        .in_set(PortalCameraSystems::UpdateFrusta)
+                        .after(crate::update_portal_camera_frusta),
+                    sync_recursion_camera_projections
+                        .in_set(PortalCameraSystems::SyncProjection)
+                        .after(crate::sync_portal_camera_projections),
+                    update_recursion_camera_activity
+                        .in_set(PortalCameraSystems::UpdateActivity)
+                        .after(crate::update_portal_camera_activity),
+                ),
+            )
+            .add_observer(setup_portal_recursion)
+            .add_observer(despawn_portal_recursion)
+            .register_type::<(PortalRecursionCamera, PortalRecursionProxy)>();
+    }
+}
+
+/// Resource holding the material shown on portals beyond
+/// [`PortalPluginSettings::max_recursion_depth`].
+#[derive(Resource, Debug)]
+pub struct PortalRecursion {
+    /// The material used by proxies beyond [`PortalPluginSettings::max_recursion_depth`].
+    ///
+    /// Its color follows [`PortalPluginSettings::recursion_fallback_color`]. Other properties can
+    /// be modified through [`Assets<StandardMaterial>`], e.g. to show a texture instead.
+    pub fallback_material: Handle<StandardMaterial>,
+}
+
+/// Component holding the recursion depth a [`Portal`] was set up with.
+///
+/// This is only inserted on [`Portal`]s that are rendered recursively. Systems rendering the
+/// [`Portal`] read the depth from here rather than from [`PortalPluginSettings`], which may have
+/// changed since.
+#[derive(Component, Debug, Clone, Copy)]
+pub(crate) struct PortalRecursionDepth(pub(crate) u32);
+
+/// Component used to mark a camera rendering a [`Portal`] at a given recursion `depth`.
+///
+/// Depth `0` is the [`PortalCamera`] itself, and isn't marked with this component.
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+#[require(Camera3d, PortalProjection)]
+pub struct PortalRecursionCamera {
+    /// The [`Portal`] entity this camera renders for.
+    pub portal: Entity,
+    /// The recursion level this camera renders.
+    pub depth: u32,
+}
+
+/// Component used to mark the proxy mesh of a [`Portal`] seen by cameras at a given `depth`.
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+pub struct PortalRecursionProxy {
+    /// The [`Portal`] entity this proxy is spawned for.
+    pub portal: Entity,
+    /// The recursion level of the cameras that see this proxy.
+    pub depth: u32,
+}
+
+/// Returns whether recursion proxies are spawned for `portal`, given recursion is enabled.
+pub(crate) fn has_recursion_proxies(portal: &Portal, mirror: bool) -> bool {
+    // Mirrors flip their image, atlas cells only hold part of one, and other surface mappings don't
+    // line up with the screen, all of which the proxies ignore
+    !mirror && !portal.atlas && portal.surface_mapping == PortalSurfaceMapping::ScreenSpace
+}
+
+/// Observer that spawns the recursion cameras and proxies when a [`PortalCamera`] is added.
+fn setup_portal_recursion(
+    trigger: Trigger<OnAdd, PortalCamera>,
+    mut commands: Commands,
+    recursion: Res<PortalRecursion>,
+    portal_camera_query: Query<(
+        &PortalCamera,
+        &Camera,
+        &Camera3d,
+        &DebandDither,
+        &Tonemapping,
+        &ColorGrading,
+        &Exposure,
+        &Msaa,
+        &PortalProjection,
+        Option<&RenderLayers>,
+    )>,
+    portal_query: Query<(&Portal, Option<&Mesh3d>, &PortalRecursionDepth)>,
+    mut images: ResMut<Assets<Image>>,
+    mut image_pool: ResMut<PortalImagePool>,
+    mut portal_materials: ResMut<Assets<PortalMaterial>>,
+) {
+    let entity = trigger.entity();
+    let Ok((
+        &PortalCamera(portal_entity),
+        camera,
+        camera_3d,
+        deband_dither,
+        tonemapping,
+        color_grading,
+        exposure,
+        msaa,
+        projection,
+        render_layers,
+    )) = portal_camera_query.get(entity)
+    else {
+        return;
+    };
+
+    // Only portals rendered recursively have a depth, see `setup_portal`
+    let Ok((portal, mesh, &PortalRecursionDepth(max_depth))) = portal_query.get(portal_entity)
+    else {
+        return;
+    };
+    let Some(mesh) = mesh else {
+        warn!("could not setup recursion for portal {portal_entity}: portal is missing a mesh");
+        return;
+    };
+
+    let RenderTarget::Image(ref image_handle) = camera.target else {
+        return;
+    };
+    let Some(image) = images.get(image_handle) else {
+        return;
+    };
+    let (size, format, sampler) = (
+        image.texture_descriptor.size,
+        image.texture_descriptor.format,
+        image.sampler.clone(),
+    );
+
+    let render_layers = render_layers.cloned().unwrap_or_default();
+    commands
+        .entity(entity)
+        .insert(render_layers.clone().with(RECURSION_RENDER_LAYER));
+
+    // Each level renders to its own image, which is sampled by the proxies of the level above
+    let mut image_handles = vec![image_handle.clone()];
+    for depth in 1..=max_depth {
+        // Sized after the image of the level above, which is already scaled
+        let image_handle = image_pool.acquire(&mut images, size, 1.0, format, &sampler);
+        image_handles.push(image_handle.clone());
+
+        commands.spawn((
+            Name::new("Portal Recursion Camera"),
+            Camera {
+                // Deeper levels have to be rendered first
+                order: camera.order - depth as isize,
+                target: RenderTarget::Image(image_handle),
+                ..camera.clone()
+            },
+            camera_3d.clone(),
+            *deband_dither,
+            *tonemapping,
+            color_grading.clone(),
+            *exposure,
+            *msaa,
+            projection.clone(),
+            render_layers
+                .clone()
+                .with(RECURSION_RENDER_LAYER + depth as usize),
+            PortalRecursionCamera {
+                portal: portal_entity,
+                depth,
+            },
+        ));
+    }
+
+    commands.entity(portal_entity).with_children(|parent| {
+        for depth in 0..=max_depth {
+            let mut proxy = parent.spawn((
+                Name::new("Portal Recursion Proxy"),
+                mesh.clone(),
+                RenderLayers::layer(RECURSION_RENDER_LAYER + depth as usize),
+                PortalRecursionProxy {
+                    portal: portal_entity,
+                    depth,
+                },
+            ));
+
+            match image_handles.get(depth as usize + 1) {
+                Some(image_handle) => {
+                    proxy.insert(MeshMaterial3d(portal_materials.add(PortalMaterial {
+                        cull_mode: portal.material_cull_mode(),
+                        ..PortalMaterial::new(image_handle.clone())
+                    })))
+                }
+                None => proxy.insert(MeshMaterial3d(recursion.fallback_material.clone())),
+            };
+        }
+    });
+}
+
+/// System that updates the [`Transform`] and [`GlobalTransform`] of [`PortalRecursionCamera`]s.
+///
+/// Levels are computed in order, since each level is rendered through a camera of the previous
+/// one. The previous level's camera is chosen as the closest one that has the [`Portal`] in front
+/// of its target and within its [`Frustum`], falling back to the [`Portal`]'s own camera.
+fn update_recursion_camera_transforms(
+    portal_query: Query<
+        (
+            Entity,
+            &Portal,
+            &LinkedPortalCamera,
+            &GlobalTransform,
+            Option<&Aabb>,
+        ),
+        (Without<PortalRecursionCamera>, Without<Mirror>),
+    >,
+    camera_query: Query<(&GlobalTransform, &Frustum), Without<PortalRecursionCamera>>,
+    target_query: Query<&GlobalTransform, Without<PortalRecursionCamera>>,
+    mut recursion_camera_query: Query<(
+        &PortalRecursionCamera,
+        &mut Transform,
+        &mut GlobalTransform,
+        &Frustum,
+    )>,
+) {
+    // Portals keep the depth they were set up with, so the deepest of them decides the levels
+    let Some(max_depth) = recursion_camera_query
+        .iter()
+        .map(|(recursion_camera, ..)| recursion_camera.depth)
+        .max()
+    else {
+        return;
+    };
+
+    // The portal and target transforms, along with the frustum of each level's camera
+    let mut portals = HashMap::new();
+    let mut levels: HashMap<(Entity, u32), (Transform, Frustum)> = HashMap::new();
+
+    for (entity, portal, linked_camera, global_transform, aabb) in &portal_query {
+        let Ok((camera_transform, frustum)) = camera_query.get(linked_camera.get()) else {
+            continue;
+        };
+        let Ok(target_transform) = target_query.get(portal.target) else {
+            continue;
+        };
+
+        portals.insert(
+            entity,
+            (
+                global_transform.compute_transform(),
+                target_transform.compute_transform(),
+                global_transform.affine(),
+                aabb.copied().unwrap_or_default(),
+            ),
+        );
+        levels.insert(
+            (entity, 0),
+            (camera_transform.compute_transform(), *frustum),
+        );
+    }
+
+    for (recursion_camera, _, _, frustum) in &recursion_camera_query {
+        levels.insert(
+            (recursion_camera.portal, recursion_camera.depth),
+            (Transform::default(), *frustum),
+        );
+    }
+
+    for depth in 1..=max_depth {
+        for (&entity, (portal_transform, target_transform, world_from_local, aabb)) in &portals {
+            let mut parent = entity;
+            let mut closest = f32::INFINITY;
+
+            for (&other, (_, other_target_transform, ..)) in &portals {
+                let Some((camera_transform, frustum)) = levels.get(&(other, depth - 1)) else {
+                    continue;
+                };
+
+                // Cameras only see what's in front of their target
+                let distance = (portal_transform.translation - other_target_transform.translation)
+                    .dot(*other_target_transform.forward());
+                if distance <= f32::EPSILON
+                    || !frustum.intersects_obb(aabb, world_from_local, false, true)
+                {
+                    continue;
+                }
+
+                let distance = camera_transform
+                    .translation
+                    .distance_squared(portal_transform.translation);
+                if distance < closest {
+                    closest = distance;
+                    parent = other;
+                }
+            }
+
+            let Some(&(parent_transform, _)) = levels.get(&(parent, depth - 1)) else {
+                continue;
+            };
+            let transform =
+                transform_through_portal(portal_transform, target_transform, parent_transform);
+            if let Some((level_transform, _)) = levels.get_mut(&(entity, depth)) {
+                *level_transform = transform;
+            }
+        }
+    }
+
+    for (recursion_camera, mut transform, mut global_transform, _) in &mut recursion_camera_query {
+        if let Some(&(level_transform, _)) =
+            levels.get(&(recursion_camera.portal, recursion_camera.depth))
+        {
+            *transform = level_transform;
+            *global_transform = GlobalTransform::from(level_transform);
+        }
+    }
+}
+
+/// System that updates the [`PortalProjection`] near clipping plane for [`PortalRecursionCamera`]s.
+///
+/// Every level of a [`Portal`] looks out of the same target, so the plane is set the same way as
+/// for its [`PortalCamera`].
+fn update_recursion_camera_frusta(
+    portal_query: Query<&Portal>,
+    mut recursion_camera_query: Query<(
+        &PortalRecursionCamera,
+        &GlobalTransform,
+        &mut PortalProjection,
+    )>,
+    target_query: Query<&GlobalTransform, Without<PortalRecursionCamera>>,
+) {
+    for (recursion_camera, global_transform, mut projection) in &mut recursion_camera_query {
+        let Ok(portal) = portal_query.get(recursion_camera.portal) else {
+            continue;
+        };
+        let Ok(target_transform) = target_query.get(portal.target) else {
+            continue;
+        };
+
+        projection.near_clip_plane =
+            portal_near_clip_plane(target_transform, global_transform, portal.clip_offset);
+    }
+}
+
+/// System that copies [`PortalProjection::projection`] of each [`PortalCamera`] to its
+/// [`PortalRecursionCamera`]s whenever it changes.
+fn sync_recursion_camera_projections(
+    portal_query: Query<&LinkedPortalCamera>,
+    portal_camera_query: Query<Ref<PortalProjection>, With<PortalCamera>>,
+    mut recursion_camera_query: Query<
+        (&PortalRecursionCamera, &mut PortalProjection),
+        Without<PortalCamera>,
+    >,
+) {
+    for (recursion_camera, mut projection) in &mut recursion_camera_query {
+        let Some(portal_projection) = portal_query
+            .get(recursion_camera.portal)
+            .ok()
+            .and_then(|linked_camera| portal_camera_query.get(linked_camera.get()).ok())
+        else {
+            continue;
+        };
+        if !portal_projection.is_changed() {
+            continue;
+        }
+
+        projection.projection = portal_projection.projection.clone();
+    }
+}
+
+/// Observer that despawns the recursion cameras and proxies when a [`PortalCamera`] is removed.
+///
+/// The images of the recursion cameras are released to the [`PortalImagePool`].
+fn despawn_portal_recursion(
+    trigger: Trigger<OnRemove, PortalCamera>,
+    mut commands: Commands,
+    portal_camera_query: Query<&PortalCamera>,
+    recursion_camera_query: Query<(Entity, &PortalRecursionCamera, &Camera)>,
+    proxy_query: Query<(Entity, &PortalRecursionProxy)>,
+    mut image_pool: ResMut<PortalImagePool>,
+) {
+    let &PortalCamera(portal_entity) = portal_camera_query
+        .get(trigger.entity())
+        .expect("observer guarantees existence of component");
+
+    let recursion_cameras = recursion_camera_query
+        .iter()
+        .filter(|(_, recursion_camera, _)| recursion_camera.portal == portal_entity)
+        .map(|(entity, _, camera)| {
+            if let RenderTarget::Image(ref image_handle) = camera.target {
+                image_pool.release(image_handle.clone());
+            }
+            entity
+        });
+    let proxies = proxy_query
+        .iter()
+        .filter(|(_, proxy)| proxy.portal == portal_entity)
+        .map(|(entity, _)| entity);
+    for entity in recursion_cameras.chain(proxies) {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// System that activates [`PortalRecursionCamera`]s only while their [`PortalCamera`] is active.
+fn update_recursion_camera_activity(
+    portal_query: Query<&LinkedPortalCamera>,
+    portal_camera_query: Query<&Camera, (With<PortalCamera>, Without<PortalRecursionCamera>)>,
+    mut recursion_camera_query: Query<(&PortalRecursionCamera, &mut Camera)>,
+) {
+    for (recursion_camera, mut camera) in &mut recursion_camera_query {
+        let Some(is_active) = portal_query
+            .get(recursion_camera.portal)
+            .ok()
+            .and_then(|linked_camera| portal_camera_query.get(linked_camera.get()).ok())
+            .map(|linked_camera| linked_camera.is_active)
+        else {
+            continue;
+        };
+
+        if camera.is_active != is_active {
+            camera.is_active = is_active;
+        }
+    }
+}
+
+/// System that resizes the images of [`PortalRecursionCamera`]s to match their [`PortalCamera`].
+///
+/// Sizes are compared every frame, so this also follows images resized for other reasons than a
+/// [`WindowResized`](bevy::window::WindowResized), e.g. a change of the primary camera's viewport.
+/// Images are only resized once their size has settled, see
+/// [`PortalPlugin::resize_delay_frames`](crate::PortalPlugin::resize_delay_frames).
+fn resize_recursion_images(
+    portal_query: Query<&LinkedPortalCamera>,
+    camera_query: Query<&Camera>,
+    recursion_camera_query: Query<(&PortalRecursionCamera, &Camera)>,
+    mut image_pool: ResMut<PortalImagePool>,
+    mut pending_resizes: ResMut<PendingImageResizes>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    for (recursion_camera, camera) in &recursion_camera_query {
+        let Some(size) = portal_query
+            .get(recursion_camera.portal)
+            .ok()
+            .and_then(|linked_camera| camera_query.get(linked_camera.get()).ok())
+            .and_then(|linked_camera| match linked_camera.target {
+                RenderTarget::Image(ref image_handle) => images.get(image_handle),
+                _ => None,
+            })
+            .map(|image| image.texture_descriptor.size)
+        else {
+            continue;
+        };
+
+        let RenderTarget::Image(ref image_handle) = camera.target else {
+            continue;
+        };
+        if images
+            .get(image_handle)
+            .is_some_and(|image| image.texture_descriptor.size == size)
+        {
+            continue;
+        }
+        pending_resizes.resize_image(&mut image_pool, &mut images, image_handle, size);
+    }
+}
+
+/// System that updates the color of [`PortalRecursion::fallback_material`] whenever
+/// [`PortalPluginSettings::recursion_fallback_color`] changes.
+fn update_recursion_fallback_color(
+    settings: Res<PortalPluginSettings>,
+    recursion: Res<PortalRecursion>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let Some(material) = materials.get(&recursion.fallback_material) else {
+        return;
+    };
+    if material.base_color == settings.recursion_fallback_color {
+        return;
+    }
+    if let Some(material) = materials.get_mut(&recursion.fallback_material) {
+        material.base_color = settings.recursion_fallback_color;
+    }
+}
@@ -0,0 +1,94 @@
+//! Capturing the image of a [`Portal`].
+//!
+//! Add the [`PortalScreenshotPlugin`], then spawn a [`PortalScreenshot`] to capture what a
+//! [`Portal`] currently shows. This wraps Bevy's [`Screenshot`], so the captured image can be
+//! handled the same way, e.g. with [`save_to_disk`] or an observer of [`ScreenshotCaptured`]:
+//!
+//! ```no_run
+//! # use bevy::{prelude::*, render::view::screenshot::save_to_disk};
+//! use bevy_easy_portals::screenshot::PortalScreenshot;
+//!
+//! fn take_screenshot(mut commands: Commands, portal: Entity) {
+//!     commands
+//!         .spawn(PortalScreenshot(portal))
+//!         .observe(save_to_disk("portal.png"));
+//! }
+//! ```
+//!
+//! # Notes
+//!
+//! * The capture happens once the [`Portal`] has been set up, so a screenshot can be requested
+//!   right after spawning the [`Portal`].
+//! * The whole image the [`PortalCamera`] renders to is captured. For portals in the
+//!   [`PortalAtlas`], that is the whole atlas, and with [`PortalImageAllocation::MaxMonitorSize`],
+//!   that includes the unused area of the image.
+//! * The image can only be converted to a `DynamicImage` (e.g. to be saved to disk) if its
+//!   [`TextureFormat`] is supported, which excludes HDR images.
+//!
+//! [`save_to_disk`]: bevy::render::view::screenshot::save_to_disk
+//! [`ScreenshotCaptured`]: bevy::render::view::screenshot::ScreenshotCaptured
+//! [`PortalCamera`]: crate::PortalCamera
+//! [`PortalAtlas`]: crate::atlas::PortalAtlas
+//! [`PortalImageAllocation::MaxMonitorSize`]: crate::PortalImageAllocation::MaxMonitorSize
+//! [`TextureFormat`]: bevy::render::render_resource::TextureFormat
+
+use bevy::{
+    prelude::*,
+    render::{camera::RenderTarget, view::screenshot::Screenshot},
+};
+
+use crate::{LinkedPortalCamera, Portal, PortalCamera};
+
+/// Plugin that captures [`PortalScreenshot`]s.
+pub struct PortalScreenshotPlugin;
+
+impl Plugin for PortalScreenshotPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PostUpdate, capture_portal_screenshots)
+            .register_type::<PortalScreenshot>();
+    }
+}
+
+/// Component used to capture the image of a [`Portal`] entity.
+///
+/// A [`Screenshot`] of the image is inserted next to this component once the [`Portal`] is set
+/// up, which triggers [`ScreenshotCaptured`] on this entity and despawns it when done.
+///
+/// This requires the [`PortalScreenshotPlugin`].
+///
+/// [`ScreenshotCaptured`]: bevy::render::view::screenshot::ScreenshotCaptured
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component)]
+pub struct PortalScreenshot(pub Entity);
+
+/// System that requests a [`Screenshot`] for each [`PortalScreenshot`] whose [`Portal`] has an
+/// image.
+fn capture_portal_screenshots(
+    mut commands: Commands,
+    screenshot_query: Query<(Entity, &PortalScreenshot), Without<Screenshot>>,
+    portal_query: Query<Option<&LinkedPortalCamera>, With<Portal>>,
+    camera_query: Query<&Camera, With<PortalCamera>>,
+) {
+    for (entity, screenshot) in &screenshot_query {
+        let Ok(linked_camera) = portal_query.get(screenshot.0) else {
+            warn!(
+                "could not capture portal screenshot: entity {} is not a portal",
+                screenshot.0
+            );
+            commands.entity(entity).despawn();
+            continue;
+        };
+        // The camera's target is the image itself, not its mip chain
+        let Some(Camera {
+            target: RenderTarget::Image(image_handle),
+            ..
+        }) = linked_camera.and_then(|linked_camera| camera_query.get(linked_camera.get()).ok())
+        else {
+            continue;
+        };
+
+        commands
+            .entity(entity)
+            .insert(Screenshot::image(image_handle.clone()));
+    }
+}
@@ -0,0 +1,106 @@
+//! Defaults [`Portal`]s are set up with.
+//!
+//! These are configured through [`PortalPlugin::settings`] and [`PortalPlugin::image_size`], and
+//! held in the [`PortalPluginSettings`] and [`PortalImageSize`] resources.
+//!
+//! [`PortalPlugin::settings`]: crate::PortalPlugin::settings
+//! [`PortalPlugin::image_size`]: crate::PortalPlugin::image_size
+
+use bevy::{
+    prelude::*,
+    render::render_resource::{Face, TextureFormat},
+};
+
+#[cfg(doc)]
+use crate::{
+    atlas::PortalAtlas, recursion::PortalRecursion, Portal, PortalCamera, PortalMaterial,
+    PortalSurfaceMapping,
+};
+
+/// Resource holding the defaults [`Portal`]s are set up with, see [`PortalPlugin::settings`].
+///
+/// [`PortalPlugin::settings`]: crate::PortalPlugin::settings
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct PortalPluginSettings {
+    /// The [`Camera::order`] of [`PortalCamera`]s, relative to the default order of `0`.
+    ///
+    /// This should be lower than the order of the primary cameras, so that the images of
+    /// [`Portal`]s are rendered before they are shown. Cameras rendering into the
+    /// [`PortalAtlas`] count down from this order.
+    ///
+    /// Defaults to `-1`.
+    pub camera_order_offset: isize,
+    /// The [`TextureFormat`] of the images of [`Portal`]s without a [`Portal::texture_format`].
+    ///
+    /// If set to `None`, the format falls back to [`Portal::image_texture_format`].
+    ///
+    /// Defaults to `None`.
+    pub texture_format: Option<TextureFormat>,
+    /// The scale applied to the [`Portal::resolution_scale`] of every [`Portal`].
+    ///
+    /// Lower this to trade image quality for performance across the board, e.g. for a graphics
+    /// quality setting.
+    ///
+    /// Defaults to `1.0`.
+    pub resolution_scale: f32,
+    /// Whether a [`PortalMaterial`] should be inserted for [`Portal`]s.
+    ///
+    /// Disabling this is the same as enabling [`Portal::manual_material`] for every [`Portal`].
+    ///
+    /// Defaults to `true`.
+    pub insert_materials: bool,
+    /// The [`Portal::cull_mode`] of [`Portal`]s that leave it at its default of
+    /// `Some(Face::Back)`, applied when they are set up.
+    ///
+    /// Defaults to `Some(Face::Back)`.
+    pub cull_mode: Option<Face>,
+    /// The number of times a [`Portal`] can be rendered within another [`Portal`], read when the
+    /// [`Portal`] is set up.
+    ///
+    /// A value of `0` disables recursive rendering. See the [`recursion`] module for details.
+    ///
+    /// Defaults to `0`.
+    ///
+    /// [`recursion`]: crate::recursion
+    pub max_recursion_depth: u32,
+    /// The color shown on portals beyond [`PortalPluginSettings::max_recursion_depth`].
+    ///
+    /// This is the color of [`PortalRecursion::fallback_material`], which is kept in sync with
+    /// this setting.
+    ///
+    /// Defaults to [`Color::BLACK`].
+    pub recursion_fallback_color: Color,
+}
+
+impl Default for PortalPluginSettings {
+    fn default() -> Self {
+        Self {
+            camera_order_offset: -1,
+            texture_format: None,
+            resolution_scale: 1.0,
+            insert_materials: true,
+            cull_mode: Some(Face::Back),
+            max_recursion_depth: 0,
+            recursion_fallback_color: Color::BLACK,
+        }
+    }
+}
+
+/// Resource deciding the size of the images of [`Portal`]s, see [`PortalPlugin::image_size`].
+///
+/// [`PortalPlugin::image_size`]: crate::PortalPlugin::image_size
+#[derive(Resource, Reflect, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[reflect(Resource, Default)]
+pub enum PortalImageSize {
+    /// Images are the size of the viewport of the primary camera.
+    ///
+    /// [`Portal`]s wait to be set up until that size is known, e.g. until the window exists.
+    #[default]
+    Viewport,
+    /// Images are a fixed size, regardless of the primary camera's viewport.
+    ///
+    /// Use this to run without a window, e.g. for headless rendering or captures in CI, where
+    /// the viewport size is unknown. With [`PortalSurfaceMapping::ScreenSpace`], the image is
+    /// stretched if its aspect ratio differs from the viewport's.
+    Fixed(UVec2),
+}
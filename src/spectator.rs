@@ -0,0 +1,251 @@
+//! Showing the view through a [`Portal`] in another window.
+//!
+//! Add the [`PortalSpectatorPlugin`], then [`PortalSpectator`] to a [`Portal`] to render exactly
+//! what its [`PortalCamera`] sees to a window of your choice, at the full resolution of that
+//! window. This helps e.g. level designers tune the placement of portals.
+//!
+//! The spectator camera inherits the rendering components of the [`PortalCamera`] when it's
+//! spawned, such as [`Tonemapping`] and [`RenderLayers`], and follows its transform and
+//! [`PortalProjection`] (including the clipping plane) every frame.
+//!
+//! [`Tonemapping`]: bevy::core_pipeline::tonemapping::Tonemapping
+
+use bevy::{
+    core_pipeline::tonemapping::{DebandDither, Tonemapping},
+    prelude::*,
+    render::{
+        camera::{camera_system, Exposure, RenderTarget},
+        view::{ColorGrading, RenderLayers},
+    },
+    window::WindowRef,
+};
+
+#[cfg(doc)]
+use crate::Portal;
+use crate::{LinkedPortalCamera, PortalCamera, PortalCameraSystems, PortalProjection};
+
+/// Plugin that shows the view through [`PortalSpectator`]s in their window.
+pub struct PortalSpectatorPlugin;
+
+impl Plugin for PortalSpectatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            (
+                spawn_portal_spectator_cameras,
+                update_portal_spectator_cameras,
+            )
+                .chain()
+                .after(PortalCameraSystems::UpdateFrusta)
+                .after(PortalCameraSystems::SyncProjection)
+                .before(camera_system::<PortalProjection>),
+        )
+        .add_systems(
+            PostUpdate,
+            deactivate_exclusive_portal_cameras.after(PortalCameraSystems::UpdateActivity),
+        )
+        .add_observer(despawn_portal_spectator_camera)
+        .register_type::<PortalSpectator>();
+    }
+}
+
+/// Component used to show the view through a [`Portal`] in another window.
+///
+/// This requires the [`PortalSpectatorPlugin`].
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component)]
+pub struct PortalSpectator {
+    /// The entity with the [`Window`] to show the view in.
+    pub window: Entity,
+    /// Whether the view should only be shown in the window, instead of on the portal as well.
+    ///
+    /// If enabled, the [`PortalCamera`] stops rendering, and the portal keeps showing the last
+    /// image that was rendered.
+    pub exclusive: bool,
+}
+
+impl PortalSpectator {
+    /// Creates a new [`PortalSpectator`] showing the view through the portal in `window`, as well
+    /// as on the portal itself.
+    #[inline]
+    #[must_use]
+    pub fn new(window: Entity) -> Self {
+        Self {
+            window,
+            exclusive: false,
+        }
+    }
+
+    /// Enables [`PortalSpectator::exclusive`].
+    #[inline]
+    #[must_use]
+    pub fn exclusive(mut self) -> Self {
+        self.exclusive = true;
+        self
+    }
+}
+
+/// Component holding the spectator camera of a [`PortalSpectator`].
+#[derive(Component)]
+struct PortalSpectatorCamera(Entity);
+
+/// Marker component for the cameras spawned for [`PortalSpectator`]s.
+#[derive(Component)]
+struct PortalSpectatorView;
+
+/// System that spawns a camera for each [`PortalSpectator`], once its [`PortalCamera`] exists.
+fn spawn_portal_spectator_cameras(
+    mut commands: Commands,
+    portal_query: Query<
+        (Entity, &LinkedPortalCamera, &PortalSpectator),
+        Without<PortalSpectatorCamera>,
+    >,
+    camera_query: Query<
+        (
+            &Camera,
+            &Transform,
+            &GlobalTransform,
+            &PortalProjection,
+            Option<&Camera3d>,
+            Option<&DebandDither>,
+            Option<&Tonemapping>,
+            Option<&ColorGrading>,
+            Option<&Exposure>,
+            Option<&Msaa>,
+            Option<&RenderLayers>,
+        ),
+        With<PortalCamera>,
+    >,
+) {
+    for (entity, linked_camera, spectator) in &portal_query {
+        let Ok((
+            camera,
+            transform,
+            global_transform,
+            projection,
+            camera_3d,
+            deband_dither,
+            tonemapping,
+            color_grading,
+            exposure,
+            msaa,
+            render_layers,
+        )) = camera_query.get(linked_camera.get())
+        else {
+            continue;
+        };
+
+        let mut spectator_camera = commands.spawn((
+            Name::new("Portal Spectator Camera"),
+            PortalSpectatorView,
+            Camera {
+                order: 0,
+                target: RenderTarget::Window(WindowRef::Entity(spectator.window)),
+                viewport: None,
+                sub_camera_view: None,
+                is_active: true,
+                ..camera.clone()
+            },
+            *transform,
+            *global_transform,
+            camera_3d.cloned().unwrap_or_default(),
+            deband_dither.copied().unwrap_or_default(),
+            tonemapping.copied().unwrap_or_default(),
+            color_grading.cloned().unwrap_or_default(),
+            exposure.copied().unwrap_or_default(),
+            msaa.copied().unwrap_or_default(),
+            projection.clone(),
+        ));
+        if let Some(render_layers) = render_layers {
+            spectator_camera.insert(render_layers.clone());
+        }
+        let spectator_camera = spectator_camera.id();
+        commands
+            .entity(entity)
+            .insert(PortalSpectatorCamera(spectator_camera));
+    }
+}
+
+/// System that keeps the cameras of [`PortalSpectator`]s in sync with their [`PortalCamera`]s.
+fn update_portal_spectator_cameras(
+    portal_query: Query<(
+        &LinkedPortalCamera,
+        Ref<PortalSpectator>,
+        &PortalSpectatorCamera,
+    )>,
+    camera_query: Query<
+        (&Transform, &GlobalTransform, &PortalProjection),
+        (With<PortalCamera>, Without<PortalSpectatorView>),
+    >,
+    mut spectator_camera_query: Query<
+        (
+            &mut Camera,
+            &mut Transform,
+            &mut GlobalTransform,
+            &mut PortalProjection,
+        ),
+        With<PortalSpectatorView>,
+    >,
+) {
+    for (linked_camera, spectator, spectator_camera) in &portal_query {
+        let Ok((transform, global_transform, projection)) = camera_query.get(linked_camera.get())
+        else {
+            continue;
+        };
+        let Ok((
+            mut camera,
+            mut spectator_transform,
+            mut spectator_global_transform,
+            mut spectator_projection,
+        )) = spectator_camera_query.get_mut(spectator_camera.0)
+        else {
+            continue;
+        };
+
+        if spectator.is_changed() {
+            camera.target = RenderTarget::Window(WindowRef::Entity(spectator.window));
+        }
+        *spectator_transform = *transform;
+        *spectator_global_transform = *global_transform;
+        // The aspect ratio is updated to the window's by `camera_system`
+        spectator_projection.projection = projection.projection.clone();
+        spectator_projection.near_clip_plane = projection.near_clip_plane;
+    }
+}
+
+/// System that keeps the [`PortalCamera`]s of exclusive [`PortalSpectator`]s from rendering.
+fn deactivate_exclusive_portal_cameras(
+    portal_query: Query<(&LinkedPortalCamera, &PortalSpectator)>,
+    mut camera_query: Query<&mut Camera, With<PortalCamera>>,
+) {
+    for (linked_camera, spectator) in &portal_query {
+        if !spectator.exclusive {
+            continue;
+        }
+        let Ok(mut camera) = camera_query.get_mut(linked_camera.get()) else {
+            continue;
+        };
+        if camera.is_active {
+            camera.is_active = false;
+        }
+    }
+}
+
+/// Observer that despawns the camera of a [`PortalSpectator`] once it is removed.
+fn despawn_portal_spectator_camera(
+    trigger: Trigger<OnRemove, PortalSpectator>,
+    mut commands: Commands,
+    spectator_camera_query: Query<&PortalSpectatorCamera>,
+) {
+    let entity = trigger.entity();
+    let Ok(spectator_camera) = spectator_camera_query.get(entity) else {
+        return;
+    };
+    commands.entity(spectator_camera.0).despawn_recursive();
+    // The entity may be in the middle of being despawned
+    commands.queue(move |world: &mut World| {
+        if let Ok(mut entity) = world.get_entity_mut(entity) {
+            entity.remove::<PortalSpectatorCamera>();
+        }
+    });
+}
@@ -0,0 +1,530 @@
+//! Teleportation through [`Portal`]s.
+//!
+//! Add [`PortalTeleporter`] to a [`Portal`] entity, and [`PortalTraveler`] to any entity that
+//! should be teleported by it. Whenever a traveler crosses the portal's mesh, its [`Transform`] is
+//! moved into the space of [`Portal::target`], the same way as the [`PortalCamera`] is.
+//!
+//! Entities with [`PortalTracked`] (which includes travelers and every [`Portal::primary_camera`])
+//! send a [`PortalCrossed`] event whenever they cross a portal's mesh, whether or not they are
+//! teleported. The event is also triggered for observers of the [`Portal`] entity.
+//!
+//! While a [`PortalTraveler`] with a mesh straddles a [`PortalTeleporter`], a
+//! [`PortalTravelerClone`] of it is shown at [`Portal::target`], so that it appears partially on
+//! both sides. Both are [`PortalClipped`] against the portal's mesh and its target, so that only
+//! the part on the right side of each is shown. Clones copy the traveler's [`StandardMaterial`];
+//! add a [`PortalTravelerCloneMaterialPlugin`] to copy other materials.
+//!
+//! # Notes
+//!
+//! * Tracked entities should not have a parent, since their [`Transform`] is treated as global.
+//! * Crossings are detected by checking where the straight path of an entity since last frame
+//!   meets a portal's plane, so entities moving along a curve may cross the portal's plane outside
+//!   of its mesh (or the other way around) within a single frame.
+//! * Only the traveler's own mesh is cloned, not the meshes of its children.
+//!
+//! [`PortalCamera`]: crate::PortalCamera
+
+use std::marker::PhantomData;
+
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+    render::{primitives::Aabb, render_resource::Face, view::RenderLayers},
+    utils::{HashMap, HashSet},
+};
+
+#[cfg(all(doc, feature = "2d"))]
+use crate::portal_2d::Portal2d;
+use crate::{clip::PortalClipped, transform_through_portal, Mirror, Portal, PortalBackFace};
+
+/// Label for systems that teleport [`PortalTraveler`]s.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, SystemSet)]
+pub struct PortalTeleportSystems;
+
+/// Plugin that teleports [`PortalTraveler`]s through [`PortalTeleporter`]s.
+///
+/// This is added by [`PortalPlugin`](crate::PortalPlugin), and shouldn't be added manually.
+pub(crate) struct PortalTeleportPlugin;
+
+impl Plugin for PortalTeleportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PortalCrossed>()
+            .init_resource::<PortalTravelerClones>()
+            .add_systems(
+                PostUpdate,
+                (teleport_travelers, update_traveler_clones)
+                    .chain()
+                    .in_set(PortalTeleportSystems)
+                    .before(TransformSystem::TransformPropagate),
+            )
+            .add_plugins(PortalTravelerCloneMaterialPlugin::<StandardMaterial>::default())
+            .add_observer(track_primary_camera)
+            .register_type::<(
+                PortalTeleporter,
+                PortalTraveler,
+                PortalTracked,
+                PortalTravelerClone,
+            )>();
+    }
+}
+
+/// Plugin that copies the `M` material of [`PortalTraveler`]s onto their [`PortalTravelerClone`]s.
+///
+/// This is added for [`StandardMaterial`] by [`PortalPlugin`](crate::PortalPlugin).
+pub struct PortalTravelerCloneMaterialPlugin<M>(PhantomData<M>);
+
+impl<M> Default for PortalTravelerCloneMaterialPlugin<M> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<M: Material> Plugin for PortalTravelerCloneMaterialPlugin<M> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            update_traveler_clone_materials::<M>
+                .after(update_traveler_clones)
+                .in_set(PortalTeleportSystems),
+        );
+    }
+}
+
+/// Component used to make a [`Portal`] teleport [`PortalTraveler`]s crossing it.
+///
+/// Which way travelers can cross depends on [`Portal::cull_mode`]: only the visible side(s) of the
+/// portal can be entered. Travelers entering the back face of a portal with a
+/// [`Portal::back_target`] are teleported there instead.
+///
+/// This also works with a [`Portal2d`], whose travelers are teleported when they enter its mesh.
+#[derive(Component, Reflect, Debug, Default)]
+#[reflect(Component, Default)]
+pub struct PortalTeleporter;
+
+/// Component used to mark an entity that can be teleported by a [`PortalTeleporter`].
+#[derive(Component, Reflect, Debug, Default)]
+#[reflect(Component, Default)]
+#[require(PortalTracked)]
+pub struct PortalTraveler;
+
+/// Component used to mark an entity that sends [`PortalCrossed`] events.
+///
+/// This is added to every [`Portal::primary_camera`] automatically.
+#[derive(Component, Reflect, Debug, Default)]
+#[reflect(Component, Default)]
+#[require(Transform, PortalTrackedTranslations)]
+pub struct PortalTracked;
+
+/// The direction a [`PortalTracked`] entity crossed a [`Portal`]'s mesh in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortalCrossingDirection {
+    /// From the front of the mesh (along its local positive z-axis) to the back.
+    FrontToBack,
+    /// From the back of the mesh to the front.
+    BackToFront,
+}
+
+/// Event sent when a [`PortalTracked`] entity crosses a [`Portal`]'s mesh.
+///
+/// This is also triggered for observers of [`PortalCrossed::portal`].
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PortalCrossed {
+    /// The [`Portal`] entity that was crossed.
+    pub portal: Entity,
+    /// The [`PortalTracked`] entity that crossed the portal.
+    pub entity: Entity,
+    /// The direction the portal was crossed in.
+    pub direction: PortalCrossingDirection,
+    /// The entity's [`Transform`] moved into the space of [`Portal::target`].
+    ///
+    /// For a [`PortalTraveler`] that was teleported, this is its new [`Transform`].
+    pub transform: Transform,
+    /// Whether the entity was teleported by a [`PortalTeleporter`].
+    pub teleported: bool,
+}
+
+/// Component added to the visual clone of a [`PortalTraveler`] straddling a [`PortalTeleporter`].
+///
+/// The clone is placed where the traveler would be if it were teleported, and is despawned once the
+/// traveler no longer straddles the portal.
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component)]
+pub struct PortalTravelerClone {
+    /// The [`PortalTraveler`] entity that is cloned.
+    pub traveler: Entity,
+    /// The [`Portal`] entity the traveler straddles.
+    pub portal: Entity,
+}
+
+/// The [`PortalTravelerClone`] of each [`PortalTraveler`] and [`Portal`] it straddles.
+#[derive(Resource, Default)]
+struct PortalTravelerClones(HashMap<(Entity, Entity), Entity>);
+
+/// A [`PortalTeleporter`] straddled by an entity, as returned by [`StraddledTeleporters::get`].
+pub(crate) struct PortalStraddle {
+    /// The [`Portal`] entity that is straddled.
+    pub portal: Entity,
+    /// The [`Portal::target`] of the portal.
+    pub target: Entity,
+    /// The entity's [`Transform`] moved into the space of [`Portal::target`].
+    pub through_portal: Transform,
+    /// Whether the entity's origin is in front of the portal's mesh.
+    pub front: bool,
+}
+
+/// [`SystemParam`] used to find the [`PortalTeleporter`]s an entity straddles.
+#[derive(SystemParam)]
+pub(crate) struct StraddledTeleporters<'w, 's> {
+    portal_query: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Portal,
+            &'static GlobalTransform,
+            &'static Aabb,
+            Option<&'static Parent>,
+            Has<PortalTeleporter>,
+            Has<PortalBackFace>,
+        ),
+        Without<Mirror>,
+    >,
+    teleporter_query: Query<'w, 's, (), With<PortalTeleporter>>,
+    global_transform_query: Query<'w, 's, &'static GlobalTransform>,
+}
+
+impl StraddledTeleporters<'_, '_> {
+    /// Returns the [`PortalTeleporter`]s whose mesh intersects the box `aabb` of an entity at
+    /// `transform`, and which the entity could teleport through from the side its origin is on.
+    ///
+    /// # Notes
+    ///
+    /// * Secondary views of a [`Portal`] share its mesh, and are skipped.
+    pub(crate) fn get(&self, transform: &Transform, aabb: &Aabb) -> Vec<PortalStraddle> {
+        let mut straddles = Vec::new();
+        for (entity, portal, portal_global_transform, portal_aabb, parent, teleporter, back_face) in
+            &self.portal_query
+        {
+            if !back_face && parent.is_some_and(|parent| self.portal_query.contains(parent.get())) {
+                continue;
+            }
+            let teleporter = teleporter
+                || (back_face
+                    && parent.is_some_and(|parent| self.teleporter_query.contains(parent.get())));
+            if !teleporter {
+                continue;
+            }
+
+            // Find the bounds of the entity relative to the portal's mesh
+            let portal_from_entity =
+                portal_global_transform.affine().inverse() * transform.compute_affine();
+            let center = Vec3::from(aabb.center);
+            let half_extents = Vec3::from(aabb.half_extents);
+            let (min, max) = [-1.0, 1.0]
+                .into_iter()
+                .flat_map(|x| [-1.0, 1.0].map(|y| Vec2::new(x, y)))
+                .flat_map(|xy| [-1.0, 1.0].map(|z| xy.extend(z)))
+                .map(|corner| portal_from_entity.transform_point3(center + corner * half_extents))
+                .fold((Vec3::MAX, Vec3::MIN), |(min, max), corner| {
+                    (min.min(corner), max.max(corner))
+                });
+
+            // Only consider entities intersecting the portal's mesh
+            let portal_min = Vec3::from(portal_aabb.min()).truncate();
+            let portal_max = Vec3::from(portal_aabb.max()).truncate();
+            if min.z >= 0.0
+                || max.z <= 0.0
+                || min.truncate().cmpgt(portal_max).any()
+                || max.truncate().cmplt(portal_min).any()
+            {
+                continue;
+            }
+
+            // Only consider entities entering the portal from a side they can teleport from
+            let front = portal_from_entity.translation.z >= 0.0;
+            let can_enter = match portal.cull_mode {
+                Some(Face::Back) => front,
+                Some(Face::Front) => !front,
+                None => true,
+            };
+            if !can_enter || ((back_face || portal.back_target.is_some()) && !front) {
+                continue;
+            }
+
+            let Ok(target_global_transform) = self.global_transform_query.get(portal.target) else {
+                continue;
+            };
+            straddles.push(PortalStraddle {
+                portal: entity,
+                target: portal.target,
+                through_portal: transform_through_portal(
+                    &portal_global_transform.compute_transform(),
+                    &target_global_transform.compute_transform(),
+                    *transform,
+                ),
+                front,
+            });
+        }
+        straddles
+    }
+}
+
+/// The translation of a [`PortalTracked`] entity relative to each [`Portal`] last frame.
+#[derive(Component, Default)]
+struct PortalTrackedTranslations(HashMap<Entity, Vec3>);
+
+/// Observer that adds [`PortalTracked`] to the primary camera of a new [`Portal`].
+fn track_primary_camera(
+    trigger: Trigger<OnAdd, Portal>,
+    mut commands: Commands,
+    portal_query: Query<&Portal>,
+    tracked_query: Query<(), With<PortalTracked>>,
+) {
+    let portal = portal_query
+        .get(trigger.entity())
+        .expect("observer guarantees existence of component");
+
+    if !tracked_query.contains(portal.primary_camera) {
+        if let Some(mut primary_camera) = commands.get_entity(portal.primary_camera) {
+            primary_camera.try_insert(PortalTracked);
+        }
+    }
+}
+
+/// System that detects [`PortalTracked`] entities crossing a [`Portal`] since last frame.
+///
+/// [`PortalTraveler`]s crossing a [`PortalTeleporter`] are teleported, and a [`PortalCrossed`]
+/// event is sent for every crossing.
+///
+/// # Notes
+///
+/// * Secondary views of a [`Portal`] share its mesh, and are skipped.
+/// * Crossings of the back face of a [`Portal`] with a [`Portal::back_target`] are sent for the
+///   back face's child [`Portal`], which teleports if its parent is a [`PortalTeleporter`].
+fn teleport_travelers(
+    mut commands: Commands,
+    portal_query: Query<
+        (
+            Entity,
+            &Portal,
+            &GlobalTransform,
+            &Aabb,
+            Option<&Parent>,
+            Has<PortalTeleporter>,
+            Has<PortalBackFace>,
+        ),
+        Without<Mirror>,
+    >,
+    teleporter_query: Query<(), With<PortalTeleporter>>,
+    global_transform_query: Query<&GlobalTransform>,
+    mut tracked_query: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut PortalTrackedTranslations,
+            Has<PortalTraveler>,
+        ),
+        With<PortalTracked>,
+    >,
+    mut crossed_writer: EventWriter<PortalCrossed>,
+) {
+    for (tracked, mut transform, mut translations, traveler) in &mut tracked_query {
+        let mut previous_translations = std::mem::take(&mut translations.0);
+        for (entity, portal, portal_global_transform, aabb, parent, teleporter, back_face) in
+            &portal_query
+        {
+            if !back_face && parent.is_some_and(|parent| portal_query.contains(parent.get())) {
+                continue;
+            }
+
+            // Positive values are in front of the portal's mesh
+            let local_translation = portal_global_transform
+                .affine()
+                .inverse()
+                .transform_point3(transform.translation);
+            translations.0.insert(entity, local_translation);
+            let Some(previous_translation) = previous_translations.remove(&entity) else {
+                continue;
+            };
+            let side = local_translation.z.signum();
+            let previous_side = previous_translation.z.signum();
+            if previous_side == side {
+                continue;
+            }
+
+            // Only consider crossings through the portal's mesh, wherever the path since last
+            // frame meets its plane, so that fast moving entities don't pass through undetected
+            let distance = previous_translation.z - local_translation.z;
+            let along_path = if distance == 0.0 {
+                0.0
+            } else {
+                previous_translation.z / distance
+            };
+            let crossing = previous_translation.lerp(local_translation, along_path);
+            let half_extents = Vec3::from(aabb.half_extents).truncate();
+            let offset = (crossing - Vec3::from(aabb.center)).truncate();
+            if offset.abs().cmpgt(half_extents).any() {
+                continue;
+            }
+            // Crossings from the back are handled by the other face
+            if (back_face || portal.back_target.is_some()) && previous_side < 0.0 {
+                continue;
+            }
+
+            let Ok(target_global_transform) = global_transform_query.get(portal.target) else {
+                continue;
+            };
+
+            let through_portal = transform_through_portal(
+                &portal_global_transform.compute_transform(),
+                &target_global_transform.compute_transform(),
+                *transform,
+            );
+
+            let can_enter = match portal.cull_mode {
+                Some(Face::Back) => previous_side > 0.0,
+                Some(Face::Front) => previous_side < 0.0,
+                None => true,
+            };
+            let teleporter = teleporter
+                || (back_face
+                    && parent.is_some_and(|parent| teleporter_query.contains(parent.get())));
+            let teleported = traveler && teleporter && can_enter;
+
+            let crossed = PortalCrossed {
+                portal: entity,
+                entity: tracked,
+                direction: if previous_side > 0.0 {
+                    PortalCrossingDirection::FrontToBack
+                } else {
+                    PortalCrossingDirection::BackToFront
+                },
+                transform: through_portal,
+                teleported,
+            };
+            crossed_writer.send(crossed);
+            commands.trigger_targets(crossed, entity);
+
+            if teleported {
+                *transform = through_portal;
+
+                // Sides relative to other portals are no longer meaningful
+                translations.0.clear();
+                break;
+            }
+        }
+    }
+}
+
+/// System that spawns, moves and despawns the [`PortalTravelerClone`]s of [`PortalTraveler`]s
+/// straddling a [`PortalTeleporter`].
+fn update_traveler_clones(
+    mut commands: Commands,
+    mut clones: ResMut<PortalTravelerClones>,
+    straddled_teleporters: StraddledTeleporters,
+    traveler_query: Query<
+        (Entity, &Transform, &Aabb, &Mesh3d, Option<&RenderLayers>),
+        (With<PortalTraveler>, Without<PortalTravelerClone>),
+    >,
+    mut clone_query: Query<
+        (&mut Transform, &mut Mesh3d, Option<&RenderLayers>),
+        (With<PortalTravelerClone>, Without<PortalTraveler>),
+    >,
+) {
+    let mut straddled = HashSet::new();
+
+    for (traveler, transform, traveler_aabb, mesh, render_layers) in &traveler_query {
+        for PortalStraddle {
+            portal: entity,
+            target,
+            through_portal,
+            front,
+        } in straddled_teleporters.get(transform, traveler_aabb)
+        {
+            straddled.insert((traveler, entity));
+            let clone = clones.0.get(&(traveler, entity)).copied();
+            if let Some((clone, (mut clone_transform, mut clone_mesh, clone_render_layers))) =
+                clone.and_then(|clone| Some((clone, clone_query.get_mut(clone).ok()?)))
+            {
+                *clone_transform = through_portal;
+                if *clone_mesh != *mesh {
+                    *clone_mesh = mesh.clone();
+                }
+                if clone_render_layers != render_layers {
+                    match render_layers {
+                        Some(render_layers) => {
+                            commands.entity(clone).insert(render_layers.clone());
+                        }
+                        None => {
+                            commands.entity(clone).remove::<RenderLayers>();
+                        }
+                    }
+                }
+                continue;
+            }
+
+            let mut clone = commands.spawn((
+                Name::new("Portal Traveler Clone"),
+                PortalTravelerClone {
+                    traveler,
+                    portal: entity,
+                },
+                through_portal,
+                mesh.clone(),
+                // Only the part that passed through the portal is shown at the target
+                PortalClipped {
+                    plane: target,
+                    keep_front: !front,
+                },
+            ));
+            if let Some(render_layers) = render_layers {
+                clone.insert(render_layers.clone());
+            }
+            clones.0.insert((traveler, entity), clone.id());
+            commands.entity(traveler).insert(PortalClipped {
+                plane: entity,
+                keep_front: front,
+            });
+        }
+    }
+
+    let mut unclipped = HashSet::new();
+    clones.0.retain(|&(traveler, portal), &mut clone| {
+        let keep = straddled.contains(&(traveler, portal));
+        if !keep {
+            if let Some(clone) = commands.get_entity(clone) {
+                clone.despawn_recursive();
+            }
+            unclipped.insert(traveler);
+        }
+        keep
+    });
+    for traveler in unclipped {
+        if straddled
+            .iter()
+            .any(|&(straddling, _)| straddling == traveler)
+        {
+            continue;
+        }
+        if let Some(mut traveler) = commands.get_entity(traveler) {
+            traveler.remove::<PortalClipped>();
+        }
+    }
+}
+
+/// System that copies the `M` material of each [`PortalTraveler`] onto its
+/// [`PortalTravelerClone`]s.
+fn update_traveler_clone_materials<M: Material>(
+    mut commands: Commands,
+    clone_query: Query<(Entity, &PortalTravelerClone, Option<&MeshMaterial3d<M>>)>,
+    material_query: Query<&MeshMaterial3d<M>, With<PortalTraveler>>,
+) {
+    for (entity, clone, clone_material) in &clone_query {
+        let Ok(material) = material_query.get(clone.traveler) else {
+            continue;
+        };
+        if clone_material.map(|m| m.id()) != Some(material.id()) {
+            commands.entity(entity).insert(material.clone());
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! Limiting how often and how many [`Portal`]s are rendered.
+//!
+//! Add [`PortalUpdateRate`] to a [`Portal`] to render it less often than every frame, and
+//! [`PortalPriority`] along with the [`PortalBudget`] resource to cap how many [`PortalCamera`]s
+//! render each frame. Throttled and over-budget portals keep showing the last image that was
+//! rendered.
+
+use bevy::{
+    image::{TextureFormatPixelInfo, Volume},
+    prelude::*,
+    render::{camera::RenderTarget, primitives::Aabb},
+    utils::HashMap,
+};
+
+use crate::{portal_screen_rect, Portal, PortalCamera};
+
+/// Component used to throttle how often a [`Portal`]'s camera renders, e.g. for decorative portals
+/// in the background.
+///
+/// On the frames the [`PortalCamera`] is skipped, it is deactivated and the portal keeps showing
+/// the last image that was rendered. Throttled portals are spread over the frames round-robin, so
+/// that they don't all render on the same frame.
+///
+/// This also applies to the views spawned for [`Portal::secondary_cameras`] and
+/// [`Portal::back_target`].
+#[derive(Component, Reflect, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[reflect(Component, Default)]
+pub enum PortalUpdateRate {
+    /// The [`PortalCamera`] renders every frame.
+    #[default]
+    EveryFrame,
+    /// The [`PortalCamera`] renders once every `n` frames.
+    EveryNFrames(u32),
+}
+
+impl PortalUpdateRate {
+    /// Returns whether a [`Portal`] with this update rate should render on a given `frame`.
+    ///
+    /// The `portal` entity decides which of the frames it renders on.
+    pub fn should_update(&self, portal: Entity, frame: u32) -> bool {
+        match *self {
+            Self::EveryFrame | Self::EveryNFrames(0 | 1) => true,
+            Self::EveryNFrames(n) => frame.wrapping_add(portal.index()).is_multiple_of(n),
+        }
+    }
+}
+
+/// Component used to rank a [`Portal`] against others when the [`PortalBudget`] is exceeded.
+///
+/// Portals with a lower priority are frozen first, and among portals of the same priority, those
+/// covering the least of the primary camera's viewport. Defaults to `0`.
+///
+/// This also applies to the views spawned for [`Portal::secondary_cameras`] and
+/// [`Portal::back_target`].
+#[derive(Component, Reflect, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[reflect(Component, Default)]
+pub struct PortalPriority(pub i32);
+
+/// Resource used to limit how many [`PortalCamera`]s render each frame.
+///
+/// Once a limit is exceeded, the [`PortalCamera`]s of the lowest-[`PortalPriority`] portals are
+/// deactivated, and those portals keep showing the last image that was rendered. Portals that
+/// would not render anyway, e.g. off screen or [`PortalDisabled`], don't count towards the budget.
+///
+/// # Notes
+///
+/// * A [`PortalCamera`] shared by several portals (see [`Portal::share_camera`]) is ranked by the
+///   highest priority among them.
+///
+/// [`PortalDisabled`]: crate::PortalDisabled
+#[derive(Resource, Reflect, Debug, Default, Clone)]
+#[reflect(Resource, Default)]
+pub struct PortalBudget {
+    /// The number of [`PortalCamera`]s that can render each frame.
+    ///
+    /// Defaults to `None`, which doesn't limit the number of cameras.
+    pub max_active_cameras: Option<usize>,
+    /// The total size in bytes of the images that can be rendered to each frame.
+    ///
+    /// Defaults to `None`, which doesn't limit texture memory.
+    pub max_texture_memory: Option<usize>,
+}
+
+impl PortalBudget {
+    /// Returns whether any limit is set.
+    pub(crate) fn is_limited(&self) -> bool {
+        self.max_active_cameras.is_some() || self.max_texture_memory.is_some()
+    }
+
+    /// Deactivates the [`PortalCamera`]s in `active_cameras` that exceed the budget, ranked by
+    /// their priority and viewport coverage in `camera_ranks`.
+    ///
+    /// `image_memory` returns the size in bytes of the image a [`PortalCamera`] renders to.
+    pub(crate) fn deactivate_over_budget(
+        &self,
+        camera_ranks: HashMap<Entity, (PortalPriority, f32)>,
+        active_cameras: &mut HashMap<Entity, bool>,
+        image_memory: impl Fn(Entity) -> usize,
+    ) {
+        let mut ranked_cameras = camera_ranks.into_iter().collect::<Vec<_>>();
+        ranked_cameras.sort_by(|(_, a), (_, b)| b.0.cmp(&a.0).then(b.1.total_cmp(&a.1)));
+
+        let mut texture_memory = 0;
+        for (index, (linked_camera, _)) in ranked_cameras.into_iter().enumerate() {
+            let image_memory = image_memory(linked_camera);
+            texture_memory += image_memory;
+            let over_budget = self
+                .max_active_cameras
+                .is_some_and(|max_active_cameras| index >= max_active_cameras)
+                || self
+                    .max_texture_memory
+                    .is_some_and(|max_texture_memory| texture_memory > max_texture_memory);
+            if over_budget {
+                texture_memory -= image_memory;
+                active_cameras.insert(linked_camera, false);
+            }
+        }
+    }
+}
+
+/// Returns the size in bytes of the image a `camera` renders to, or `0` if it doesn't render to
+/// an image.
+pub(crate) fn camera_image_memory(camera: &Camera, images: &Assets<Image>) -> usize {
+    match &camera.target {
+        RenderTarget::Image(image_handle) => images.get(image_handle).map_or(0, |image| {
+            let descriptor = &image.texture_descriptor;
+            descriptor.size.volume() * descriptor.format.pixel_size()
+        }),
+        _ => 0,
+    }
+}
+
+/// Returns the fraction of the primary camera's viewport covered by a [`Portal`]'s [`Aabb`].
+///
+/// Portals partially behind the primary camera, i.e. being walked through, cover all of it.
+pub(crate) fn portal_viewport_coverage(
+    portal: &Portal,
+    global_transform: &GlobalTransform,
+    aabb: &Aabb,
+    primary_camera_query: &Query<(&Camera, &GlobalTransform), Without<PortalCamera>>,
+) -> f32 {
+    let Ok((primary_camera, primary_camera_transform)) =
+        primary_camera_query.get(portal.primary_camera)
+    else {
+        return 0.0;
+    };
+    let Some(viewport_size) = primary_camera.physical_viewport_size() else {
+        return 0.0;
+    };
+
+    let clip_from_local = primary_camera.clip_from_view()
+        * primary_camera_transform.compute_matrix().inverse()
+        * global_transform.compute_matrix();
+    portal_screen_rect(&clip_from_local, aabb, viewport_size, false).map_or(1.0, |(_, size)| {
+        size.as_vec2().element_product() / viewport_size.as_vec2().element_product()
+    })
+}
@@ -0,0 +1,126 @@
+//! Validation of [`Portal`] configurations.
+//!
+//! Add the [`PortalValidationPlugin`] to check each [`Portal`] when it's added or changed, and log
+//! a warning for common mistakes that leave a portal blank, such as:
+//!
+//! * A [`Portal::target`] without a [`GlobalTransform`].
+//! * A [`Portal::primary_camera`] that isn't a [`Camera3d`].
+//! * A portal mesh that doesn't face along the portal's local Z axis, like a [`Rectangle`] does.
+//! * A portal that targets itself while it isn't rendered recursively, see
+//!   [`PortalPluginSettings::max_recursion_depth`].
+//!
+//! [`PortalPluginSettings::max_recursion_depth`]: crate::PortalPluginSettings::max_recursion_depth
+
+use bevy::{
+    prelude::*,
+    render::{mesh::PrimitiveTopology, render_resource::Face},
+    transform::TransformSystem,
+};
+
+use crate::{recursion::PortalRecursionDepth, Mirror, Portal, PortalSetupState};
+
+/// Plugin that logs warnings for misconfigured [`Portal`]s.
+///
+/// See the [module docs](self) for the checks performed.
+pub struct PortalValidationPlugin;
+
+impl Plugin for PortalValidationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            validate_portals.after(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+/// System that checks [`Portal`]s once they are added, changed or set up.
+fn validate_portals(
+    portal_query: Query<
+        (
+            Entity,
+            &Portal,
+            &PortalSetupState,
+            Option<&Mesh3d>,
+            Has<Mirror>,
+            Has<PortalRecursionDepth>,
+        ),
+        Or<(Changed<Portal>, Changed<PortalSetupState>, Changed<Mesh3d>)>,
+    >,
+    transform_query: Query<(), With<GlobalTransform>>,
+    camera_query: Query<Has<Camera3d>, With<Camera>>,
+    parent_query: Query<&Parent>,
+    meshes: Res<Assets<Mesh>>,
+) {
+    for (entity, portal, setup_state, mesh, mirror, recursive) in &portal_query {
+        if !transform_query.contains(portal.target) {
+            warn!(
+                "Portal {entity} has target {} without a `GlobalTransform`, so its camera can't \
+                 be positioned; spawn the target with a `Transform`",
+                portal.target
+            );
+        }
+
+        if let Ok(false) = camera_query.get(portal.primary_camera) {
+            warn!(
+                "Portal {entity} has primary camera {} without `Camera3d`; portals are only \
+                 rendered for 3D cameras",
+                portal.primary_camera
+            );
+        }
+
+        if let Some(normal) = mesh
+            .and_then(|mesh| meshes.get(&mesh.0))
+            .and_then(mesh_winding_normal)
+        {
+            // The side facing the camera has to point along +Z, see `reflect_through_mirror`
+            let facing_away = match portal.cull_mode {
+                Some(Face::Back) => normal.z < 0.5,
+                Some(Face::Front) => normal.z > -0.5,
+                None => normal.z.abs() < 0.5,
+            };
+            if facing_away {
+                warn!(
+                    "Portal {entity} has a mesh that doesn't face along its local Z axis, so it \
+                     may be culled or clipped; use a `Rectangle`, rotate the mesh, or change \
+                     `Portal::cull_mode`"
+                );
+            }
+        }
+
+        let targets_self = portal.target == entity
+            || parent_query
+                .iter_ancestors(portal.target)
+                .any(|ancestor| ancestor == entity);
+        // Whether the portal is rendered recursively is only known once it's set up
+        let set_up = *setup_state == PortalSetupState::Active;
+        if !mirror && set_up && !recursive && targets_self {
+            warn!(
+                "Portal {entity} targets itself, so its camera can't see past the portal's own \
+                 image; set `PortalPluginSettings::max_recursion_depth`, or use `Portal::mirror`"
+            );
+        }
+    }
+}
+
+/// Returns the normalized sum of the triangle normals of a `mesh`, following its winding order.
+///
+/// Returns [`None`] if the mesh isn't a triangle list, or has no area.
+fn mesh_winding_normal(mesh: &Mesh) -> Option<Vec3> {
+    if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+        return None;
+    }
+    let positions = mesh.attribute(Mesh::ATTRIBUTE_POSITION)?.as_float3()?;
+    let indices: Vec<usize> = match mesh.indices() {
+        Some(indices) => indices.iter().collect(),
+        None => (0..positions.len()).collect(),
+    };
+
+    let normal: Vec3 = indices
+        .chunks_exact(3)
+        .filter_map(|triangle| {
+            let [a, b, c] = [0, 1, 2].map(|i| positions.get(triangle[i]).copied().map(Vec3::from));
+            Some((b? - a?).cross(c? - a?))
+        })
+        .sum();
+    normal.try_normalize()
+}